@@ -0,0 +1,181 @@
+//! Measures the throughput of the parse paths and of the engines on a
+//! generated fixture, and the allocations they make per row.
+//!
+//!     $ cargo bench
+//!     $ TXREADER_BENCH_TXNS=2000000 cargo bench -- parse
+//!
+//! The fixture has `TXREADER_BENCH_TXNS` transactions, 200000 by default,
+//! of valid lifecycles of disputes over 1000 clients with a fixed seed,
+//! so that runs on different commits are comparable. The time of every
+//! benchmark is measured by criterion in the `parse` and `engine` groups,
+//! and the allocations and the bytes they take, separately, in the
+//! `allocs` and `bytes` groups, per row of the fixture.
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, Criterion, SamplingMode, Throughput};
+use futures::executor::block_on;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use txreader::tx::generate::Generator;
+use txreader::tx::{self, Engine, Options};
+
+/// The system allocator, counting the allocations and the bytes they
+/// take, to measure the memory churn of a benchmark.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// A criterion measurement of one of the counters of `Counting`, e.g.
+/// the number of allocations made while a benchmark runs.
+struct Counter {
+    counter: &'static AtomicU64,
+    unit:    &'static str,
+    per_row: &'static str,
+}
+
+impl Measurement for Counter {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> u64 {
+        self.counter.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, started: u64) -> u64 {
+        self.counter.load(Ordering::Relaxed) - started
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for Counter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+
+    /// Scales the counts per run of the fixture to counts per row.
+    fn scale_throughputs(&self, _typical_value: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        if let Throughput::Elements(rows) = throughput {
+            values.iter_mut().for_each(|value| *value /= *rows as f64);
+        }
+        self.per_row
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+}
+
+/// The generated transactions, as a csv file and as they were generated.
+struct Fixture {
+    _dir: tempfile::TempDir,
+    path: PathBuf,
+    rows: u64,
+    txns: Vec<tx::Transaction>,
+}
+
+/// Returns the fixture, which is generated by the first benchmark.
+fn fixture() -> &'static Fixture {
+    static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let num_txns = std::env::var("TXREADER_BENCH_TXNS")
+            .map(|value| value.parse().unwrap_or_else(|e| panic!("invalid TXREADER_BENCH_TXNS `{}`: {}", value, e)))
+            .unwrap_or(200_000);
+        let generator = Generator { num_txns, num_clients: 1000, seed: Some(1), lifecycles: true, ..Generator::default() };
+        let dir = tempfile::tempdir().expect("could not create the fixture directory");
+        let path = dir.path().join("txns.csv");
+        let file = std::fs::File::create(&path).expect("could not create the fixture");
+        tx::generate::write_rows(io::BufWriter::new(file), &generator.columns(), generator.rows()).expect("could not write the fixture");
+        Fixture { _dir: dir, path, rows: num_txns as u64, txns: generator.txns() }
+    })
+}
+
+/// Runs the benchmarks of the parse paths and of the engines, in groups
+/// named after `prefix`, with the measurement of `c`.
+fn benches<M: Measurement>(c: &mut Criterion<M>, prefix: &str) {
+    let fixture = fixture();
+    let paths = std::slice::from_ref(&fixture.path);
+    let parse = |options: Options| move || block_on(tx::process_paths(paths, &options)).map(drop);
+
+    let mut group = c.benchmark_group(format!("{}parse", prefix));
+    group.sample_size(10).sampling_mode(SamplingMode::Flat).throughput(Throughput::Elements(fixture.rows));
+    group.bench_function("serde", |b| b.iter(parse(Options::default())));
+    group.bench_function("fast", |b| b.iter(parse(Options { fast_parse: true, ..Options::default() })));
+    group.bench_function("fast-mmap", |b| b.iter(parse(Options { fast_parse: true, mmap: true, ..Options::default() })));
+    group.bench_function("parallel-mmap", |b| b.iter(parse(Options { parallel_parse: true, mmap: true, ..Options::default() })));
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}engine", prefix));
+    group.sample_size(10).sampling_mode(SamplingMode::Flat).throughput(Throughput::Elements(fixture.rows));
+    group.bench_function("channel", |b| b.iter(parse(Options::default())));
+    group.bench_function("channel-1", |b| b.iter(parse(Options { threads: Some(1), ..Options::default() })));
+    group.bench_function("blocking", |b| b.iter(|| tx::process_paths_blocking(paths, &Options::default()).map(drop)));
+    group.bench_function("apply", |b| b.iter(|| {
+        let mut engine = Engine::new();
+        fixture.txns.iter().cloned().for_each(|txn| { engine.apply(txn).ok(); });
+    }));
+    group.finish();
+}
+
+fn time(c: &mut Criterion) {
+    benches(c, "");
+}
+
+fn allocations(c: &mut Criterion<Counter>) {
+    benches(c, "allocs/");
+}
+
+fn allocated(c: &mut Criterion<Counter>) {
+    benches(c, "bytes/");
+}
+
+criterion_group!(timing, time);
+criterion_group! {
+    name = churn;
+    config = Criterion::default().with_measurement(Counter { counter: &ALLOCATIONS, unit: "allocs", per_row: "allocs/row" });
+    targets = allocations
+}
+criterion_group! {
+    name = churn_bytes;
+    config = Criterion::default().with_measurement(Counter { counter: &ALLOCATED, unit: "bytes", per_row: "bytes/row" });
+    targets = allocated
+}
+criterion_main!(timing, churn, churn_bytes);
@@ -0,0 +1,48 @@
+//! Feeds arbitrary sequences of transactions to `Engine::apply`, over a
+//! few clients and ids so that they refer to each other, and checks the
+//! invariants of `tx::invariants` after every one.
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_decimal::Decimal;
+use txreader::tx::{invariants, DisputePolicy, Engine, Options, Transaction};
+
+#[derive(Debug, Arbitrary)]
+struct Txn {
+    kind:   u8,
+    client: u8,
+    tx:     u8,
+    to:     u8,
+    amount: i64,
+    scale:  u8,
+}
+
+impl Txn {
+    /// Returns the transaction, unless its amount is invalid.
+    fn txn(&self) -> Option<Transaction> {
+        let (client, tx, to) = (u16::from(self.client % 4), u32::from(self.tx % 16), u16::from(self.to % 4));
+        let amount = Decimal::new(self.amount, u32::from(self.scale % 29));
+        match self.kind % 8 {
+            0 => Transaction::deposit(client, tx, amount).ok(),
+            1 => Transaction::withdrawal(client, tx, amount).ok(),
+            2 => Some(Transaction::dispute(client, tx)),
+            3 => Some(Transaction::resolve(client, tx)),
+            4 => Some(Transaction::chargeback(client, tx)),
+            5 => Some(Transaction::unlock(client, tx)),
+            6 => Transaction::adjustment(client, tx, amount).ok(),
+            _ => Transaction::transfer(client, to, tx, amount).ok(),
+        }
+    }
+}
+
+fuzz_target!(|txns: Vec<Txn>| {
+    let options = Options { dispute_policy: DisputePolicy::DepositsOnly, ..Options::default() };
+    let mut engine = Engine::new().with_options(options.clone());
+    for txn in txns.iter().filter_map(Txn::txn) {
+        let before = engine.account_in(txn.client_id(), txn.currency()).cloned();
+        let result = engine.apply(txn.clone());
+        let after = engine.account_in(txn.client_id(), txn.currency());
+        invariants::applied(&txn, &result, before.as_ref(), after, &options).unwrap();
+        invariants::balances(engine.accounts()).unwrap();
+    }
+});
@@ -0,0 +1,27 @@
+//! Feeds arbitrary bytes to the csv reader, with serde and with
+//! `Options::fast_parse`, and writes the accounts they lead to in every
+//! output format. Neither may panic, and both parsers must lead to the
+//! same accounts.
+#![no_main]
+use futures::executor::block_on;
+use libfuzzer_sys::fuzz_target;
+use std::io;
+use txreader::tx::{self, Account, Options};
+
+fuzz_target!(|data: &[u8]| {
+    let process = |options: &Options| block_on(tx::process_reader(io::Cursor::new(data.to_vec()), options)).map(|(accounts, _)| accounts);
+    let serde = process(&Options::default());
+    let fast = process(&Options { fast_parse: true, ..Options::default() });
+    if let (Ok(serde), Ok(fast)) = (&serde, &fast) {
+        assert_eq!(serde, fast);
+    }
+    for accounts in [serde, fast].iter().flatten() {
+        assert!(accounts.iter().all(Account::balance_check));
+        let mut out = io::sink();
+        block_on(tx::print_accounts_with(&mut out, accounts));
+        block_on(tx::print_fixed_accounts_with(&mut out, accounts));
+        block_on(tx::print_accounts_json(&mut out, accounts));
+        block_on(tx::print_fixed_accounts_json(&mut out, accounts));
+        block_on(tx::print_accounts_table(&mut out, accounts));
+    }
+});
@@ -0,0 +1,39 @@
+/* C bindings of txreader, built with
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib`. */
+
+#ifndef TXREADER_H
+#define TXREADER_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Engine txreader_engine;
+
+/* Reads the transactions from the csv file at `path`, and writes the
+ * accounts as csv to `out_buf`, followed by a NUL, if it has room for
+ * them. Returns the length of the accounts without the NUL, or -1 if
+ * the file can not be read. */
+ssize_t txreader_process_file(const char *path, char *out_buf, size_t out_len);
+
+/* Returns a new, empty engine, released by txreader_engine_finish. */
+txreader_engine *txreader_engine_new(void);
+
+/* Applies a transaction given as a csv row, e.g. "deposit,1,1,2.5".
+ * Returns 0 if it was applied, 1 if it was rejected, and -1 if the row
+ * can not be parsed. */
+int txreader_engine_apply(txreader_engine *engine, const char *row);
+
+/* Writes the accounts like txreader_process_file, and releases the
+ * engine once they were written. The engine is kept if `out_buf` has
+ * no room for them. */
+ssize_t txreader_engine_finish(txreader_engine *engine, char *out_buf, size_t out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
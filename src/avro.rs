@@ -0,0 +1,185 @@
+use crate::tx::{Account, Rejection, TxError};
+use apache_avro::types::Value;
+use apache_avro::{Schema, Writer};
+use rust_decimal::Decimal;
+use std::io;
+
+/// The schema of the accounts, embedded in every Avro file of accounts.
+/// The funds are decimals with four digits after decimal, and the
+/// currency is null for the accounts without one.
+pub const ACCOUNT_SCHEMA: &str = concat!(
+    r#"{"type":"record","name":"Account","namespace":"txreader","fields":["#,
+    r#"{"name":"client","type":"int"},"#,
+    r#"{"name":"currency","type":["null","string"],"default":null},"#,
+    r#"{"name":"available","type":{"type":"bytes","logicalType":"decimal","precision":38,"scale":4}},"#,
+    r#"{"name":"held","type":{"type":"bytes","logicalType":"decimal","precision":38,"scale":4}},"#,
+    r#"{"name":"total","type":{"type":"bytes","logicalType":"decimal","precision":38,"scale":4}},"#,
+    r#"{"name":"locked","type":"boolean"}"#,
+    r#"]}"#,
+);
+
+/// The schema of the rejected transactions, like the columns of the
+/// csv file of `--rejections`.
+pub const REJECTION_SCHEMA: &str = concat!(
+    r#"{"type":"record","name":"Rejection","namespace":"txreader","fields":["#,
+    r#"{"name":"line","type":"long"},"#,
+    r#"{"name":"type","type":"string"},"#,
+    r#"{"name":"client","type":"int"},"#,
+    r#"{"name":"tx","type":"long"},"#,
+    r#"{"name":"amount","type":["null",{"type":"bytes","logicalType":"decimal","precision":38,"scale":4}],"default":null},"#,
+    r#"{"name":"reason","type":"string"}"#,
+    r#"]}"#,
+);
+
+/// The marker between the blocks of a file. It is fixed instead of
+/// random, so that the same accounts give the same file.
+const SYNC: [u8; 16] = *b"txreader.avro.v1";
+
+/// Returns `true` if the file has the `.avro` extension.
+pub fn is_avro(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("avro"))
+}
+
+/// Writes the accounts to the `writer` as an Avro object container
+/// file, with `ACCOUNT_SCHEMA` embedded.
+pub async fn print_accounts_avro(writer: &mut impl io::Write, accounts: &[Account]) -> Result<(), TxError> {
+    let records = accounts.iter().map(|account| vec![
+        ("client", Value::Int(account.client_id().into())),
+        ("currency", optional(account.currency().map(|currency| Value::String(currency.to_string())))),
+        ("available", decimal(account.available())),
+        ("held", decimal(account.held())),
+        ("total", decimal(account.total())),
+        ("locked", Value::Boolean(account.is_locked())),
+    ]);
+    write_container(writer, ACCOUNT_SCHEMA, records)
+}
+
+/// Writes the rejected transactions to the `writer` as an Avro object
+/// container file, with `REJECTION_SCHEMA` embedded.
+pub async fn print_rejections_avro(writer: &mut impl io::Write, rejections: &[Rejection]) -> Result<(), TxError> {
+    let records = rejections.iter().map(|rejection| vec![
+        ("line", Value::Long(rejection.line as i64)),
+        ("type", Value::String(format!("{:?}", rejection.txn.kind()).to_lowercase())),
+        ("client", Value::Int(rejection.txn.client_id().into())),
+        ("tx", Value::Long(rejection.txn.tx_id().into())),
+        ("amount", optional(rejection.txn.amount().map(decimal))),
+        ("reason", Value::String(rejection.reason.to_string())),
+    ]);
+    write_container(writer, REJECTION_SCHEMA, records)
+}
+
+/// Writes the records, as the fields of the `schema` in order, to the
+/// `writer` as an uncompressed file with the `schema` embedded.
+fn write_container<'a>( writer:  &mut impl io::Write
+                      , schema:  &str
+                      , records: impl Iterator<Item = Vec<(&'a str, Value)>>
+                      ) -> Result<(), TxError> {
+    let schema = Schema::parse_str(schema)?;
+    let mut container = Writer::builder().schema(&schema).writer(writer).marker(SYNC).build();
+    for fields in records {
+        container.append(Value::Record(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect()))?;
+    }
+    container.flush()?;
+    Ok(())
+}
+
+/// Returns the value of a union of null and another type.
+fn optional(value: Option<Value>) -> Value {
+    match value {
+        Some(value) => Value::Union(1, Box::new(value)),
+        None => Value::Union(0, Box::new(Value::Null)),
+    }
+}
+
+/// Returns a `decimal` with a scale of four, as the big-endian
+/// two's-complement bytes of its unscaled number.
+fn decimal(amount: Decimal) -> Value {
+    let mut amount = amount.round_dp(4);
+    amount.rescale(4);
+    Value::Decimal(amount.mantissa().to_be_bytes().into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use apache_avro::Reader;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_print_accounts_avro() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = crate::Engine::new();
+        engine.apply(crate::Transaction::deposit(1, 1, dec!(1.5))?)?;
+        engine.apply(crate::Transaction::deposit(2, 2, dec!(0.0128))?.with_currency("EUR"))?;
+        engine.apply(crate::Transaction::dispute(2, 2).with_currency("EUR"))?;
+        let accounts = engine.into_accounts();
+
+        /*
+         * When
+         */
+        let mut avro = vec![];
+        futures::executor::block_on(print_accounts_avro(&mut avro, &accounts))?;
+        let reader = Reader::new(&avro[..])?;
+
+        /*
+         * Then
+         */
+        let record = |fields: Vec<(&str, Value)>| Value::Record(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect());
+        assert_eq!(reader.writer_schema(), &Schema::parse_str(ACCOUNT_SCHEMA)?);
+        assert_eq!(reader.collect::<Result<Vec<Value>, _>>()?,
+                   vec![ record(vec![ ("client", Value::Int(1))
+                                    , ("currency", optional(None))
+                                    , ("available", decimal(dec!(1.5)))
+                                    , ("held", decimal(dec!(0)))
+                                    , ("total", decimal(dec!(1.5)))
+                                    , ("locked", Value::Boolean(false))
+                                    ])
+                       , record(vec![ ("client", Value::Int(2))
+                                    , ("currency", optional(Some(Value::String(String::from("EUR")))))
+                                    , ("available", decimal(dec!(0)))
+                                    , ("held", decimal(dec!(0.0128)))
+                                    , ("total", decimal(dec!(0.0128)))
+                                    , ("locked", Value::Boolean(false))
+                                    ])
+                       ]);
+        assert!(avro.ends_with(&SYNC));
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_rejections_avro() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let rejection = |line, txn, reason| Rejection { file: 0, path: None, line, txn, reason };
+        let rejections = vec![ rejection(3, crate::Transaction::adjustment(1, 2, dec!(-0.0129))?, TxError::AccountLocked(1))
+                             , rejection(4, crate::Transaction::dispute(1, 5), TxError::UnknownTx(5))
+                             ];
+
+        /*
+         * When
+         */
+        let mut avro = vec![];
+        futures::executor::block_on(print_rejections_avro(&mut avro, &rejections))?;
+        let values = Reader::new(&avro[..])?.collect::<Result<Vec<Value>, _>>()?;
+
+        /*
+         * Then
+         */
+        let fields = |value: &Value| match value {
+            Value::Record(fields) => fields.iter().map(|(_, value)| value.clone()).collect(),
+            _ => vec![],
+        };
+        assert_eq!(values.iter().map(fields).collect::<Vec<_>>(),
+                   vec![ vec![ Value::Long(3), Value::String(String::from("adjustment")), Value::Int(1), Value::Long(2)
+                             , optional(Some(decimal(dec!(-0.0129)))), Value::String(TxError::AccountLocked(1).to_string())
+                             ]
+                       , vec![ Value::Long(4), Value::String(String::from("dispute")), Value::Int(1), Value::Long(5)
+                             , optional(None), Value::String(TxError::UnknownTx(5).to_string())
+                             ]
+                       ]);
+        Ok(())
+    }
+}
@@ -1,10 +1,21 @@
+use crate::config;
+use crate::tx::generate::{ClientDistribution, Distribution, Ratios};
+use crate::tx::{self, AmountUnit, DisputePolicy, DuplicatePolicy, LockedPolicy, RoundingMode};
+use std::io::IsTerminal;
+use std::str::FromStr;
+use structopt::clap;
 use structopt::StructOpt;
 
 #[derive(Debug)]
 #[derive(StructOpt)]
+#[structopt(global_settings = &[clap::AppSettings::AllArgsOverrideSelf])]
+#[structopt(after_help = "EXIT STATUS:\n    0    The run succeeded\n    1    The run failed, e.g. a file could not be read, a row is malformed with --strict, or --max-errors was exceeded\n    2    A transaction was rejected with --strict\n    3    The accounts compared by `diff` or `golden --check` differ")]
 pub struct Cli {
-    #[structopt(parse(from_os_str), required_unless="generate", help = "Path to the csv file that contains transactions. Optional if --generate is set")]
-    pub path: Option<std::path::PathBuf>,
+    #[structopt(parse(from_os_str), help = "Paths, directories, glob patterns or http(s) URLs of the csv files that contain transactions, read as one ordered log, or `-` to read from stdin. Optional if --generate is set or stdin is piped")]
+    pub paths: Vec<std::path::PathBuf>,
+
+    #[structopt(long = "config", parse(from_os_str), help = "Path to a TOML file that sets flags by their long names, e.g. `precision = 2`, which the flags given here override. Defaults to txreader.toml if it exists")]
+    pub config: Option<std::path::PathBuf>,
 
     // Generate a list of random transactions if set to true
     #[structopt(short = "G", long = "generate", help = "Generates a list of random transactions")]
@@ -15,8 +26,614 @@ pub struct Cli {
 
     #[structopt(short = "c", long = "clients", default_value = "100", help = "Number of clients in the generated transactions")]
     pub num_clients: u16,
+
+    #[structopt(long = "seed", requires = "generate", help = "Seed of the random transactions to generate, so that the same seed generates the same transactions")]
+    pub seed: Option<u64>,
+
+    #[structopt(long = "lifecycles", requires = "generate", help = "Generates valid lifecycles of disputes: a dispute only of a deposit, a resolve or chargeback only of an open dispute, and a withdrawal only of available funds")]
+    pub lifecycles: bool,
+
+    #[structopt(long = "invalid-rate", requires = "lifecycles", parse(try_from_str = parse_rate), help = "Share of the transactions of --lifecycles, from 0 to 1 or as a percentage like 5%, that are made invalid on purpose, e.g. a resolve of a deposit that is not disputed [default: 0]")]
+    pub invalid_rate: Option<f64>,
+
+    #[structopt(long = "malformed-rate", requires = "generate", parse(try_from_str = parse_rate), help = "Share of the generated rows, from 0 to 1 or as a percentage like 5%, that are malformed on purpose, e.g. with an unknown type or an amount that is not a number. Not named --invalid-rate, which makes transactions of --lifecycles invalid [default: 0]")]
+    pub malformed_rate: Option<f64>,
+
+    #[structopt(long = "min-amount", default_value = "1", help = "Smallest amount of the generated transactions, in ten-thousandths, e.g. 10000 for 1.0")]
+    pub min_amount: i64,
+
+    #[structopt(long = "max-amount", default_value = "10000000", help = "Largest amount of the generated transactions, in ten-thousandths, e.g. 10000000 for 1000.0")]
+    pub max_amount: i64,
+
+    #[structopt(long = "amount-distribution", default_value = "uniform", possible_values = Distribution::NAMES, help = "How the generated amounts are distributed: evenly, or mostly small with a few large ones")]
+    pub amount_distribution: Distribution,
+
+    #[structopt(long = "client-distribution", default_value = "uniform", help = "How the generated transactions are distributed over the clients: `uniform`, or `zipf:<s>` for a few hot clients, where client k has a weight of 1/k^s, e.g. `zipf:1.1`")]
+    pub client_distribution: ClientDistribution,
+
+    #[structopt(long = "deposit-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the deposits among the generated transactions, relative to the other kinds")]
+    pub deposit_ratio: Option<f64>,
+
+    #[structopt(long = "withdrawal-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the withdrawals among the generated transactions, relative to the other kinds")]
+    pub withdrawal_ratio: Option<f64>,
+
+    #[structopt(long = "dispute-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the disputes among the generated transactions, relative to the other kinds")]
+    pub dispute_ratio: Option<f64>,
+
+    #[structopt(long = "resolve-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the resolves among the generated transactions, relative to the other kinds")]
+    pub resolve_ratio: Option<f64>,
+
+    #[structopt(long = "chargeback-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the chargebacks among the generated transactions, relative to the other kinds")]
+    pub chargeback_ratio: Option<f64>,
+
+    #[structopt(long = "unlock-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the unlocks among the generated transactions, relative to the other kinds")]
+    pub unlock_ratio: Option<f64>,
+
+    #[structopt(long = "adjustment-ratio", requires = "generate", parse(try_from_str = parse_ratio), help = "Weight of the adjustments among the generated transactions, relative to the other kinds")]
+    pub adjustment_ratio: Option<f64>,
+
+    #[structopt(long = "currencies", use_delimiter = true, requires = "generate", help = "Comma separated currencies to draw the generated transactions in, in a currency column, e.g. `EUR,USD`")]
+    pub currencies: Vec<String>,
+
+    #[structopt(long = "timestamps", requires = "generate", help = "Adds a timestamp column to the generated transactions, with times in RFC 3339 that increase from one to the next")]
+    pub timestamps: bool,
+
+    #[structopt(long = "out", parse(from_os_str), requires = "generate", help = "Path to a csv file to write the generated transactions to, instead of stdout")]
+    pub out: Option<std::path::PathBuf>,
+
+    #[structopt(long = "shards", requires = "out", help = "Number of csv files to split the generated transactions into, read in order as one log, e.g. txns-1.csv to txns-4.csv for --out txns.csv")]
+    pub shards: Option<std::num::NonZeroUsize>,
+
+    #[structopt(long = "parallel", requires = "shards", help = "Generates the shards at once, one thread each, split by client instead of in order, with every client in one of them")]
+    pub parallel: bool,
+
+    #[structopt(short = "f", long = "format", possible_values = Format::NAMES, help = "Output format of the accounts, a table if stdout is a terminal and csv otherwise by default")]
+    pub format: Option<Format>,
+
+    #[structopt(long = "totals", help = "Ends the table of the accounts with a row of their total funds and the number of locked accounts, one for every currency")]
+    pub totals: bool,
+
+    #[structopt(long = "color", default_value = "auto", possible_values = Color::NAMES, help = "Whether the table of the accounts highlights locked accounts and negative available funds in red, and held funds in yellow. `auto` colors it if stdout is a terminal and NO_COLOR is not set")]
+    pub color: Color,
+
+    #[structopt(long = "output", parse(from_os_str), conflicts_with_all = &["watch", "replay"], help = "Path to a file to write the accounts to, instead of stdout")]
+    pub output: Option<std::path::PathBuf>,
+
+    #[structopt(long = "shard-output", requires = "output", help = "Number of files to split the accounts into by range of client ids, e.g. accounts-1.csv to accounts-4.csv for --output accounts.csv, to be loaded at once")]
+    pub shard_output: Option<std::num::NonZeroUsize>,
+
+    #[structopt(long = "fixed", help = "Prints the funds with exactly four digits after decimal, e.g. `2.0000`")]
+    pub fixed: bool,
+
+    #[structopt(long = "strict", help = "Aborts on the first malformed row instead of skipping it")]
+    pub strict: bool,
+
+    #[structopt(long = "rounding", default_value = "half-even", possible_values = RoundingMode::NAMES, help = "How the amounts are rounded to the precision")]
+    pub rounding: RoundingMode,
+
+    #[structopt(long = "precision", default_value = "4", help = "Number of digits after decimal the amounts are rounded to")]
+    pub precision: u32,
+
+    #[structopt(long = "dispute-policy", default_value = "all", possible_values = DisputePolicy::NAMES, help = "Which transactions can be disputed: only deposits, or withdrawals as well")]
+    pub dispute_policy: DisputePolicy,
+
+    #[structopt(long = "allow-client-mismatch", help = "Does not check that a dispute, resolve or chargeback comes from the client of the transaction it refers to")]
+    pub allow_client_mismatch: bool,
+
+    #[structopt(long = "duplicates", default_value = "reject", possible_values = DuplicatePolicy::NAMES, help = "What to do with a deposit or withdrawal that reuses the id of an earlier one of the client: reject it, or warn and apply it")]
+    pub duplicates: DuplicatePolicy,
+
+    #[structopt(long = "global-unique-tx", help = "Checks the ids of the deposits and withdrawals for duplicates across all clients, instead of per client")]
+    pub global_unique_tx: bool,
+
+    #[structopt(long = "locked-policy", default_value = "allow-disputes", possible_values = LockedPolicy::NAMES, help = "Whether a locked account still accepts disputes, resolves and chargebacks, or is frozen")]
+    pub locked_policy: LockedPolicy,
+
+    #[structopt(long = "amounts", default_value = "decimal", possible_values = AmountUnit::NAMES, help = "Whether the amounts are decimals, e.g. `1.5`, or integer numbers of ten-thousandths in minor units, e.g. `15000`")]
+    pub amounts: AmountUnit,
+
+    #[structopt(long = "no-headers", help = "Reads csv files without a header row, with the columns `type,client,tx,amount,to,currency` unless --columns is set")]
+    pub no_headers: bool,
+
+    #[structopt(long = "columns", use_delimiter = true, help = "Comma separated names of the csv columns in order, in place of the header row, e.g. `type,client,tx,amount`")]
+    pub columns: Option<Vec<String>>,
+
+    #[structopt(long = "map", number_of_values = 1, parse(try_from_str = parse_mapping), help = "Reads a field from a csv column of another name, e.g. `client=client_id`. Can be repeated")]
+    pub map: Vec<(String, String)>,
+
+    #[structopt(long = "delimiter", parse(try_from_str = parse_delimiter), help = "Delimiter of the csv columns, e.g. `;` or `tab`. Defaults to `,`, or a tab for `.tsv` files")]
+    pub delimiter: Option<u8>,
+
+    #[structopt(long = "decimal-comma", help = "Reads the amounts with a decimal comma, and dots that group the thousands, e.g. `1.234,56`")]
+    pub decimal_comma: bool,
+
+    #[structopt(long = "capacity", default_value = "1024", help = "Number of transactions buffered for each worker before reading waits for it, which bounds the memory used")]
+    pub capacity: usize,
+
+    #[structopt(long = "threads", help = "Number of threads that apply the transactions, each to a shard of the clients. Defaults to one per core")]
+    pub threads: Option<usize>,
+
+    #[structopt(long = "mmap", help = "Memory-maps the input files instead of reading them through a buffer. The files must not be changed while they are read")]
+    pub mmap: bool,
+
+    #[structopt(long = "fast-parse", help = "Parses the csv records by hand instead of with serde, which is faster and reads the same transactions")]
+    pub fast_parse: bool,
+
+    #[structopt(long = "parallel-parse", help = "Parses the csv files in chunks on all cores. Reads a whole file into memory, unless --mmap is set")]
+    pub parallel_parse: bool,
+
+    #[structopt(long = "max-memory", help = "Number of bytes the buffered transactions, the transactions kept for disputes and the clients of the transaction ids may take in memory. It bounds --capacity, and the transactions kept for disputes and the clients of the ids are spilled to temporary files past it. Unbounded if not set")]
+    pub max_memory: Option<usize>,
+
+    #[structopt(long = "only-clients", help = "Applies and prints only the transactions and accounts of these clients, a list of client ids and ranges like `1,5,100-200`. A transfer to a selected client is only applied if its sender is selected as well")]
+    pub clients: Option<tx::clients::ClientFilter>,
+
+    #[structopt(long = "limit", conflicts_with_all = &["watch", "checkpoint"], help = "Stops reading once this number of transactions were read, not counting the malformed rows that are skipped, and prints the accounts at that point")]
+    pub limit: Option<u64>,
+
+    #[structopt(long = "until-tx", conflicts_with_all = &["watch", "checkpoint"], help = "Stops reading after the first transaction with this id, and prints the accounts at that point")]
+    pub until_tx: Option<u32>,
+
+    #[structopt(long = "retries", default_value = "0", help = "Number of times the download of an input URL is resumed from where it stopped after it failed part way, which needs the server to support ranges")]
+    pub retries: u32,
+
+    #[structopt(long = "rejections", parse(from_os_str), help = "Path to a csv file to write the rejected transactions to")]
+    pub rejections: Option<std::path::PathBuf>,
+
+    #[structopt(long = "quarantine", parse(from_os_str), help = "Path to a csv file to write the malformed and the rejected rows to, in the columns of the input with a reason column appended, so they can be fixed and submitted again")]
+    pub quarantine: Option<std::path::PathBuf>,
+
+    #[structopt(long = "max-errors", help = "Aborts the run once more rows than this were malformed or rejected, either a number of rows like `10` or a percentage of the rows read like `5%`. A percentage is checked once 1000 rows were read, and at the end")]
+    pub max_errors: Option<tx::threshold::Limit>,
+
+    #[structopt(long = "fail-fast", conflicts_with = "max-errors", help = "Aborts the run on the first malformed or rejected row, like --max-errors 0")]
+    pub fail_fast: bool,
+
+    #[structopt(long = "stats", help = "Prints summary statistics of the run to stderr")]
+    pub stats: bool,
+
+    #[structopt(long = "stats-out", parse(from_os_str), help = "Path to a file to write summary statistics of the run to")]
+    pub stats_out: Option<std::path::PathBuf>,
+
+    #[structopt(long = "profile", parse(from_os_str), help = "Path to a file to write a JSON report of the time of every stage, the rows processed, the throughput and the peak memory of the run to")]
+    pub profile: Option<std::path::PathBuf>,
+
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences), help = "Logs more, without RUST_LOG: -v for info, -vv for debug and -vvv for trace")]
+    pub verbose: u8,
+
+    #[structopt(long = "progress", help = "Draws the bytes read, the rows, the throughput and the time left to stderr. On by default if stderr is a terminal and --quiet is not set")]
+    pub progress: bool,
+
+    #[structopt(short = "q", long = "quiet", conflicts_with = "verbose", help = "Logs nothing, not even errors")]
+    pub quiet: bool,
+
+    #[structopt(long = "log-format", default_value = "text", possible_values = LogFormat::NAMES, help = "Format of the logs on stderr: text, or one JSON object per line")]
+    pub log_format: LogFormat,
+
+    #[cfg(feature = "postgres")]
+    #[structopt(long = "sink", help = "Postgres url, e.g. `postgres://user@localhost/db`, to upsert the accounts into")]
+    pub sink: Option<String>,
+
+    #[cfg(feature = "postgres")]
+    #[structopt(long = "sink-table", default_value = "accounts", help = "Table to upsert the accounts into with --sink")]
+    pub sink_table: String,
+
+    #[structopt(long = "load-state", parse(from_os_str), help = "Path to an engine state saved by --save-state to start from, instead of empty accounts")]
+    pub load_state: Option<std::path::PathBuf>,
+
+    #[structopt(long = "initial-accounts", parse(from_os_str), conflicts_with = "load-state", help = "Path to the accounts csv of an earlier run to start from, instead of empty accounts")]
+    pub initial_accounts: Option<std::path::PathBuf>,
+
+    #[structopt(long = "save-state", parse(from_os_str), help = "Path to save the engine state to, with the balances and the dispute history of all clients")]
+    pub save_state: Option<std::path::PathBuf>,
+
+    #[cfg(feature = "sqlite")]
+    #[structopt(long = "storage", parse(from_os_str), conflicts_with_all = &["load-state", "save-state", "initial-accounts", "checkpoint"], help = "Path to a SQLite database that keeps the accounts and the dispute history across runs")]
+    pub storage: Option<std::path::PathBuf>,
+
+    #[structopt(long = "checkpoint", parse(from_os_str), help = "Path to periodically save the engine state and the position in the file to")]
+    pub checkpoint: Option<std::path::PathBuf>,
+
+    #[structopt(long = "checkpoint-every", default_value = "100000", help = "Number of rows between two checkpoints")]
+    pub checkpoint_every: u64,
+
+    #[structopt(long = "resume", requires = "checkpoint", help = "Continues from the last checkpoint instead of the start of the file")]
+    pub resume: bool,
+
+    #[structopt(long = "watch", conflicts_with_all = &["rejections", "quarantine", "stats", "stats-out", "profile"], help = "Follows the file as it is appended to, and prints the accounts again after new rows")]
+    pub watch: bool,
+
+    #[structopt(long = "replay", conflicts_with_all = &["watch", "rejections", "quarantine", "stats", "stats-out", "profile", "load-state", "initial-accounts", "save-state", "checkpoint"], help = "Prints the account of the client after every transaction, instead of the accounts at the end")]
+    pub replay: bool,
+
+    #[structopt(long = "interval", default_value = "1", help = "Seconds between two polls of the file in --watch mode")]
+    pub interval: u64,
+
+    #[structopt(long = "metrics-addr", requires = "watch", help = "Address to serve the metrics of --watch mode on, at /metrics")]
+    pub metrics_addr: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Runs an HTTP server that applies posted transactions and serves the accounts
+    Serve {
+        #[structopt(long = "addr", default_value = "127.0.0.1:8080", help = "Address to listen on")]
+        addr: String,
+    },
+    /// Compares two accounts outputs, and exits with status 3 if they differ
+    Diff {
+        #[structopt(parse(from_os_str), help = "Path to the old accounts csv")]
+        old: std::path::PathBuf,
+        #[structopt(parse(from_os_str), help = "Path to the new accounts csv")]
+        new: std::path::PathBuf,
+    },
+    /// Writes the accounts of an input to a golden file, or checks them against it with --check and exits with status 3 if they differ
+    Golden {
+        #[structopt(parse(from_os_str), help = "Path to the transactions")]
+        input: std::path::PathBuf,
+        #[structopt(parse(from_os_str), help = "Path to the golden file of the expected accounts")]
+        expected: std::path::PathBuf,
+        #[structopt(long = "check", help = "Compares the accounts to the golden file and prints the differences, instead of writing it")]
+        check: bool,
+    },
+    /// Prints every transaction of a client in order, whether it was applied, and the running balance
+    Query {
+        #[structopt(long = "client", help = "Client id to print the transactions of")]
+        client: u16,
+        #[structopt(parse(from_os_str), required = true, help = "Paths to the transactions, directories or glob patterns, or `-` for stdin")]
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Traces the lifecycle of a transaction id: whether each transaction with the id was applied, and why not
+    Explain {
+        #[structopt(long = "tx", help = "Transaction id to trace")]
+        tx: u32,
+        #[structopt(parse(from_os_str), required = true, help = "Paths to the transactions, directories or glob patterns, or `-` for stdin")]
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Runs a gRPC server that applies submitted transactions and serves the accounts
+    #[cfg(feature = "grpc")]
+    Grpc {
+        #[structopt(long = "addr", default_value = "127.0.0.1:50051", help = "Address to listen on")]
+        addr: String,
+    },
+    /// Consumes transactions from a Kafka topic and prints snapshots of the accounts
+    #[cfg(feature = "kafka")]
+    Kafka {
+        #[structopt(long = "brokers", use_delimiter = true, default_value = "localhost:9092", help = "Comma separated list of brokers")]
+        brokers: Vec<String>,
+        #[structopt(long = "topic", help = "Topic of the transactions, one csv row per message")]
+        topic: String,
+        #[structopt(long = "group", default_value = "txreader", help = "Consumer group to commit the offsets to")]
+        group: String,
+        #[structopt(long = "interval", default_value = "5", help = "Seconds between two snapshots of the accounts")]
+        interval: u64,
+    },
+}
+
+/// Where the transactions are read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Paths(Vec<std::path::PathBuf>),
+    Stdin,
+}
+
+impl Cli {
+    /// Returns `Input::Stdin` if the only path is `-`, or if no path
+    /// is given and stdin is not a terminal.
+    pub fn input(&self) -> Option<Input> {
+        Input::from_paths(&self.paths)
+    }
+
+    /// Returns the format of --format, or a table if the accounts are
+    /// printed to a terminal, or csv.
+    pub fn output_format(&self) -> Format {
+        match self.format {
+            Some(format) => format,
+            None if self.output.is_none() && std::io::stdout().is_terminal() => Format::Table,
+            None => Format::Csv,
+        }
+    }
+
+    /// Returns the style of the table of the accounts, of --fixed,
+    /// --totals and --color.
+    pub fn table_style(&self) -> tx::TableStyle {
+        let colors = match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => self.output.is_none() && std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        };
+        tx::TableStyle { fixed: self.fixed, totals: self.totals, colors }
+    }
+
+    /// Returns the threshold of --max-errors, or of --fail-fast.
+    fn max_errors(&self) -> Option<std::sync::Arc<tx::threshold::Threshold>> {
+        let limit = match self.fail_fast {
+            true => Some(tx::threshold::Limit::Count(0)),
+            false => self.max_errors,
+        };
+        limit.map(|limit| std::sync::Arc::new(tx::threshold::Threshold::new(limit)))
+    }
+
+    /// Returns the mix of the generated transactions, if a ratio is set,
+    /// with the ratios that are not set as in the default mix.
+    pub fn ratios(&self) -> Option<Ratios> {
+        let ratios = [ self.deposit_ratio, self.withdrawal_ratio, self.dispute_ratio, self.resolve_ratio
+                     , self.chargeback_ratio, self.unlock_ratio, self.adjustment_ratio
+                     ];
+        if ratios.iter().all(Option::is_none) {
+            return None;
+        }
+        let default = if self.lifecycles { Ratios::LIFECYCLES } else { Ratios::RANDOM };
+        Some(Ratios { deposit:    self.deposit_ratio.unwrap_or(default.deposit)
+                    , withdrawal: self.withdrawal_ratio.unwrap_or(default.withdrawal)
+                    , dispute:    self.dispute_ratio.unwrap_or(default.dispute)
+                    , resolve:    self.resolve_ratio.unwrap_or(default.resolve)
+                    , chargeback: self.chargeback_ratio.unwrap_or(default.chargeback)
+                    , unlock:     self.unlock_ratio.unwrap_or(default.unlock)
+                    , adjustment: self.adjustment_ratio.unwrap_or(default.adjustment)
+                    })
+    }
+
+    /// Returns the `tx::Options` to read and apply the transactions with.
+    pub fn options(&self) -> tx::Options {
+        tx::Options { strict:                self.strict
+                    , report:                self.rejections.is_some()
+                    , rounding:              tx::Rounding { mode: self.rounding, precision: self.precision }
+                    , dispute_policy:        self.dispute_policy
+                    , allow_client_mismatch: self.allow_client_mismatch
+                    , duplicates:            self.duplicates
+                    , global_unique_tx:      self.global_unique_tx
+                    , locked_policy:         self.locked_policy
+                    , amounts:               self.amounts
+                    , csv:                   tx::CsvFormat { no_headers:    self.no_headers
+                                                           , columns:       self.columns.clone()
+                                                           , map:           self.map.clone()
+                                                           , delimiter:     self.delimiter
+                                                           , decimal_comma: self.decimal_comma
+                                                           }
+                    , capacity:              Some(self.capacity)
+                    , threads:               self.threads
+                    , mmap:                  self.mmap
+                    , fast_parse:            self.fast_parse
+                    , parallel_parse:        self.parallel_parse
+                    , max_memory:            self.max_memory
+                    , progress:              None
+                    , quarantine:            None
+                    , max_errors:            self.max_errors()
+                    , clients:               self.clients.clone()
+                    , limit:                 self.limit
+                    , until_tx:              self.until_tx
+                    , retries:               self.retries
+                    }
+    }
+}
+
+impl Input {
+    /// Returns `Input::Stdin` if the only path is `-`, or if no path
+    /// is given and stdin is not a terminal.
+    pub fn from_paths(paths: &[std::path::PathBuf]) -> Option<Input> {
+        match paths {
+            [path] if path.as_os_str() == "-" => Some(Input::Stdin),
+            [] if !std::io::stdin().is_terminal() => Some(Input::Stdin),
+            [] => None,
+            paths => Some(Input::Paths(paths.to_vec())),
+        }
+    }
+}
+
+/// The output format of the accounts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Jsonl,
+    Table,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    #[cfg(feature = "parquet")]
+    Arrow,
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &[ "csv", "json", "jsonl", "table"
+                                               , #[cfg(feature = "parquet")] "parquet"
+                                               , #[cfg(feature = "parquet")] "arrow"
+                                               , #[cfg(feature = "xlsx")] "xlsx"
+                                               , #[cfg(feature = "avro")] "avro"
+                                               ];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "csv"   => Ok(Format::Csv),
+            "json"  => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "table" => Ok(Format::Table),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Format::Parquet),
+            #[cfg(feature = "parquet")]
+            "arrow" => Ok(Format::Arrow),
+            #[cfg(feature = "xlsx")]
+            "xlsx"  => Ok(Format::Xlsx),
+            #[cfg(feature = "avro")]
+            "avro"  => Ok(Format::Avro),
+            _       => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+/// When the table of the accounts is colored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// If the accounts are printed to a terminal, and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        match s {
+            "auto"   => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never"  => Ok(Color::Never),
+            _        => Err(format!("unknown color choice `{}`", s)),
+        }
+    }
+}
+
+/// The format of the logs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "json"];
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _      => Err(format!("unknown log format `{}`", s)),
+        }
+    }
+}
+
+impl Cli {
+    /// Returns the level of the logs set by -q or -v, or `None` if
+    /// neither is set, in which case `RUST_LOG` is used.
+    pub fn log_level(&self) -> Option<log::LevelFilter> {
+        match (self.quiet, self.verbose) {
+            (true, _) => Some(log::LevelFilter::Off),
+            (false, 0) => None,
+            (false, 1) => Some(log::LevelFilter::Info),
+            (false, 2) => Some(log::LevelFilter::Debug),
+            (false, _) => Some(log::LevelFilter::Trace),
+        }
+    }
+}
+
+/// Parses a `field=column` mapping of `--map`.
+fn parse_mapping(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((field, column)) if !field.is_empty() && !column.is_empty() => Ok((field.to_string(), column.to_string())),
+        _ => Err(format!("expected `field=column`, found `{}`", s)),
+    }
+}
+
+/// Parses a share from 0 to 1, e.g. `0.05`, or a percentage like `5%`.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate = match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|percent| percent / 100.0),
+        None => s.parse::<f64>(),
+    };
+    match rate {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("expected a number from 0 to 1 or a percentage, found `{}`", s)),
+    }
+}
+
+/// Parses a weight that is not negative, e.g. `2.5`.
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if ratio >= 0.0 && ratio.is_finite() => Ok(ratio),
+        _ => Err(format!("expected a number that is not negative, found `{}`", s)),
+    }
+}
+
+/// Parses the single character of `--delimiter`, or `tab`.
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        b"tab" | b"\\t" => Ok(b'\t'),
+        &[delimiter] => Ok(delimiter),
+        _ => Err(format!("expected a single character or `tab`, found `{}`", s)),
+    }
 }
 
 pub fn args() -> Cli {
-    Cli::from_args()
-}
\ No newline at end of file
+    let args = with_config(Cli::from_args());
+    if !args.generate && args.command.is_none() && args.input().is_none() {
+        clap::Error::with_description(
+            "The <paths> argument is required unless --generate is set or stdin is piped",
+            clap::ErrorKind::MissingRequiredArgument
+        ).exit();
+    }
+    #[cfg(feature = "parquet")]
+    if args.fixed && matches!(args.format, Some(Format::Parquet | Format::Arrow)) {
+        clap::Error::with_description(
+            "--fixed can not be used with --format parquet or arrow",
+            clap::ErrorKind::ArgumentConflict
+        ).exit();
+    }
+    #[cfg(feature = "xlsx")]
+    if args.fixed && args.format == Some(Format::Xlsx) {
+        clap::Error::with_description(
+            "--fixed can not be used with --format xlsx",
+            clap::ErrorKind::ArgumentConflict
+        ).exit();
+    }
+    #[cfg(feature = "avro")]
+    if args.fixed && args.format == Some(Format::Avro) {
+        clap::Error::with_description(
+            "--fixed can not be used with --format avro",
+            clap::ErrorKind::ArgumentConflict
+        ).exit();
+    }
+    if args.min_amount < 0 || args.min_amount > args.max_amount {
+        clap::Error::with_description(
+            "--min-amount must be at least 0 and at most --max-amount",
+            clap::ErrorKind::InvalidValue
+        ).exit();
+    }
+    if (args.watch || args.checkpoint.is_some()) && !matches!(args.input(), Some(Input::Paths(ref paths)) if paths.len() == 1) {
+        clap::Error::with_description(
+            "--watch and --checkpoint require exactly one <paths> argument",
+            clap::ErrorKind::ArgumentConflict
+        ).exit();
+    }
+    args
+}
+/// Parses the command line again after the flags of the config file,
+/// `--config` or `config::DEFAULT_PATH`, so that the flags given on the
+/// command line override them. The config flags that are given on the
+/// command line by their long name are left out, since the values of
+/// one like `--columns` would add up otherwise, except for `--map`.
+fn with_config(args: Cli) -> Cli {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None if std::path::Path::new(config::DEFAULT_PATH).is_file() => std::path::PathBuf::from(config::DEFAULT_PATH),
+        None => return args,
+    };
+    let flags = config::args(&path)
+        .unwrap_or_else(|e| clap::Error::with_description(&e, clap::ErrorKind::InvalidValue).exit());
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let given = |flag: &std::ffi::OsString| {
+        let name = flag.to_string_lossy();
+        let name = name.split('=').next().unwrap_or_default();
+        name != "--map" && argv.iter().any(|arg| arg.to_string_lossy().split('=').next() == Some(name))
+    };
+    let flags = flags.into_iter().filter(|flag| !given(flag));
+    Cli::from_iter(argv.iter().take(1).cloned().chain(flags).chain(argv.iter().skip(1).cloned()))
+}
@@ -0,0 +1,313 @@
+use crate::tx::{self, Account, Options, Parsed, Report, TxError};
+use arrow::array::{Array, ArrayRef, BooleanArray, Decimal128Array, StringArray, UInt16Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use csv::{Position, StringRecord};
+use tracing::info_span;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::errors::ParquetError;
+use rust_decimal::Decimal;
+use std::io;
+use std::sync::Arc;
+
+/// Returns `true` if the file has the `.parquet` extension.
+pub fn is_parquet(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("parquet"))
+}
+
+/// Reads the transactions from a Parquet file and returns `Vec<Account>`
+/// that contains a list of parsed accounts.
+pub async fn accounts_from_parquet(path: &std::path::PathBuf) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_parquet(path, &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Reads the transactions from a Parquet file with the given `Options`.
+pub async fn process_parquet(path: &std::path::PathBuf, options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    tx::process(txns_from_parquet(path, options.strict)?, options).await
+}
+
+/// Returns an iterator that lazily reads the transactions from a Parquet
+/// file, one `RecordBatch` at a time. The columns are matched by name like
+/// the csv headers (`type`, `client`, `tx`, `amount`), and every value is
+/// parsed from its string form, so the rows are validated exactly like csv
+/// records. Only invalid rows are skipped unless `strict` is set, while a
+/// batch that can not be read or decoded is an error, like an IO error of
+/// a csv file.
+pub fn txns_from_parquet(path: &std::path::PathBuf, strict: bool) -> Result<impl Iterator<Item = Parsed> + Send, TxError> {
+    let span = info_span!("txns_from_parquet", path = %path.display()).entered();
+    let file = std::fs::File::open(path)?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+    drop(span);
+
+    let headers: StringRecord =
+        reader.schema().fields().iter()
+            .map(|field| field.name().as_str())
+            .collect();
+    let mut line = 0;
+    let txns =
+        reader
+            .flat_map(move |batch| {
+                let parsed = batch.map_err(ParquetError::from).map_err(TxError::from)
+                    .and_then(|batch| deserialize(&batch, &headers, line));
+                match parsed {
+                    Ok(rows) => { line += rows.len() as u64; rows },
+                    Err(e) => vec![Err(e)],
+                }
+            })
+            .filter(move |parsed| matches!(parsed, Err(TxError::Parquet(_))) || tx::keep(parsed, strict));
+    Ok(txns)
+}
+
+/// Deserializes the rows of a `RecordBatch` into `Transaction`. Each row is
+/// turned into a `csv::StringRecord` positioned at its row number, counted
+/// from 1, which is used in place of a line.
+fn deserialize(batch: &RecordBatch, headers: &StringRecord, offset: u64) -> Result<Vec<Parsed>, TxError> {
+    let columns = batch.columns().iter()
+        .map(|column| cast(column, &DataType::Utf8))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ParquetError::from)?;
+    let columns: Vec<&StringArray> = columns.iter()
+        .map(|column| column.as_any().downcast_ref::<StringArray>().unwrap())
+        .collect();
+    let rows = (0..batch.num_rows())
+        .map(|i| {
+            let line = offset + i as u64 + 1;
+            let mut record: StringRecord =
+                columns.iter()
+                    .map(|column| if column.is_null(i) { "" } else { column.value(i).trim() })
+                    .collect();
+            let mut pos = Position::new();
+            pos.set_line(line).set_record(line);
+            record.set_position(Some(pos));
+            record.deserialize(Some(headers))
+                .map(|txn| (line.into(), txn))
+                .map_err(|e| tx::parse_error(e, Some(headers)))
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// Writes the accounts to the `writer` as a Parquet file. The monetary
+/// columns are stored as `Decimal128` with four digits after decimal.
+pub async fn print_accounts_parquet(writer: &mut impl io::Write, accounts: &[Account]) -> Result<(), TxError> {
+    let batch = accounts_batch(accounts)?;
+    // `ArrowWriter` needs a `Send` writer, which e.g. `io::StdoutLock` is not
+    let mut buf = vec![];
+    let mut wtr = ArrowWriter::try_new(&mut buf, batch.schema(), None)?;
+    wtr.write(&batch)?;
+    wtr.close()?;
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// Number of accounts in a record batch of the Arrow output.
+const BATCH_ROWS: usize = 8192;
+
+/// Writes the accounts to the `writer` as an Arrow IPC stream, in record
+/// batches of `BATCH_ROWS` accounts with the columns of `accounts_batch`.
+/// A batch is built and flushed as soon as its accounts have arrived,
+/// so that a reader can start on it while the next ones are computed.
+/// Since the schema is written before the first account, the stream
+/// always has the `currency` column, which is null without a currency.
+pub async fn print_accounts_arrow(writer: &mut impl io::Write, accounts: impl IntoIterator<Item = Account>) -> Result<(), TxError> {
+    let mut wtr = StreamWriter::try_new(writer, &batch_of(&[], true)?.schema()).map_err(ParquetError::from)?;
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    let mut accounts = accounts.into_iter().peekable();
+    while let Some(account) = accounts.next() {
+        batch.push(account);
+        if batch.len() == BATCH_ROWS || accounts.peek().is_none() {
+            wtr.write(&batch_of(&batch, true)?).map_err(ParquetError::from)?;
+            wtr.flush().map_err(ParquetError::from)?;
+            batch.clear();
+        }
+    }
+    wtr.finish().map_err(ParquetError::from)?;
+    Ok(())
+}
+
+/// Returns the accounts as a `RecordBatch` with the same column
+/// names as the csv output, including the `currency` column only if
+/// any of the accounts has a currency.
+pub fn accounts_batch(accounts: &[Account]) -> Result<RecordBatch, TxError> {
+    batch_of(accounts, accounts.iter().any(|a| a.currency().is_some()))
+}
+
+/// Returns the accounts as a `RecordBatch`, with the `currency` column
+/// if `currency` is set.
+fn batch_of(accounts: &[Account], currency: bool) -> Result<RecordBatch, TxError> {
+    let money = DataType::Decimal128(38, 4);
+    let mut fields = vec![ Field::new("client",    DataType::UInt16,  false)
+                         , Field::new("available", money.clone(),     false)
+                         , Field::new("held",      money.clone(),     false)
+                         , Field::new("total",     money,             false)
+                         , Field::new("locked",    DataType::Boolean, false)
+                         ];
+    let decimals = |f: fn(&Account) -> Decimal| -> Result<ArrayRef, ParquetError> {
+        let array = accounts.iter().map(|a| mantissa(f(a))).collect::<Decimal128Array>()
+            .with_precision_and_scale(38, 4)?;
+        Ok(Arc::new(array))
+    };
+    let mut columns: Vec<ArrayRef> = vec![ Arc::new(accounts.iter().map(|a| a.client_id()).collect::<UInt16Array>())
+                                         , decimals(Account::available)?
+                                         , decimals(Account::held)?
+                                         , decimals(Account::total)?
+                                         , Arc::new(accounts.iter().map(|a| Some(a.is_locked())).collect::<BooleanArray>())
+                                         ];
+    if currency {
+        fields.insert(1, Field::new("currency", DataType::Utf8, true));
+        columns.insert(1, Arc::new(accounts.iter().map(Account::currency).collect::<StringArray>()));
+    }
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).map_err(ParquetError::from)?)
+}
+
+/// Returns the amount as an integer number of ten-thousandths.
+fn mantissa(amount: Decimal) -> i128 {
+    let mut amount = amount.round_dp(4);
+    amount.rescale(4);
+    amount.mantissa()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::columnar::*;
+    use arrow::array::{Float64Array, Int64Array};
+    use futures::executor::block_on;
+    use rust_decimal_macros::dec;
+    use std::convert::TryInto;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_process_parquet() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let schema = Arc::new(Schema::new(vec![ Field::new("type",   DataType::Utf8,    false)
+                                              , Field::new("client", DataType::Int64,   false)
+                                              , Field::new("tx",     DataType::Int64,   false)
+                                              , Field::new("amount", DataType::Float64, true)
+                                              ]));
+        let columns: Vec<ArrayRef> = vec![ Arc::new(StringArray::from(vec!["deposit", "withdrawal", "dispute", "dépôt", "deposit"]))
+                                         , Arc::new(Int64Array::from(vec![1, 1, 1, 1, 70000]))
+                                         , Arc::new(Int64Array::from(vec![1, 2, 1, 3, 4]))
+                                         , Arc::new(Float64Array::from(vec![Some(10.5), Some(0.25), None, Some(1.0), Some(1.0)]))
+                                         ];
+        let file = Builder::new().suffix(".parquet").tempfile()?;
+        let mut writer = ArrowWriter::try_new(file.reopen()?, schema.clone(), None)?;
+        writer.write(&RecordBatch::try_new(schema, columns)?)?;
+        writer.close()?;
+        let path = file.path().to_path_buf();
+
+        /*
+         * When
+         */
+        let (accounts, _) = block_on(process_parquet(&path, &Options::default()))?;
+        let strict = block_on(process_parquet(&path, &Options { strict: true, ..Options::default() }));
+
+        /*
+         * Then
+         */
+        assert!(is_parquet(&path));
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available(), dec!(-0.25));
+        assert_eq!(accounts[0].held(),      dec!(10.5));
+        assert_eq!(accounts[0].total(),     dec!(10.25));
+        assert!(matches!(strict, Err(TxError::Parse { line: 4, .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_parquet_corrupt() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let schema = Arc::new(Schema::new(vec![ Field::new("type",   DataType::Utf8,  false)
+                                              , Field::new("client", DataType::Int64, false)
+                                              , Field::new("tx",     DataType::Int64, false)
+                                              , Field::new("amount", DataType::Utf8,  true)
+                                              ]));
+        let columns: Vec<ArrayRef> = vec![ Arc::new(StringArray::from(vec!["deposit"; 100]))
+                                         , Arc::new(Int64Array::from(vec![1; 100]))
+                                         , Arc::new(Int64Array::from((1..=100).collect::<Vec<i64>>()))
+                                         , Arc::new(StringArray::from(vec!["1.0"; 100]))
+                                         ];
+        let mut bytes = vec![];
+        let mut writer = ArrowWriter::try_new(&mut bytes, schema.clone(), None)?;
+        writer.write(&RecordBatch::try_new(schema, columns)?)?;
+        writer.close()?;
+        // Overwrites the pages of the column chunks, which are read in
+        // batches after the footer
+        let footer = u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into()?) as usize;
+        let pages = bytes.len() - 8 - footer;
+        bytes[4..pages].iter_mut().for_each(|b| *b = 0xff);
+        let file = Builder::new().suffix(".parquet").tempfile()?;
+        std::fs::write(file.path(), &bytes)?;
+
+        /*
+         * When
+         */
+        let result = block_on(process_parquet(&file.path().to_path_buf(), &Options::default()));
+
+        /*
+         * Then
+         */
+        assert!(matches!(result, Err(TxError::Parquet(_))), "{:?}", result);
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_parquet() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let path = &std::path::PathBuf::from("transactions_simple.csv");
+        let accounts = block_on(tx::accounts_from_path(path))?;
+
+        /*
+         * When
+         */
+        let mut file = Builder::new().suffix(".parquet").tempfile()?;
+        block_on(print_accounts_parquet(&mut file, &accounts))?;
+
+        /*
+         * Then
+         */
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file.reopen()?)?.build()?
+            .collect::<Result<Vec<RecordBatch>, _>>()?;
+        assert_eq!(batches[0].num_rows(), 4);
+        let available = batches[0].column(1).as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(available.value_as_string(0), "1.4996");
+        assert_eq!(available.value_as_string(1), "2.0000");
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_arrow() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let txns = (1..=BATCH_ROWS as u16 + 10).map(|client| tx::Transaction::deposit(client, client.into(), dec!(1.5)));
+        let accounts = block_on(tx::accounts_from_iter(txns.collect::<Result<Vec<_>, _>>()?))?;
+
+        /*
+         * When
+         */
+        let mut stream = vec![];
+        block_on(print_accounts_arrow(&mut stream, accounts))?;
+
+        /*
+         * Then
+         */
+        let batches = arrow::ipc::reader::StreamReader::try_new(stream.as_slice(), None)?
+            .collect::<Result<Vec<RecordBatch>, _>>()?;
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), vec![BATCH_ROWS, 10]);
+        assert_eq!(batches[0].schema().field(1).name(), "currency");
+        assert_eq!(batches[1].column(1).null_count(), 10);
+        let available = batches[1].column(2).as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(available.value_as_string(9), "1.5000");
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+/// The config file that is read, if it exists, unless `--config` is set.
+pub const DEFAULT_PATH: &str = "txreader.toml";
+
+/// A config file: the flags by their long names, and a `[map]` table
+/// that renames the csv columns.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub map:   BTreeMap<String, Value>,
+    #[serde(flatten)]
+    pub flags: BTreeMap<String, Value>,
+}
+
+/// A value of a key of the config file. Tables other than `[map]` are
+/// not a `Value`, so they are rejected.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// Reads the config file at `path`, and returns its keys as the command
+/// line flags they stand for, e.g. `--precision=2` for `precision = 2`.
+pub fn args(path: &Path) -> Result<Vec<OsString>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read config file `{}`: {}", path.display(), e))?;
+    let config = parse(&text).map_err(|e| format!("invalid config file `{}`: {}", path.display(), e))?;
+    to_args(config)
+}
+
+/// Parses a config file as TOML.
+pub fn parse(text: &str) -> Result<Config, String> {
+    toml::from_str(text).map_err(|e: toml::de::Error| e.to_string())
+}
+
+/// Returns the command line flags of the config. A key is the long
+/// name of a flag, with `_` or `-` between words. A boolean flag is only
+/// set if its value is `true`, an array is joined with commas, and every
+/// key of the `[map]` table is a `--map` flag.
+pub fn to_args(config: Config) -> Result<Vec<OsString>, String> {
+    let mut args = vec![];
+    for (key, value) in config.flags {
+        let flag = format!("--{}", key.replace('_', "-"));
+        if flag == "--config" {
+            return Err(String::from("`config` can not be set in a config file"));
+        }
+        match value {
+            Value::Boolean(true) => args.push(OsString::from(flag)),
+            Value::Boolean(false) => {},
+            Value::Array(values) => {
+                let values = values.into_iter().map(|value| scalar(&key, value)).collect::<Result<Vec<_>, _>>()?;
+                args.push(OsString::from(format!("{}={}", flag, values.join(","))));
+            },
+            value => args.push(OsString::from(format!("{}={}", flag, scalar(&key, value)?))),
+        }
+    }
+    for (field, value) in config.map {
+        args.push(OsString::from(format!("--map={}={}", field, scalar(&format!("map.{}", field), value)?)));
+    }
+    Ok(args)
+}
+
+/// Returns a string or a number as the value of a flag.
+fn scalar(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Float(n) => Ok(n.to_string()),
+        _ => Err(format!("`{}` must be a string or a number", key)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        /*
+         * Given
+         */
+        let text = r##"
+            # engine
+            precision = 2
+            dispute_policy = "deposits"   # not withdrawals
+            strict = true
+            fixed = false
+            threads = 4
+            deposit_ratio = 0.5
+            columns = [ "type", "client",
+                        "tx", "amount" ]
+            delimiter = '#'
+
+            [map]
+            client = "client_id"
+        "##;
+
+        /*
+         * When
+         */
+        let config = parse(text);
+        let args = config.clone().and_then(to_args);
+
+        /*
+         * Then
+         */
+        assert_eq!(config.unwrap().flags["dispute_policy"], Value::String(String::from("deposits")));
+        assert_eq!(args, Ok(vec![ "--columns=type,client,tx,amount"
+                                , "--delimiter=#"
+                                , "--deposit-ratio=0.5"
+                                , "--dispute-policy=deposits"
+                                , "--precision=2"
+                                , "--strict"
+                                , "--threads=4"
+                                , "--map=client=client_id"
+                                ].into_iter().map(OsString::from).collect()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        /*
+         * Given
+         */
+        let invalid = [ "precision"
+                      , "precision = 2 3"
+                      , "rounding = \"half-even"
+                      , "columns = [\"type\" \"client\"]"
+                      , "[engine]\nthreads = 4"
+                      , "precision = 2\nprecision = 3"
+                      ];
+
+        /*
+         * When
+         */
+        let errors = invalid.iter().map(|text| parse(text)).collect::<Vec<_>>();
+        let config = to_args(parse("config = \"other.toml\"").unwrap());
+        let table = to_args(parse("[map]\nclient = []").unwrap());
+
+        /*
+         * Then
+         */
+        assert!(errors.iter().all(Result::is_err), "{:?}", errors);
+        assert!(config.is_err());
+        assert!(table.is_err());
+    }
+}
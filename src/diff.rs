@@ -0,0 +1,145 @@
+use crate::tx::Account;
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+
+/// The difference between the account of a client in a currency in two
+/// snapshots. A client that is missing from a snapshot has no account there.
+#[derive(Debug, PartialEq)]
+pub struct AccountDiff {
+    pub client_id: u16,
+    pub currency:  Option<String>,
+    pub old:       Option<Account>,
+    pub new:       Option<Account>,
+}
+
+#[derive(Serialize)]
+struct DiffRecord<'a> {
+    client:    u16,
+    /// Only written if any of the accounts has a currency.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency:  Option<&'a str>,
+    status:    &'static str,
+    available: Decimal,
+    held:      Decimal,
+    total:     Decimal,
+    locked:    &'static str,
+}
+
+impl AccountDiff {
+    /// Returns `true` if the account is locked in the new snapshot,
+    /// but was not locked, or not there, in the old one.
+    pub fn newly_locked(&self) -> bool {
+        self.new.as_ref().is_some_and(Account::is_locked)
+            && !self.old.as_ref().is_some_and(Account::is_locked)
+    }
+
+    fn delta(&self, funds: fn(&Account) -> Decimal) -> Decimal {
+        (self.new.as_ref().map_or(Decimal::ZERO, funds) - self.old.as_ref().map_or(Decimal::ZERO, funds)).normalize()
+    }
+
+    fn to_record(&self, currency: bool) -> DiffRecord<'_> {
+        let status = match (&self.old, &self.new) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "changed",
+        };
+        let locked = match (self.old.as_ref().map(Account::is_locked), self.new.as_ref().map(Account::is_locked)) {
+            _ if self.newly_locked() => "locked",
+            (Some(true), Some(false)) => "unlocked",
+            _ => "",
+        };
+        DiffRecord {
+            client:    self.client_id,
+            currency:  currency.then(|| self.currency.as_deref().unwrap_or_default()),
+            status,
+            available: self.delta(Account::available),
+            held:      self.delta(Account::held),
+            total:     self.delta(Account::total),
+            locked,
+        }
+    }
+}
+
+/// Compares two snapshots of the accounts, and returns the clients whose
+/// account differs, ordered by client id and currency.
+pub fn diff_accounts(old: Vec<Account>, new: Vec<Account>) -> Vec<AccountDiff> {
+    let mut diffs: BTreeMap<(u16, Option<String>), AccountDiff> = BTreeMap::new();
+    for account in old {
+        let (client_id, currency) = (account.client_id(), account.currency().map(String::from));
+        diffs.insert((client_id, currency.clone()), AccountDiff { client_id, currency, old: Some(account), new: None });
+    }
+    for account in new {
+        let (client_id, currency) = (account.client_id(), account.currency().map(String::from));
+        diffs.entry((client_id, currency.clone()))
+            .or_insert(AccountDiff { client_id, currency, old: None, new: None })
+            .new = Some(account);
+    }
+    diffs.into_values()
+        .filter(|diff| diff.old != diff.new)
+        .collect()
+}
+
+/// Writes the differences to the `writer` as csv, with the change in the
+/// available, held and total funds of every client, and whether the
+/// account got locked or unlocked.
+pub fn print_diffs_with(writer: &mut impl io::Write, diffs: &[AccountDiff]) -> Result<(), csv::Error> {
+    let currency = diffs.iter().any(|diff| diff.currency.is_some());
+    let mut wtr = WriterBuilder::new().from_writer(writer);
+    for diff in diffs {
+        wtr.serialize(diff.to_record(currency))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::{self, Engine, Transaction};
+    use rust_decimal_macros::dec;
+
+    fn accounts(txns: Vec<Transaction>) -> Vec<Account> {
+        let mut engine = Engine::new();
+        txns.into_iter().for_each(|txn| { let _ = engine.apply(txn); });
+        engine.into_accounts()
+    }
+
+    #[test]
+    fn test_diff_accounts() -> Result<(), tx::TxError> {
+        /*
+         * Given
+         */
+        let old = accounts(vec![ Transaction::deposit(1, 1, dec!(10))?
+                               , Transaction::deposit(2, 2, dec!(5))?
+                               , Transaction::deposit(3, 3, dec!(1))?
+                               ]);
+        let new = accounts(vec![ Transaction::deposit(1, 1, dec!(10))?
+                               , Transaction::deposit(2, 2, dec!(5))?
+                               , Transaction::dispute(2, 2)
+                               , Transaction::chargeback(2, 2)
+                               , Transaction::deposit(4, 4, dec!(2.5))?
+                               ]);
+
+        /*
+         * When
+         */
+        let diffs = diff_accounts(old, new);
+        let mut output = Vec::new();
+        print_diffs_with(&mut output, &diffs).unwrap();
+
+        /*
+         * Then
+         */
+        assert_eq!(diffs.iter().map(|d| d.client_id).collect::<Vec<u16>>(), vec![2, 3, 4]);
+        assert!(diffs[0].newly_locked());
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "client,status,available,held,total,locked\n\
+                    2,changed,-5,0,-5,locked\n\
+                    3,removed,-1,0,-1,\n\
+                    4,added,2.5,0,2.5,\n");
+        Ok(())
+    }
+}
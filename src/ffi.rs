@@ -0,0 +1,210 @@
+use crate::tx::{self, CsvFormat, Engine, Options};
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+/// Reads the transactions from the csv file at `path`, and writes the
+/// accounts as csv to `out_buf`, followed by a NUL, if it has room for
+/// them. Returns the length of the accounts without the NUL, so a larger
+/// buffer can be passed again if it is not less than `out_len`, or -1
+/// if the file can not be read or the reading panicked.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, and `out_buf` must point to
+/// `out_len` writable bytes, or be null if `out_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn txreader_process_file(path: *const c_char, out_buf: *mut c_char, out_len: usize) -> isize {
+    guarded(-1, || {
+        let path = match string(path) {
+            Some(path) => PathBuf::from(path),
+            None => return -1,
+        };
+        match tx::process_paths_blocking(&[path], &Options::default()) {
+            Ok((accounts, _)) => copy(&csv(&accounts), out_buf, out_len),
+            Err(e) => { log::error!("Error: {}", e); -1 },
+        }
+    })
+}
+
+/// Returns a new, empty `Engine`, which must be released with
+/// `txreader_engine_finish`, or null if it can not be created.
+#[no_mangle]
+pub extern "C" fn txreader_engine_new() -> *mut Engine {
+    guarded(std::ptr::null_mut(), || Box::into_raw(Box::new(Engine::new())))
+}
+
+/// Applies a transaction, given as a csv row without header, e.g.
+/// `deposit,1,1,2.5`. Returns 0 if it was applied, 1 if it was
+/// rejected, which leaves the accounts unchanged, and -1 if the row
+/// can not be parsed or the engine panicked.
+///
+/// # Safety
+///
+/// `engine` must have been returned by `txreader_engine_new` and not
+/// been finished yet, and `row` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn txreader_engine_apply(engine: *mut Engine, row: *const c_char) -> c_int {
+    guarded(-1, || {
+        let (engine, row) = match (engine.as_mut(), string(row)) {
+            (Some(engine), Some(row)) => (engine, row),
+            _ => return -1,
+        };
+        let options = Options { strict: true, csv: CsvFormat { no_headers: true, ..CsvFormat::default() }, ..Options::default() };
+        match tx::txns_from_reader(row.as_bytes(), &options).next() {
+            Some(Ok((_, txn))) => match engine.apply(txn) {
+                Ok(()) => 0,
+                Err(e) => { log::debug!("Rejected transaction: {}", e); 1 },
+            },
+            _ => -1,
+        }
+    })
+}
+
+/// Writes the accounts of the engine like `txreader_process_file`, and
+/// releases the engine once they were written. If `out_buf` has no room
+/// for them, the engine is kept, so that it can be finished again with
+/// a larger buffer. Returns -1 if the engine is null or panicked.
+///
+/// # Safety
+///
+/// `engine` must have been returned by `txreader_engine_new` and not
+/// been finished yet, and `out_buf` must point to `out_len` writable
+/// bytes, or be null if `out_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn txreader_engine_finish(engine: *mut Engine, out_buf: *mut c_char, out_len: usize) -> isize {
+    guarded(-1, || {
+        let accounts = match engine.as_ref() {
+            Some(engine) => {
+                let mut accounts: Vec<tx::Account> = engine.accounts().cloned().collect();
+                accounts.sort_by_key(tx::Account::client_id);
+                csv(&accounts)
+            },
+            None => return -1,
+        };
+        let written = copy(&accounts, out_buf, out_len);
+        if (written as usize) < out_len {
+            drop(Box::from_raw(engine));
+        }
+        written
+    })
+}
+
+/// Runs `f`, and returns `error` instead if it panics, since a panic
+/// must not unwind into the caller in C.
+fn guarded<T>(error: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        log::error!("Error: txreader panicked");
+        error
+    })
+}
+
+/// Returns the string behind a C string, or `None` if it is null or
+/// not UTF-8.
+unsafe fn string<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+fn csv(accounts: &[tx::Account]) -> Vec<u8> {
+    let mut output = vec![];
+    tx::write_csv(&mut output, accounts, false);
+    output
+}
+
+/// Copies `output` and a NUL to `out_buf` if it has room for both,
+/// and returns the length of `output`.
+unsafe fn copy(output: &[u8], out_buf: *mut c_char, out_len: usize) -> isize {
+    if output.len() < out_len && !out_buf.is_null() {
+        std::ptr::copy_nonoverlapping(output.as_ptr(), out_buf as *mut u8, output.len());
+        *out_buf.add(output.len()) = 0;
+    }
+    isize::try_from(output.len()).unwrap_or(isize::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_process_file() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,2.5
+                        withdrawal,1,2,1")?;
+        let path = CString::new(file.path().to_str().unwrap())?;
+        let missing = CString::new("missing.csv")?;
+        let mut small = [0 as c_char; 8];
+        let mut buf = [0 as c_char; 128];
+
+        /*
+         * When
+         */
+        let needed = unsafe { txreader_process_file(path.as_ptr(), small.as_mut_ptr(), small.len()) };
+        let written = unsafe { txreader_process_file(path.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+        let failed = unsafe { txreader_process_file(missing.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+
+        /*
+         * Then
+         */
+        let expected = "client,available,held,total,locked\n1,1.5,0.0,1.5,false\n";
+        assert_eq!((needed, written, failed), (expected.len() as isize, expected.len() as isize, -1));
+        assert_eq!(small, [0; 8]);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str()?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_engine() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let rows = ["deposit,1,1,2.5", "withdrawal,1,2,9", "dispute,1,1,", "deposit,1"]
+            .iter()
+            .map(|row| CString::new(*row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut small = [0 as c_char; 8];
+        let mut buf = [0 as c_char; 128];
+
+        /*
+         * When
+         */
+        let engine = txreader_engine_new();
+        let applied = rows.iter().map(|row| unsafe { txreader_engine_apply(engine, row.as_ptr()) }).collect::<Vec<_>>();
+        let needed = unsafe { txreader_engine_finish(engine, small.as_mut_ptr(), small.len()) };
+        let written = unsafe { txreader_engine_finish(engine, buf.as_mut_ptr(), buf.len()) };
+
+        /*
+         * Then
+         */
+        let expected = "client,available,held,total,locked\n1,0.0,2.5,2.5,false\n";
+        assert_eq!(applied, vec![0, 1, 0, -1]);
+        assert_eq!((needed, written), (expected.len() as isize, expected.len() as isize));
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str()?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_guarded() {
+        /*
+         * When
+         */
+        let panicked = guarded(-1, || -> isize { panic!("overflow") });
+        let returned = guarded(-1, || 3);
+
+        /*
+         * Then
+         */
+        assert_eq!((panicked, returned), (-1, 3));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::tx::{Account, Engine, Transaction, TxError};
+use log::info;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+use std::sync::Mutex;
+use tonic::{Request, Response, Status, Streaming};
+
+/// The messages and service traits generated from `proto/txreader.proto`.
+pub mod proto {
+    tonic::include_proto!("txreader");
+}
+
+use proto::settlement_server::{Settlement, SettlementServer};
+use proto::{GetAccountRequest, Rejection, StreamReply, SubmitReply, TransactionKind};
+
+/// The `Settlement` gRPC service, backed by an in-memory `Engine`.
+#[derive(Debug, Default)]
+pub struct Service {
+    engine: Mutex<Engine>,
+}
+
+/// Runs the `Settlement` gRPC service on `addr` until the process is
+/// stopped.
+pub async fn serve(addr: &str) -> Result<(), TxError> {
+    let addr = addr.parse().map_err(|e| TxError::Server(Box::new(e)))?;
+    info!("Listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(SettlementServer::new(Service::default()))
+        .serve(addr)
+        .await
+        .map_err(|e| TxError::Server(Box::new(e)))
+}
+
+impl Service {
+    /// Applies a transaction, and returns why it was rejected, if it was.
+    fn apply(&self, txn: Transaction) -> Option<String> {
+        let mut engine = self.engine.lock().unwrap();
+        engine.apply(txn).err().map(|e| e.to_string())
+    }
+}
+
+#[tonic::async_trait]
+impl Settlement for Service {
+    async fn submit_transaction(&self, request: Request<proto::Transaction>) -> Result<Response<SubmitReply>, Status> {
+        let txn = to_transaction(request.into_inner()).map_err(Status::invalid_argument)?;
+        let reason = self.apply(txn);
+        Ok(Response::new(SubmitReply { applied: reason.is_none(), reason }))
+    }
+
+    async fn stream_transactions(&self, request: Request<Streaming<proto::Transaction>>) -> Result<Response<StreamReply>, Status> {
+        let mut txns = request.into_inner();
+        let mut reply = StreamReply::default();
+        while let Some(txn) = txns.message().await? {
+            let tx = txn.tx;
+            let txn = to_transaction(txn).map_err(Status::invalid_argument)?;
+            match self.apply(txn) {
+                None => reply.applied += 1,
+                Some(reason) => reply.rejected.push(Rejection { tx, reason }),
+            }
+        }
+        Ok(Response::new(reply))
+    }
+
+    async fn get_account(&self, request: Request<GetAccountRequest>) -> Result<Response<proto::Account>, Status> {
+        let GetAccountRequest { client, currency } = request.into_inner();
+        let engine = self.engine.lock().unwrap();
+        u16::try_from(client).ok()
+            .and_then(|id| engine.account_in(id, currency.as_deref()))
+            .map(|account| Response::new(to_message(account)))
+            .ok_or_else(|| Status::not_found(format!("account `{}` not found", client)))
+    }
+}
+
+/// Converts a `proto::Transaction` into a valid `Transaction`, or
+/// returns why it is invalid.
+fn to_transaction(txn: proto::Transaction) -> Result<Transaction, String> {
+    let client = u16::try_from(txn.client)
+        .map_err(|_| format!("invalid client `{}`", txn.client))?;
+    let to_client = || -> Result<u16, String> {
+        let to = txn.to
+            .ok_or_else(|| TxError::InvalidTransfer(txn.tx).to_string())?;
+        u16::try_from(to)
+            .map_err(|_| format!("invalid client `{}`", to))
+    };
+    let amount = || -> Result<Decimal, String> {
+        let amount = txn.amount.as_deref()
+            .ok_or_else(|| TxError::MissingAmount(txn.tx).to_string())?;
+        amount.parse()
+            .map_err(|_| format!("invalid amount `{}`", amount))
+    };
+    let currency = txn.currency.clone();
+    let txn = match txn.kind() {
+        TransactionKind::Deposit    => Transaction::deposit(client, txn.tx, amount()?),
+        TransactionKind::Withdrawal => Transaction::withdrawal(client, txn.tx, amount()?),
+        TransactionKind::Dispute    => Ok(Transaction::dispute(client, txn.tx)),
+        TransactionKind::Resolve    => Ok(Transaction::resolve(client, txn.tx)),
+        TransactionKind::Chargeback => Ok(Transaction::chargeback(client, txn.tx)),
+        TransactionKind::Unlock     => Ok(Transaction::unlock(client, txn.tx)),
+        TransactionKind::Adjustment => Transaction::adjustment(client, txn.tx, amount()?),
+        TransactionKind::Transfer   => Transaction::transfer(client, to_client()?, txn.tx, amount()?),
+    };
+    txn.map(|txn| match currency {
+            Some(currency) => txn.with_currency(&currency),
+            None => txn,
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn to_message(account: &Account) -> proto::Account {
+    proto::Account {
+        client:    account.client_id().into(),
+        available: account.available().to_string(),
+        held:      account.held().to_string(),
+        total:     account.total().to_string(),
+        locked:    account.is_locked(),
+        currency:  account.currency().map(String::from),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+    use tonic::Code;
+
+    fn txn(kind: TransactionKind, client: u32, tx: u32, amount: Option<&str>) -> Request<proto::Transaction> {
+        Request::new(proto::Transaction { kind: kind.into(), client, tx, amount: amount.map(String::from), to: None, currency: None })
+    }
+
+    #[test]
+    fn test_submit_transaction() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let service = Service::default();
+
+        /*
+         * When
+         */
+        let deposit = block_on(service.submit_transaction(txn(TransactionKind::Deposit, 1, 1, Some("2.5"))))?;
+        let withdrawal = block_on(service.submit_transaction(txn(TransactionKind::Withdrawal, 1, 2, Some("3"))))?;
+        let dispute = block_on(service.submit_transaction(txn(TransactionKind::Dispute, 1, 1, None)))?;
+        let missing = block_on(service.submit_transaction(txn(TransactionKind::Deposit, 1, 3, None)));
+        let negative = block_on(service.submit_transaction(txn(TransactionKind::Deposit, 1, 4, Some("-1"))));
+        let account = block_on(service.get_account(Request::new(GetAccountRequest { client: 1, currency: None })))?;
+        let unknown = block_on(service.get_account(Request::new(GetAccountRequest { client: 2, currency: None })));
+
+        /*
+         * Then
+         */
+        assert_eq!(deposit.into_inner(), SubmitReply { applied: true, reason: None });
+        assert_eq!(withdrawal.into_inner(), SubmitReply { applied: false
+                                                        , reason: Some("insufficient available funds for transaction 2".into())
+                                                        });
+        assert!(dispute.into_inner().applied);
+        assert_eq!(missing.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(negative.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(account.into_inner(), proto::Account { client:    1
+                                                        , available: "0.0".into()
+                                                        , held:      "2.5".into()
+                                                        , total:     "2.5".into()
+                                                        , locked:    false
+                                                        , currency:  None
+                                                        });
+        assert_eq!(unknown.unwrap_err().code(), Code::NotFound);
+        Ok(())
+    }
+}
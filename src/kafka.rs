@@ -0,0 +1,101 @@
+use crate::tx::{self, CsvFormat, Engine, Options, TxError};
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use log::{debug, info};
+use std::time::{Duration, Instant};
+
+/// Where and how to consume the transactions from.
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    pub brokers:  Vec<String>,
+    pub topic:    String,
+    pub group:    String,
+    /// Time between two snapshots of the accounts.
+    pub interval: Duration,
+    /// Aborts on the first message that can not be parsed,
+    /// instead of skipping it.
+    pub strict:   bool,
+}
+
+/// Consumes the transactions from a Kafka topic, one csv row per message,
+/// and applies them to an `Engine` until the process is stopped. The
+/// offsets are committed to the consumer group after every poll, and
+/// `on_snapshot` is called with the engine at most once per interval,
+/// whenever new transactions were applied.
+pub fn consume(options: &KafkaOptions, mut on_snapshot: impl FnMut(&Engine)) -> Result<(), TxError> {
+    let mut consumer = Consumer::from_hosts(options.brokers.clone())
+        .with_topic(options.topic.clone())
+        .with_group(options.group.clone())
+        .with_fallback_offset(FetchOffset::Earliest)
+        .with_offset_storage(Some(GroupOffsetStorage::Kafka))
+        .create()?;
+    info!("Consuming {} from {:?}", options.topic, options.brokers);
+
+    let mut engine = Engine::new();
+    let mut applied = 0;
+    let mut last_snapshot = Instant::now();
+    loop {
+        for messages in consumer.poll()?.iter() {
+            for message in messages.messages() {
+                debug!("Message at offset {} of partition {}", message.offset, messages.partition());
+                applied += apply_message(&mut engine, message.value, options.strict)?;
+            }
+            consumer.consume_messageset(messages)?;
+        }
+        consumer.commit_consumed()?;
+
+        if applied > 0 && last_snapshot.elapsed() >= options.interval {
+            on_snapshot(&engine);
+            applied = 0;
+            last_snapshot = Instant::now();
+        }
+    }
+}
+
+/// Applies the transaction in a message to the engine. Returns the
+/// number of transactions that were read, that is `0` for a malformed
+/// message that was skipped. A message is a csv row without headers,
+/// with the columns of a file without a header row.
+fn apply_message(engine: &mut Engine, value: &[u8], strict: bool) -> Result<usize, TxError> {
+    let csv = CsvFormat { no_headers: true, ..CsvFormat::default() };
+    let mut read = 0;
+    for parsed in tx::txns_from_rows(value, &Options { strict, csv, ..Options::default() }) {
+        let (_, txn) = parsed?;
+        let _ = engine.apply(txn);
+        read += 1;
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_apply_message() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+
+        /*
+         * When
+         */
+        let deposit = apply_message(&mut engine, b"deposit,1,1,2.5", false)?;
+        let dispute = apply_message(&mut engine, b"dispute, 1, 1,", false)?;
+        let transfer = apply_message(&mut engine, b"deposit,1,5,1\ntransfer,1,6,0.5,2", false)?;
+        let currency = apply_message(&mut engine, b"deposit,3,4,7,,EUR", false)?;
+        let skipped = apply_message(&mut engine, b"deposit,x,2,1", false)?;
+        let strict = apply_message(&mut engine, b"deposit,x,2,1", true);
+
+        /*
+         * Then
+         */
+        assert_eq!((deposit, dispute, transfer, currency, skipped), (1, 1, 2, 1, 0));
+        assert!(matches!(strict, Err(TxError::Parse { .. })));
+        assert_eq!(engine.account(1).unwrap().held(), dec!(2.5));
+        assert_eq!(engine.account(2).unwrap().available(), dec!(0.5));
+        assert_eq!(engine.account_in(3, Some("EUR")).unwrap().available(), dec!(7));
+        Ok(())
+    }
+}
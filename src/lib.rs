@@ -1,2 +1,30 @@
+#[cfg(feature = "avro")]
+pub mod avro;
 pub mod cli;
+pub mod config;
+pub mod diff;
+pub mod metrics;
+pub mod query;
+pub mod server;
+pub mod trace;
+#[cfg(feature = "postgres")]
+pub mod sink;
 pub mod tx;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "tokio")]
+pub mod runtime;
+#[cfg(feature = "object-store")]
+pub mod store;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+pub use tx::{Account, Engine, Options, Transaction, TransactionKind, TxError};
\ No newline at end of file
@@ -1,28 +1,507 @@
+use anyhow::Context;
 use futures::executor::block_on;
 use log::{info, error};
-use std::path::PathBuf;
-use txreader::cli;
-use txreader::tx;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use txreader::cli::{self, Format, Input, LogFormat};
+use txreader::query::{self, Filter};
+use txreader::trace::LogTimings;
+use txreader::tx::quarantine::Quarantine;
+use tracing::{info_span, Instrument};
+use txreader::{server, tx};
 
-fn main() {
-    env_logger::init();
+/// The status the process exits with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Exit {
+    Success  = 0,
+    /// A file could not be read or written, a row is malformed with
+    /// --strict, --max-errors was exceeded, or the arguments are invalid.
+    Failure  = 1,
+    /// A transaction was rejected with --strict.
+    Rejected = 2,
+    /// The accounts compared by `diff` or `golden --check` differ.
+    Differ   = 3,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> ExitCode {
+        ExitCode::from(exit as u8)
+    }
+}
+
+fn main() -> ExitCode {
     let args = cli::args();
-    if args.generate {
-        block_on(generate(args.num_txns, args.num_clients));
+    init_logger(&args);
+    let timings = Arc::new(match args.profile {
+        Some(_) => LogTimings::profiling(),
+        None => LogTimings::default(),
+    });
+    if let Err(e) = tracing::subscriber::set_global_default(Arc::clone(&timings)) {
+        error!("Error: {}", e);
+    }
+    let exit = if args.command.is_some() {
+        run_command(&args)
+    } else if args.generate {
+        match generate(&args) {
+            Ok(()) => Exit::Success,
+            Err(error) => { error!("Error: {:?}", error); Exit::Failure },
+        }
+    } else if args.replay {
+        match replay(args.input().unwrap(), &args.options()) {
+            Ok(()) => Exit::Success,
+            Err(error) => { error!("Error: {:?}", error); Exit::Failure },
+        }
+    } else if args.watch {
+        let interval = std::time::Duration::from_secs(args.interval);
+        block_on(watch(&args.paths[0], interval, args.output_format(), args.table_style(), &args.options(), args.metrics_addr.as_deref()))
     } else {
-        block_on(read(&args.path.unwrap()));
+        let input = args.input().unwrap();
+        let progress = progress(&args, &input);
+        let quarantine = match quarantine(&args) {
+            Ok(quarantine) => quarantine,
+            Err(error) => { error!("Error: {:?}", error); return Exit::Failure.into() },
+        };
+        let options = tx::Options { progress: progress.clone(), quarantine: quarantine.clone(), ..args.options() };
+        let checkpoints = args.checkpoint.as_ref().map(|path| tx::checkpoint::Checkpoints {
+            path:   path.clone(),
+            every:  args.checkpoint_every,
+            resume: args.resume,
+        });
+        let state = State {
+            load:     args.load_state.as_ref(),
+            accounts: args.initial_accounts.as_ref(),
+            save:     args.save_state.as_ref(),
+            checkpoints,
+            #[cfg(feature = "sqlite")]
+            storage:  args.storage.as_ref(),
+        };
+        let output = Output {
+            format:     args.output_format(),
+            style:      args.table_style(),
+            path:       args.output.as_ref(),
+            shards:     args.shard_output.map_or(1, |shards| shards.get()),
+            rejections: args.rejections.as_ref(),
+            stats:      args.stats,
+            stats_out:  args.stats_out.as_ref(),
+            profile:    args.profile.as_ref().map(|path| (path, &*timings)),
+            #[cfg(feature = "postgres")]
+            sink:       args.sink.as_deref().map(|url| (url, args.sink_table.as_str())),
+        };
+        let mut exit = block_on(read(input, &output, &options, &state));
+        if let Some(Err(error)) = quarantine.map(|quarantine| quarantine.flush()) {
+            error!("Error: {:?}", error);
+            exit = Exit::Failure;
+        }
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+        exit
+    };
+    exit.into()
+}
+
+/// Returns the progress of reading the input if `--progress` is set, or
+/// if stderr is a terminal and `--quiet` is not set.
+fn progress(args: &cli::Cli, input: &Input) -> Option<Arc<tx::progress::Progress>> {
+    if !args.progress && (args.quiet || !io::stderr().is_terminal()) {
+        return None;
+    }
+    let total = match input {
+        Input::Paths(paths) => tx::expand_paths(paths).ok()
+            .and_then(|paths| paths.iter().map(|path| path.metadata().ok().filter(|m| m.is_file()).map(|m| m.len())).sum()),
+        Input::Stdin => None,
+    };
+    Some(Arc::new(tx::progress::Progress::new(total)))
+}
+
+/// Creates the quarantine file if `--quarantine` is set.
+fn quarantine(args: &cli::Cli) -> Result<Option<Arc<Quarantine>>, anyhow::Error> {
+    let Some(path) = &args.quarantine else { return Ok(None) };
+    let quarantine = Quarantine::create(path, &args.options().csv)
+        .with_context(|| format!("could not create the quarantine file {:?}", path))?;
+    Ok(Some(Arc::new(quarantine)))
+}
+
+/// Logs to stderr at the level of -q or -v, or of `RUST_LOG` if neither
+/// is set, as text or as one JSON object per line.
+fn init_logger(args: &cli::Cli) {
+    let mut builder = match args.log_level() {
+        Some(level) => { let mut builder = env_logger::Builder::new(); builder.filter_level(level); builder },
+        None => env_logger::Builder::from_default_env(),
+    };
+    if args.log_format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({ "time":    buf.timestamp().to_string()
+                                         , "level":   record.level().as_str()
+                                         , "target":  record.target()
+                                         , "message": record.args().to_string()
+                                         });
+            writeln!(buf, "{}", line)
+        });
     }
+    builder.init();
+}
+
+/// Where to write the accounts, the rejected transactions and the
+/// summary statistics to.
+struct Output<'a> {
+    format:     Format,
+    /// Whether the funds are printed with exactly four digits after
+    /// decimal, and how a table is printed.
+    style:      tx::TableStyle,
+    /// The file to write the accounts to instead of stdout, and the
+    /// number of files to split them into by range of client ids.
+    path:       Option<&'a PathBuf>,
+    shards:     usize,
+    rejections: Option<&'a PathBuf>,
+    /// Prints the statistics to stderr.
+    stats:      bool,
+    stats_out:  Option<&'a PathBuf>,
+    /// Where to write the profile of the run, as kept by the timings.
+    profile:    Option<(&'a PathBuf, &'a LogTimings)>,
+    /// The url and the table of a Postgres database.
+    #[cfg(feature = "postgres")]
+    sink:       Option<(&'a str, &'a str)>,
 }
 
-async fn read(path: &PathBuf) {
-    info!("Reading from path {:?}", path);
-    match tx::read(path).await {
-        Ok(_) => info!("Done."),
-        Err(error) => error!("Error: {:?}", error)
+/// Where to load the engine state or the initial accounts from before
+/// reading, where to save the state to afterwards, and where to
+/// checkpoint it in between.
+struct State<'a> {
+    load:        Option<&'a PathBuf>,
+    accounts:    Option<&'a PathBuf>,
+    save:        Option<&'a PathBuf>,
+    checkpoints: Option<tx::checkpoint::Checkpoints>,
+    #[cfg(feature = "sqlite")]
+    storage:     Option<&'a PathBuf>,
+}
+
+async fn read(input: Input, output: &Output<'_>, options: &tx::Options, state: &State<'_>) -> Exit {
+    info!("Reading from {:?}", input);
+    match read_to_stdout(input, output, options, state).await {
+        Ok(exit) => { info!("Done."); exit },
+        Err(error) => { error!("Error: {:?}", error); Exit::Failure },
     }
 }
 
-async fn generate(num_txns: u32, num_clients: u16) {
-    info!("Generating {} transactions from {} clients...", num_txns, num_clients);
-    tx::generate_txns(num_txns, num_clients).await
-}
\ No newline at end of file
+async fn read_to_stdout( input: Input
+                       , output: &Output<'_>
+                       , options: &tx::Options
+                       , state: &State<'_>
+                       ) -> Result<Exit, anyhow::Error> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = state.storage {
+        let (accounts, report) = store(input, options, path)?;
+        print(&selected(accounts, options), &report, output).await?;
+        return Ok(exit(&report, options));
+    }
+    let stateful = state.load.is_some() || state.accounts.is_some() || state.save.is_some() || state.checkpoints.is_some();
+    let (accounts, report) = if stateful {
+        resume(input, options, state).await?
+    } else {
+        match input {
+            Input::Paths(paths) => tx::process_paths(&paths, options).await?,
+            Input::Stdin => tx::process_reader(io::stdin(), options).await?,
+        }
+    };
+    print(&selected(accounts, options), &report, output).await?;
+    Ok(exit(&report, options))
+}
+
+/// Keeps only the accounts of the clients of --only-clients, if it is set.
+fn selected(accounts: Vec<tx::Account>, options: &tx::Options) -> Vec<tx::Account> {
+    match &options.clients {
+        Some(clients) => clients.accounts(accounts),
+        None => accounts,
+    }
+}
+
+/// Returns `Exit::Rejected` if a transaction was rejected with --strict.
+fn exit(report: &tx::Report, options: &tx::Options) -> Exit {
+    match options.strict && !report.stats.rejected.is_empty() {
+        true => Exit::Rejected,
+        false => Exit::Success,
+    }
+}
+
+async fn print( accounts: &[tx::Account]
+              , report: &tx::Report
+              , output: &Output<'_>
+              ) -> Result<(), anyhow::Error> {
+    match output.path {
+        Some(path) => write_shards(path, output.shards, accounts, output).instrument(info_span!("print_accounts")).await?,
+        None => print_accounts(accounts, output.format, output.style).instrument(info_span!("print_accounts")).await?,
+    }
+    if let Some(rejections) = output.rejections {
+        write_rejections(rejections, &report.rejections).await?;
+    }
+    if output.stats {
+        eprint!("{}", report.stats);
+    }
+    if let Some(path) = output.stats_out {
+        std::fs::write(path, report.stats.to_string())?;
+    }
+    if let Some((path, timings)) = output.profile {
+        if let Some(profile) = timings.profile(report.stats.read) {
+            std::fs::write(path, serde_json::to_string_pretty(&profile)?)?;
+        }
+    }
+    #[cfg(feature = "postgres")]
+    if let Some((url, table)) = output.sink {
+        txreader::sink::upsert_accounts(url, table, accounts)?;
+    }
+    Ok(())
+}
+
+/// Writes the rejected transactions to a csv file, or to an Avro file
+/// if the path has the `.avro` extension and the feature is enabled.
+async fn write_rejections(path: &Path, rejections: &[tx::Rejection]) -> Result<(), anyhow::Error> {
+    let mut file = std::fs::File::create(path)?;
+    #[cfg(feature = "avro")]
+    if txreader::avro::is_avro(path) {
+        txreader::avro::print_rejections_avro(&mut file, rejections).await?;
+        return Ok(());
+    }
+    tx::print_rejections_with(&mut file, rejections).await;
+    Ok(())
+}
+
+/// Applies the transactions to the accounts kept in a SQLite database.
+#[cfg(feature = "sqlite")]
+fn store(input: Input, options: &tx::Options, path: &Path) -> Result<(Vec<tx::Account>, tx::Report), anyhow::Error> {
+    use tx::storage::{self, Storage};
+
+    let mut storage = storage::sqlite::SqliteStorage::open(path)?;
+    let report = match input {
+        Input::Paths(paths) => storage::process_paths(&paths, options, &mut storage)?,
+        Input::Stdin => storage::process_reader(io::stdin(), options, &mut storage)?,
+    };
+    Ok((storage.accounts()?, report))
+}
+
+/// Applies the transactions on top of the loaded engine state, and
+/// saves the new state.
+async fn resume(input: Input, options: &tx::Options, state: &State<'_>) -> Result<(Vec<tx::Account>, tx::Report), anyhow::Error> {
+    let engine = match (state.load, state.accounts) {
+        (Some(path), _) => tx::snapshot::load(path)?,
+        (None, Some(path)) => tx::Engine::from_accounts(tx::read_accounts_from_path(path)?),
+        (None, None) => tx::Engine::new(),
+    };
+    let (engine, report) = match (input, &state.checkpoints) {
+        (Input::Paths(paths), Some(checkpoints)) => tx::checkpoint::process_path(&paths[0], options, checkpoints, engine)?,
+        (Input::Paths(paths), None) => tx::resume_paths(&paths, options, engine).await?,
+        (Input::Stdin, _) => tx::resume_reader(io::stdin(), options, engine).await?,
+    };
+    if let Some(path) = state.save {
+        tx::snapshot::save(path, &engine)?;
+    }
+    Ok((engine.into_accounts(), report))
+}
+
+async fn print_accounts(accounts: &[tx::Account], format: Format, style: tx::TableStyle) -> Result<(), anyhow::Error> {
+    write_accounts(&mut io::stdout().lock(), accounts, format, style).await
+}
+
+/// Writes the accounts to `shards` files, each with a range of client
+/// ids, named like the shards of `--generate --out`.
+async fn write_shards(path: &Path, shards: usize, accounts: &[tx::Account], output: &Output<'_>) -> Result<(), anyhow::Error> {
+    let paths = tx::shard_paths(path, shards);
+    for (path, accounts) in paths.iter().zip(tx::shard_accounts(accounts, paths.len())) {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        write_accounts(&mut file, accounts, output.format, output.style).await?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+async fn write_accounts(writer: &mut impl io::Write, accounts: &[tx::Account], format: Format, style: tx::TableStyle) -> Result<(), anyhow::Error> {
+    match format {
+        Format::Csv   if style.fixed => tx::print_fixed_accounts_with(writer, accounts).await,
+        Format::Json  if style.fixed => tx::print_fixed_accounts_json(writer, accounts).await,
+        Format::Jsonl if style.fixed => tx::print_fixed_accounts_jsonl(writer, accounts).await,
+        Format::Csv   => tx::print_accounts_with(writer, accounts).await,
+        Format::Json  => tx::print_accounts_json(writer, accounts).await,
+        Format::Jsonl => tx::print_accounts_jsonl(writer, accounts).await,
+        Format::Table => tx::print_accounts_table_styled(writer, accounts, style).await,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => txreader::columnar::print_accounts_parquet(writer, accounts).await?,
+        #[cfg(feature = "parquet")]
+        Format::Arrow => txreader::columnar::print_accounts_arrow(writer, accounts.iter().cloned()).await?,
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => txreader::xlsx::print_accounts_xlsx(writer, accounts).await?,
+        #[cfg(feature = "avro")]
+        Format::Avro => txreader::avro::print_accounts_avro(writer, accounts).await?,
+    }
+    Ok(())
+}
+
+async fn watch(path: &Path, interval: Duration, format: Format, style: tx::TableStyle, options: &tx::Options, metrics: Option<&str>) -> Exit {
+    info!("Watching {:?}", path);
+    match watch_to_stdout(path, interval, format, style, options, metrics).await {
+        Ok(_) => { info!("Done."); Exit::Success },
+        Err(error) => { error!("Error: {:?}", error); Exit::Failure },
+    }
+}
+
+/// Polls the file every interval, and prints all accounts again
+/// whenever new rows were applied. The metrics of the engine are
+/// served on a thread of their own if `metrics` is set.
+async fn watch_to_stdout( path: &Path
+                        , interval: Duration
+                        , format: Format
+                        , style: tx::TableStyle
+                        , options: &tx::Options
+                        , metrics: Option<&str>
+                        ) -> Result<(), anyhow::Error> {
+    let engine = Arc::new(Mutex::new(tx::Engine::new().with_options(options.clone())));
+    if let Some(addr) = metrics {
+        let addr = addr.to_string();
+        let engine = Arc::clone(&engine);
+        std::thread::spawn(move || {
+            if let Err(error) = txreader::metrics::serve(&addr, engine) {
+                error!("Error: {:?}", error);
+            }
+        });
+    }
+    let mut tail = tx::Tail::new(path);
+    loop {
+        let accounts = {
+            let mut engine = engine.lock().unwrap_or_else(PoisonError::into_inner);
+            match tail.poll(&mut engine, options.strict)? {
+                0 => None,
+                _ => Some(selected(engine.accounts().cloned().collect::<Vec<_>>(), options)),
+            }
+        };
+        if let Some(mut accounts) = accounts {
+            accounts.sort_by_key(tx::Account::client_id);
+            print_accounts(&accounts, format, style).await?;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn run_command(args: &cli::Cli) -> Exit {
+    let result = match args.command.as_ref().unwrap() {
+        cli::Command::Serve { addr } => server::serve(addr, &args.options()).map(|()| Exit::Success),
+        cli::Command::Diff { old, new } => diff(old, new),
+        cli::Command::Golden { input, expected, check } => golden(input, expected, *check, &args.options()),
+        cli::Command::Query { client, paths } => query(paths, &args.options(), Filter::Client(*client)).map(|()| Exit::Success),
+        cli::Command::Explain { tx, paths } => query(paths, &args.options(), Filter::Tx(*tx)).map(|()| Exit::Success),
+        #[cfg(feature = "grpc")]
+        cli::Command::Grpc { addr } =>
+            tokio::runtime::Runtime::new()
+                .map_err(tx::TxError::from)
+                .and_then(|runtime| runtime.block_on(txreader::grpc::serve(addr)))
+                .map(|()| Exit::Success),
+        #[cfg(feature = "kafka")]
+        cli::Command::Kafka { brokers, topic, group, interval } => {
+            let options = txreader::kafka::KafkaOptions { brokers: brokers.clone()
+                                                        , topic: topic.clone()
+                                                        , group: group.clone()
+                                                        , interval: Duration::from_secs(*interval)
+                                                        , strict: args.strict
+                                                        };
+            txreader::kafka::consume(&options, |engine| {
+                let mut accounts: Vec<_> = engine.accounts().cloned().collect();
+                accounts.sort_by_key(tx::Account::client_id);
+                if let Err(error) = block_on(print_accounts(&accounts, args.output_format(), args.table_style())) {
+                    error!("Error: {:?}", error);
+                }
+            }).map(|()| Exit::Success)
+        },
+    };
+    match result {
+        Ok(exit) => exit,
+        Err(error) => { error!("Error: {:?}", error); Exit::Failure },
+    }
+}
+
+/// Prints the differences between two accounts outputs, and returns
+/// `Exit::Differ` if there are any.
+fn diff(old: &PathBuf, new: &PathBuf) -> Result<Exit, tx::TxError> {
+    let diffs = txreader::diff::diff_accounts(tx::read_accounts_from_path(old)?, tx::read_accounts_from_path(new)?);
+    txreader::diff::print_diffs_with(&mut io::stdout().lock(), &diffs).map_err(io::Error::from)?;
+    match diffs.is_empty() {
+        true => Ok(Exit::Success),
+        false => Ok(Exit::Differ),
+    }
+}
+
+/// Writes the accounts of the input to `expected` in a canonical form,
+/// ordered by client id and currency with four digits after decimal. With
+/// `check` it compares them to `expected` instead, prints the differences
+/// and returns `Exit::Differ` if there are any.
+fn golden(input: &PathBuf, expected: &PathBuf, check: bool, options: &tx::Options) -> Result<Exit, tx::TxError> {
+    let (mut accounts, _) = tx::process_paths_blocking(std::slice::from_ref(input), options)?;
+    accounts.sort_by(|a, b| (a.client_id(), a.currency()).cmp(&(b.client_id(), b.currency())));
+    let mut output = vec![];
+    block_on(tx::print_fixed_accounts_with(&mut output, &accounts));
+    if !check {
+        std::fs::write(expected, output)?;
+        info!("Wrote {} accounts to {}", accounts.len(), expected.display());
+        return Ok(Exit::Success);
+    }
+    if std::fs::read(expected)? == output {
+        return Ok(Exit::Success);
+    }
+    let diffs = txreader::diff::diff_accounts(tx::read_accounts_from_path(expected)?, accounts);
+    match diffs.is_empty() {
+        true => error!("{} has the same accounts, but not in the canonical form", expected.display()),
+        false => txreader::diff::print_diffs_with(&mut io::stdout().lock(), &diffs).map_err(io::Error::from)?,
+    }
+    Ok(Exit::Differ)
+}
+
+/// Prints the account of the client after every transaction.
+fn replay(input: Input, options: &tx::Options) -> Result<(), tx::TxError> {
+    let stdout = io::stdout();
+    let mut lock = stdout.lock();
+    match input {
+        Input::Paths(paths) => query::replay_paths(&mut lock, &paths, options),
+        Input::Stdin => query::replay_reader(&mut lock, io::stdin(), options),
+    }
+}
+
+/// Prints the transactions that match the filter, whether each was
+/// applied or rejected, and the balance of the account after it.
+fn query(paths: &[PathBuf], options: &tx::Options, filter: Filter) -> Result<(), tx::TxError> {
+    let entries = match Input::from_paths(paths) {
+        Some(Input::Paths(paths)) => query::trace_paths(&paths, options, filter)?,
+        _ => query::trace_reader(io::stdin(), options, filter)?,
+    };
+    query::print_entries_with(&mut io::stdout().lock(), &entries).map_err(io::Error::from)?;
+    Ok(())
+}
+
+fn generate(args: &cli::Cli) -> Result<(), tx::TxError> {
+    info!("Generating {} transactions from {} clients...", args.num_txns, args.num_clients);
+    let generator = tx::generate::Generator { num_txns:       args.num_txns
+                                            , num_clients:    args.num_clients
+                                            , seed:           args.seed
+                                            , lifecycles:     args.lifecycles
+                                            , invalid_rate:   args.invalid_rate.unwrap_or(0.0)
+                                            , min_amount:     args.min_amount
+                                            , max_amount:     args.max_amount
+                                            , amounts:        args.amount_distribution
+                                            , clients:        args.client_distribution
+                                            , malformed_rate: args.malformed_rate.unwrap_or(0.0)
+                                            , ratios:         args.ratios()
+                                            , currencies:     args.currencies.clone()
+                                            , timestamps:     args.timestamps
+                                            };
+    match &args.out {
+        Some(out) => {
+            let shards = args.shards.map_or(1, |shards| shards.get());
+            let paths = match args.parallel {
+                true => tx::generate::write_parts(out, shards, &generator)?,
+                false => tx::generate::write_shards(out, shards, &generator)?,
+            };
+            info!("Wrote {} rows to {} files", generator.num_txns, paths.len());
+            Ok(())
+        },
+        None => tx::generate::write_rows(io::BufWriter::new(io::stdout().lock()), &generator.columns(), generator.rows()),
+    }
+}
@@ -0,0 +1,104 @@
+use crate::tx::{Engine, TxError};
+use log::{debug, info, warn};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use tiny_http::{Header, Response, Server};
+
+/// Returns the metrics of the engine in the Prometheus text format: the
+/// transactions applied so far, the rejected ones by reason, and the
+/// chargebacks as counters, and the accounts, the locked accounts and
+/// the total held funds as gauges.
+pub fn render(engine: &Engine) -> String {
+    let stats = engine.stats();
+    let mut out = String::new();
+    metric(&mut out, "txreader_transactions_processed_total", "counter", "Transactions applied or rejected.", stats.read);
+    header(&mut out, "txreader_transactions_rejected_total", "counter", "Transactions rejected, by reason.");
+    for (reason, n) in &stats.rejected {
+        let _ = writeln!(out, "txreader_transactions_rejected_total{{reason=\"{}\"}} {}", reason, n);
+    }
+    metric(&mut out, "txreader_chargebacks_total", "counter", "Chargebacks applied.", engine.chargebacks());
+    metric(&mut out, "txreader_accounts", "gauge", "Accounts of all clients.", stats.accounts);
+    metric(&mut out, "txreader_locked_accounts", "gauge", "Accounts locked by a chargeback.", stats.locked);
+    metric(&mut out, "txreader_held_funds", "gauge", "Funds held by disputes, over all accounts.", stats.held);
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Returns the header of a response with metrics.
+pub(crate) fn content_type() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap()
+}
+
+/// Runs an HTTP server on `addr` that serves the metrics of the engine
+/// at `GET /metrics`, e.g. on a thread of its own next to `--watch`. A
+/// response that fails, e.g. as the client disconnected, is logged and
+/// the server keeps serving.
+pub fn serve(addr: &str, engine: Arc<Mutex<Engine>>) -> Result<(), TxError> {
+    let server = Server::http(addr).map_err(TxError::Server)?;
+    info!("Serving metrics on {}", server.server_addr());
+    for request in server.incoming_requests() {
+        let response = match (request.method().as_str(), request.url()) {
+            ("GET", "/metrics") => {
+                let metrics = render(&engine.lock().unwrap_or_else(PoisonError::into_inner));
+                Response::from_string(metrics).with_header(content_type())
+            },
+            _ => Response::from_string("not found").with_status_code(404),
+        };
+        debug!("{} {} -> {}", request.method(), request.url(), response.status_code().0);
+        if let Err(e) = request.respond(response) {
+            warn!("Could not respond to a request for the metrics: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::Transaction;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_render() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+        engine.apply(Transaction::deposit(2, 2, dec!(2.5))?)?;
+        engine.apply(Transaction::withdrawal(2, 3, dec!(5))?).ok();
+        engine.apply(Transaction::dispute(1, 1))?;
+        engine.apply(Transaction::dispute(2, 2))?;
+        engine.apply(Transaction::chargeback(2, 2))?;
+        engine.apply(Transaction::chargeback(1, 4)).ok();
+
+        /*
+         * When
+         */
+        let metrics = render(&engine);
+
+        /*
+         * Then
+         */
+        let samples = metrics.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+        assert_eq!(samples, vec![ "txreader_transactions_processed_total 7"
+                                , "txreader_transactions_rejected_total{reason=\"insufficient_funds\"} 1"
+                                , "txreader_transactions_rejected_total{reason=\"unknown_tx\"} 1"
+                                , "txreader_chargebacks_total 1"
+                                , "txreader_accounts 2"
+                                , "txreader_locked_accounts 1"
+                                , "txreader_held_funds 10"
+                                ]);
+        assert!(metrics.contains("# TYPE txreader_locked_accounts gauge\n"));
+        Ok(())
+    }
+}
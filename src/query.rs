@@ -0,0 +1,263 @@
+use crate::tx::{self, Account, Engine, Options, Parsed, Transaction, TransactionKind, TxError};
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::io;
+
+/// Which transactions to trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// All transactions of a client, including the transfers to it.
+    Client(u16),
+    /// All transactions with a transaction id, i.e. a deposit or a
+    /// withdrawal and the disputes, resolves and chargebacks of it.
+    Tx(u32),
+    /// Every transaction, to replay the whole log.
+    All,
+}
+
+impl Filter {
+    fn matches(&self, txn: &Transaction) -> bool {
+        match *self {
+            Filter::Client(client_id) => txn.client_id() == client_id || txn.to_client() == Some(client_id),
+            Filter::Tx(tx_id) => txn.tx_id() == tx_id,
+            Filter::All => true,
+        }
+    }
+}
+
+/// A traced `Transaction`, together with the reason if it was rejected,
+/// and the account of its client right after it, or of the filtered
+/// client for a transfer to it.
+#[derive(Debug)]
+pub struct Entry {
+    pub line:     u64,
+    pub txn:      Transaction,
+    pub rejected: Option<TxError>,
+    pub account:  Account,
+}
+
+#[derive(Serialize)]
+struct EntryRecord<'a> {
+    line:      u64,
+    #[serde(rename = "type")]
+    kind:      TransactionKind,
+    client:    u16,
+    tx:        u32,
+    amount:    Option<Decimal>,
+    to_client: Option<u16>,
+    currency:  Option<&'a str>,
+    status:    &'static str,
+    reason:    &'a str,
+    available: Decimal,
+    held:      Decimal,
+    total:     Decimal,
+    locked:    bool,
+}
+
+/// Reads the transactions from several files as one ordered log, like
+/// `tx::process_paths`, applies them in order, and returns every
+/// transaction that matches the `filter`.
+pub fn trace_paths(paths: &[std::path::PathBuf], options: &Options, filter: Filter) -> Result<Vec<Entry>, TxError> {
+    trace(tx::txns_from_paths(paths, options)?, options, filter).collect()
+}
+
+/// Reads the transactions from a `std::io::Read` reader, applies them
+/// in order, and returns every transaction that matches the `filter`.
+pub fn trace_reader(reader: impl io::Read, options: &Options, filter: Filter) -> Result<Vec<Entry>, TxError> {
+    trace(tx::txns_from_reader(reader, options), options, filter).collect()
+}
+
+/// Reads the transactions from several files as one ordered log, and
+/// writes the state of the account to the `writer` after every one of
+/// them, as it is applied, like `print_entries_with`.
+pub fn replay_paths(writer: &mut impl io::Write, paths: &[std::path::PathBuf], options: &Options) -> Result<(), TxError> {
+    write_entries(writer, trace(tx::txns_from_paths(paths, options)?, options, Filter::All))
+}
+
+/// Reads the transactions from a `std::io::Read` reader, and writes the
+/// state of the account to the `writer` after every one of them.
+pub fn replay_reader(writer: &mut impl io::Write, reader: impl io::Read, options: &Options) -> Result<(), TxError> {
+    write_entries(writer, trace(tx::txns_from_reader(reader, options), options, Filter::All))
+}
+
+/// Applies the transactions in order, and lazily yields every one that
+/// matches the `filter`. Stops at the first error.
+fn trace(txns: impl Iterator<Item = Parsed>, options: &Options, filter: Filter) -> impl Iterator<Item = Result<Entry, TxError>> {
+    let mut engine = Engine::new().with_options(options.clone());
+    let unit = options.amounts;
+    txns.filter_map(move |parsed| {
+        let (line, txn) = match parsed {
+            Ok((line, txn)) => (line.line, txn.in_unit(unit)),
+            Err(e) => return Some(Err(e)),
+        };
+        if !filter.matches(&txn) {
+            let _ = engine.apply(txn);
+            return None;
+        }
+        let client_id = match filter {
+            Filter::Client(client_id) => client_id,
+            _ => txn.client_id(),
+        };
+        let rejected = engine.apply(txn.clone()).err();
+        let account = engine.account_in(client_id, txn.currency()).cloned()
+            .unwrap_or_else(|| Account::new_in(client_id, txn.currency()));
+        Some(Ok(Entry { line, txn, rejected, account }))
+    })
+}
+
+/// Writes the traced transactions to the `writer` as csv, with whether
+/// each was applied or rejected and why, and the resulting balance.
+pub fn print_entries_with(writer: &mut impl io::Write, entries: &[Entry]) -> Result<(), csv::Error> {
+    let mut wtr = WriterBuilder::new().from_writer(writer);
+    entries.iter().try_for_each(|entry| serialize_entry(&mut wtr, entry))?;
+    wtr.flush()?;
+    Ok(())
+}
+
+fn write_entries(writer: &mut impl io::Write, entries: impl Iterator<Item = Result<Entry, TxError>>) -> Result<(), TxError> {
+    let mut wtr = WriterBuilder::new().from_writer(writer);
+    for entry in entries {
+        serialize_entry(&mut wtr, &entry?).map_err(io::Error::from)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn serialize_entry(wtr: &mut csv::Writer<impl io::Write>, entry: &Entry) -> Result<(), csv::Error> {
+    let reason = entry.rejected.as_ref().map(TxError::to_string).unwrap_or_default();
+    wtr.serialize(EntryRecord { line:      entry.line
+                              , kind:      entry.txn.kind()
+                              , client:    entry.txn.client_id()
+                              , tx:        entry.txn.tx_id()
+                              , amount:    entry.txn.amount()
+                              , to_client: entry.txn.to_client()
+                              , currency:  entry.txn.currency()
+                              , status:    if entry.rejected.is_some() { "rejected" } else { "applied" }
+                              , reason:    &reason
+                              , available: entry.account.available()
+                              , held:      entry.account.held()
+                              , total:     entry.account.total()
+                              , locked:    entry.account.is_locked()
+                              })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trace_reader_client() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,2,2,5
+                     withdrawal,1,3,20
+                     dispute,1,1,
+                     withdrawal,2,4,1";
+
+        /*
+         * When
+         */
+        let entries = trace_reader(io::Cursor::new(input), &Options::default(), Filter::Client(1))?;
+        let mut output = Vec::new();
+        print_entries_with(&mut output, &entries)?;
+
+        /*
+         * Then
+         */
+        assert_eq!(entries.iter().map(|e| e.line).collect::<Vec<u64>>(), vec![2, 4, 5]);
+        assert_eq!(String::from_utf8(output)?,
+                   "line,type,client,tx,amount,to_client,currency,status,reason,available,held,total,locked\n\
+                    2,deposit,1,1,10,,,applied,,10,0.0,10,false\n\
+                    4,withdrawal,1,3,20,,,rejected,insufficient available funds for transaction 3,10,0.0,10,false\n\
+                    5,dispute,1,1,,,,applied,,0,10,10,false\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_reader_transfer() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to,currency
+                     deposit,2,1,10,,EUR
+                     deposit,1,2,3,,
+                     transfer,2,3,4,1,EUR";
+
+        /*
+         * When
+         */
+        let entries = trace_reader(io::Cursor::new(input), &Options::default(), Filter::Client(1))?;
+        let mut output = Vec::new();
+        print_entries_with(&mut output, &entries)?;
+
+        /*
+         * Then
+         */
+        assert_eq!(String::from_utf8(output)?,
+                   "line,type,client,tx,amount,to_client,currency,status,reason,available,held,total,locked\n\
+                    3,deposit,1,2,3,,,applied,,3,0.0,3,false\n\
+                    4,transfer,2,3,4,1,EUR,applied,,4,0.0,4,false\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_reader_tx() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,1,2,5
+                     resolve,1,1,
+                     dispute,1,1,
+                     dispute,1,2,
+                     chargeback,1,1,
+                     resolve,1,2,";
+
+        /*
+         * When
+         */
+        let entries = trace_reader(io::Cursor::new(input), &Options::default(), Filter::Tx(1))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(entries.iter().map(|e| e.line).collect::<Vec<u64>>(), vec![2, 4, 5, 7]);
+        assert!(matches!(entries[1].rejected, Some(TxError::NotDisputed(1))));
+        assert!(entries[2].rejected.is_none());
+        assert!(entries[3].rejected.is_none());
+        assert!(entries[3].account.is_locked());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_reader() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,2,2,5
+                     dispute,1,1,";
+
+        /*
+         * When
+         */
+        let mut output = Vec::new();
+        replay_reader(&mut output, io::Cursor::new(input), &Options::default())?;
+
+        /*
+         * Then
+         */
+        assert_eq!(String::from_utf8(output)?,
+                   "line,type,client,tx,amount,to_client,currency,status,reason,available,held,total,locked\n\
+                    2,deposit,1,1,10,,,applied,,10,0.0,10,false\n\
+                    3,deposit,2,2,5,,,applied,,5,0.0,5,false\n\
+                    4,dispute,1,1,,,,applied,,0,10,10,false\n");
+        Ok(())
+    }
+}
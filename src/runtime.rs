@@ -0,0 +1,119 @@
+use crate::tx::{self, Account, Options, Parsed, Report, TxError};
+use futures::future::FutureObj;
+use futures::task::{Spawn, SpawnError};
+use std::path::{Path, PathBuf};
+use tokio::runtime::Handle;
+
+/// Spawns the workers and the reading of a run on a tokio runtime,
+/// instead of on the thread pool of the crate, e.g. with
+/// `tx::process_paths_with_spawner`.
+#[derive(Debug, Clone)]
+pub struct TokioSpawner {
+    handle: Handle,
+}
+
+impl TokioSpawner {
+    /// Spawns on the runtime of `handle`.
+    pub fn new(handle: Handle) -> TokioSpawner {
+        TokioSpawner { handle }
+    }
+
+    /// Spawns on the runtime the caller runs on. Panics if it is not
+    /// called from within a tokio runtime, like `Handle::current`.
+    pub fn current() -> TokioSpawner {
+        TokioSpawner::new(Handle::current())
+    }
+}
+
+impl Spawn for TokioSpawner {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        drop(self.handle.spawn(future));
+        Ok(())
+    }
+}
+
+/// Reads the transactions from a file on the blocking threads of tokio
+/// and returns `Vec<Account>` that contains a list of parsed accounts.
+/// It must be called from within a tokio runtime, which the workers are
+/// spawned on.
+pub async fn accounts_from_path(path: &PathBuf) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_paths(std::slice::from_ref(path), &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Like `tx::process_paths`, but reads the files on the blocking threads
+/// of tokio with `spawn_blocking`, and spawns the workers on the tokio
+/// runtime it is called from. The files are read through a buffer, up to
+/// `options.capacity` transactions ahead of the workers, so that a huge
+/// file is never held in memory as a whole.
+pub async fn process_paths(paths: &[PathBuf], options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    let files = tx::expand_paths(paths)?;
+    let mut txns = vec![];
+    for path in &files {
+        txns.push(txns_from_path(path, options).await?);
+    }
+    let (accounts, report) = tx::process_with(&TokioSpawner::current(), tx::limited(tx::in_files(txns), options), options).await?;
+    Ok((accounts, report.named(&files)))
+}
+
+/// Opens the file from path with `tx::txns_from_path`, and reads its
+/// transactions on a blocking thread of tokio, which sends them to the
+/// returned iterator. The thread stops once the iterator is dropped.
+async fn txns_from_path(path: &Path, options: &Options) -> Result<Box<dyn Iterator<Item = Parsed> + Send>, TxError> {
+    let (path, opened) = (path.to_path_buf(), options.clone());
+    let txns = tokio::task::spawn_blocking(move || tx::txns_from_path(&path, &opened)).await
+        .map_err(std::io::Error::from)??;
+    let (sender, receiver) = std::sync::mpsc::sync_channel(tx::capacity(options, 1));
+    drop(tokio::task::spawn_blocking(move || {
+        for parsed in txns {
+            if sender.send(parsed).is_err() {
+                break;
+            }
+        }
+    }));
+    Ok(Box::new(receiver.into_iter()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_process_paths() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,2.5
+                        deposit,2,2,4
+                        withdrawal,2,3,1
+                        dispute,1,1,")?;
+        let paths = [file.path().to_path_buf()];
+        let options = Options { threads: Some(2), report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, report) = process_paths(&paths, &options).await?;
+        let (bounded, _) = process_paths(&paths, &Options { capacity: Some(1), ..options.clone() }).await?;
+        let (spawned, _) = tx::process_paths_with_spawner(&TokioSpawner::current(), &paths, &options).await?;
+        let (pooled, _) = tx::process_paths(&paths, &options).await?;
+        let missing = accounts_from_path(&PathBuf::from("missing.csv")).await;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, pooled);
+        assert_eq!(bounded, pooled);
+        assert_eq!(spawned, pooled);
+        assert_eq!(accounts.iter().map(|a| (a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![(dec!(0), dec!(2.5)), (dec!(3), dec!(0))]);
+        assert!(report.rejections.is_empty());
+        assert!(matches!(missing, Err(TxError::Read { .. })));
+        Ok(())
+    }
+}
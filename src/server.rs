@@ -0,0 +1,187 @@
+use crate::metrics;
+use crate::tx::{self, Engine, Options, Transaction, TxError};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Request, Response, Server};
+
+/// The body of `POST /transactions` in JSON, either a single
+/// transaction or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Batch {
+    Many(Vec<Transaction>),
+    One(Transaction),
+}
+
+/// Runs an HTTP server on `addr` that applies the posted transactions to
+/// an in-memory `Engine`, and serves the current accounts:
+///
+/// * `POST /transactions` applies csv (`text/csv`) or JSON
+///   (`application/json`) transactions.
+/// * `GET /accounts` returns the accounts of all clients.
+/// * `GET /accounts/{client_id}` returns the account of a client.
+/// * `GET /metrics` returns the metrics of the engine for Prometheus.
+///
+/// The requests are handled one at a time, in the order they arrive,
+/// and the transactions are applied with `options`. A request that
+/// fails, e.g. as the client disconnected, is logged and the server
+/// keeps serving.
+pub fn serve(addr: &str, options: &Options) -> Result<(), TxError> {
+    let server = Server::http(addr).map_err(TxError::Server)?;
+    info!("Listening on {}", server.server_addr());
+    let mut engine = Engine::new().with_options(options.clone());
+    for request in server.incoming_requests() {
+        if let Err(e) = handle(&mut engine, request) {
+            warn!("Could not handle a request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(engine: &mut Engine, mut request: Request) -> Result<(), TxError> {
+    let content_type = request.headers().iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_owned());
+    let mut body = Vec::new();
+    request.as_reader().read_to_end(&mut body)?;
+    if request.method().as_str() == "GET" && request.url() == "/metrics" {
+        let response = Response::from_string(metrics::render(engine)).with_header(metrics::content_type());
+        request.respond(response)?;
+        return Ok(());
+    }
+    let (status, value) = route(engine, request.method().as_str(), request.url(), content_type.as_deref(), &body);
+    debug!("{} {} -> {}", request.method(), request.url(), status);
+
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    request.respond(response)?;
+    Ok(())
+}
+
+/// Returns the status code and the JSON body of the response to a request.
+fn route(engine: &mut Engine, method: &str, url: &str, content_type: Option<&str>, body: &[u8]) -> (u16, Value) {
+    let segments: Vec<&str> = url.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["transactions"]) => post_transactions(engine, content_type, body),
+        ("GET", ["accounts"]) => {
+            let mut accounts: Vec<_> = engine.accounts().collect();
+            accounts.sort_by_key(|a| a.client_id());
+            (200, json!(accounts))
+        },
+        ("GET", ["accounts", client_id]) => {
+            match client_id.parse().ok().and_then(|id| engine.account(id)) {
+                Some(account) => (200, json!(account)),
+                None => error(404, format!("account `{}` not found", client_id)),
+            }
+        },
+        (_, ["transactions"]) | (_, ["accounts"]) | (_, ["accounts", _]) =>
+            error(405, format!("method {} not allowed", method)),
+        _ => error(404, format!("`{}` not found", url)),
+    }
+}
+
+/// Parses all transactions of the body before any is applied, so a
+/// malformed body leaves the engine unchanged.
+fn post_transactions(engine: &mut Engine, content_type: Option<&str>, body: &[u8]) -> (u16, Value) {
+    let mime = content_type.and_then(|c| c.split(';').next()).map(str::trim);
+    let txns = match mime {
+        None | Some("text/csv") =>
+            tx::txns_from_reader(body, &Options { strict: true, ..Options::default() })
+                .map(|parsed| parsed.map(|(_, txn)| txn))
+                .collect::<Result<Vec<_>, TxError>>()
+                .map_err(|e| e.to_string()),
+        Some("application/json") =>
+            serde_json::from_slice(body)
+                .map(|batch| match batch {
+                    Batch::Many(txns) => txns,
+                    Batch::One(txn) => vec![txn],
+                })
+                .map_err(|e| e.to_string()),
+        Some(other) => return error(415, format!("unsupported content type `{}`", other)),
+    };
+    let txns = match txns {
+        Ok(txns) => txns,
+        Err(e) => return error(400, e),
+    };
+
+    let mut applied = 0;
+    let mut rejected = Vec::new();
+    for txn in txns {
+        let tx_id = txn.tx_id();
+        match engine.apply(txn) {
+            Ok(()) => applied += 1,
+            Err(e) => rejected.push(json!({ "tx": tx_id, "reason": e.to_string() })),
+        }
+    }
+    (200, json!({ "applied": applied, "rejected": rejected }))
+}
+
+fn error(status: u16, message: String) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_route_post_transactions() {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        let csv = b"type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,20\n";
+        let json = br#"[{"type":"deposit","client":2,"tx":3,"amount":"1.5"},{"type":"dispute","client":1,"tx":1,"amount":null}]"#;
+
+        /*
+         * When
+         */
+        let from_csv = route(&mut engine, "POST", "/transactions", Some("text/csv"), csv);
+        let from_json = route(&mut engine, "POST", "/transactions", Some("application/json; charset=utf-8"), json);
+        let malformed = route(&mut engine, "POST", "/transactions", None, b"type,client,tx,amount\ndeposit,x,4,1\n");
+        let unsupported = route(&mut engine, "POST", "/transactions", Some("text/plain"), b"");
+
+        /*
+         * Then
+         */
+        assert_eq!(from_csv, (200, json!({ "applied": 1
+                                         , "rejected": [{ "tx": 2, "reason": "insufficient available funds for transaction 2" }]
+                                         })));
+        assert_eq!(from_json, (200, json!({ "applied": 2, "rejected": [] })));
+        assert_eq!(malformed.0, 400);
+        assert_eq!(unsupported.0, 415);
+        assert_eq!(engine.account(1).unwrap().held(), dec!(10));
+        assert_eq!(engine.account(2).unwrap().available(), dec!(1.5));
+        assert!(engine.account(4).is_none());
+    }
+
+    #[test]
+    fn test_route_get_accounts() {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        engine.apply(Transaction::dispute(2, 1)).ok();
+        engine.apply(Transaction::deposit(1, 1, dec!(5)).unwrap()).unwrap();
+
+        /*
+         * When
+         */
+        let all = route(&mut engine, "GET", "/accounts", None, b"");
+        let one = route(&mut engine, "GET", "/accounts/1", None, b"");
+        let unknown = route(&mut engine, "GET", "/accounts/3", None, b"");
+        let not_allowed = route(&mut engine, "DELETE", "/accounts/1", None, b"");
+
+        /*
+         * Then
+         */
+        assert_eq!(all.1.as_array().unwrap().iter().map(|a| a["client"].clone()).collect::<Vec<_>>(), vec![json!(1), json!(2)]);
+        assert_eq!(one, (200, json!({ "client": 1, "available": "5", "held": "0.0", "total": "5", "locked": false })));
+        assert_eq!(unknown.0, 404);
+        assert_eq!(not_allowed.0, 405);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::tx::{Account, TxError};
+use tracing::info_span;
+use postgres::{Client, NoTls};
+
+/// Upserts the accounts into a Postgres `table`, keyed by client id and
+/// currency, where the accounts in no currency have an empty one,
+/// creating the table if it does not exist. All accounts are written
+/// in one transaction.
+pub fn upsert_accounts(url: &str, table: &str, accounts: &[Account]) -> Result<(), TxError> {
+    let _span = info_span!("upsert_accounts").entered();
+    let table = quote_ident(table);
+    let mut client = Client::connect(url, NoTls)?;
+    let mut txn = client.transaction()?;
+    txn.batch_execute(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            client    INTEGER NOT NULL,
+            currency  TEXT    NOT NULL DEFAULT '',
+            available NUMERIC NOT NULL,
+            held      NUMERIC NOT NULL,
+            total     NUMERIC NOT NULL,
+            locked    BOOLEAN NOT NULL,
+            PRIMARY KEY (client, currency)
+        )", table))?;
+    let upsert = txn.prepare(&format!(
+        "INSERT INTO {} (client, currency, available, held, total, locked) VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (client, currency) DO UPDATE
+         SET available = EXCLUDED.available, held = EXCLUDED.held, total = EXCLUDED.total, locked = EXCLUDED.locked",
+        table))?;
+    for account in accounts {
+        txn.execute(&upsert, &[ &i32::from(account.client_id())
+                              , &account.currency().unwrap_or_default()
+                              , &account.available()
+                              , &account.held()
+                              , &account.total()
+                              , &account.is_locked()
+                              ])?;
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+/// Quotes a table name, which may be qualified by a schema, e.g.
+/// `reporting.accounts`, as a Postgres identifier.
+fn quote_ident(table: &str) -> String {
+    table.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quote_ident() {
+        assert_eq!(quote_ident("accounts"), r#""accounts""#);
+        assert_eq!(quote_ident("reporting.accounts"), r#""reporting"."accounts""#);
+        assert_eq!(quote_ident(r#"a"; DROP TABLE x; --"#), r#""a""; DROP TABLE x; --""#);
+    }
+}
@@ -0,0 +1,181 @@
+use futures::StreamExt;
+use object_store::local::LocalFileSystem;
+use object_store::{ObjectStore, ObjectStoreScheme};
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver};
+use url::Url;
+
+/// Number of chunks of an object that are downloaded ahead of the parser.
+const PREFETCH: usize = 16;
+
+/// The schemes of the URLs that are read with `ObjectReader`, whether or
+/// not the feature of their store is enabled.
+const SCHEMES: &[&str] = &["s3://", "s3a://", "gs://", "az://", "azure://", "abfs://", "abfss://", "adl://", "file://"];
+
+/// Returns `true` if the path is the URL of an object in a store, e.g.
+/// `s3://bucket/key`, `gs://bucket/key`, `az://container/blob` or
+/// `file:///path`, which is read with `ObjectReader` instead of opened
+/// as a file.
+pub fn is_object_url(path: &std::path::Path) -> bool {
+    path.to_str().is_some_and(|path| SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+/// Reads an object of a store as it is downloaded, so that a transaction
+/// log in a bucket is parsed without being downloaded first. The store is
+/// picked by the scheme of the URL, and the stores other than `file://`
+/// need the feature of their own: `s3`, `gcs` or `azure`. Their region and
+/// credentials are taken from the environment, that is the `AWS_*`,
+/// `GOOGLE_*` or `AZURE_*` variables, e.g. `AWS_REGION`,
+/// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, or from the instance
+/// metadata if they are not set.
+pub struct ObjectReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk:  io::Cursor<Vec<u8>>,
+}
+
+impl ObjectReader {
+    /// Starts the download of the object at the URL on a thread of its
+    /// own, and waits for its response, so that e.g. a missing object
+    /// fails here.
+    pub fn open(url: &str) -> io::Result<ObjectReader> {
+        let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidInput, format!("`{}` {}", url, reason));
+        let parsed = Url::parse(url).map_err(|e| invalid(format!("is not a valid URL: {}", e)))?;
+        let (scheme, key) = ObjectStoreScheme::parse(&parsed).map_err(|e| invalid(e.to_string()))?;
+        let store: Box<dyn ObjectStore> = match scheme {
+            ObjectStoreScheme::Local => Box::new(LocalFileSystem::new()),
+            #[cfg(feature = "s3")]
+            ObjectStoreScheme::AmazonS3 => Box::new(object_store::aws::AmazonS3Builder::from_env().with_url(url).build()?),
+            #[cfg(feature = "gcs")]
+            ObjectStoreScheme::GoogleCloudStorage => Box::new(object_store::gcp::GoogleCloudStorageBuilder::from_env().with_url(url).build()?),
+            #[cfg(feature = "azure")]
+            ObjectStoreScheme::MicrosoftAzure => Box::new(object_store::azure::MicrosoftAzureBuilder::from_env().with_url(url).build()?),
+            #[cfg(not(feature = "s3"))]
+            ObjectStoreScheme::AmazonS3 => return Err(invalid("needs the `s3` feature".to_string())),
+            #[cfg(not(feature = "gcs"))]
+            ObjectStoreScheme::GoogleCloudStorage => return Err(invalid("needs the `gcs` feature".to_string())),
+            #[cfg(not(feature = "azure"))]
+            ObjectStoreScheme::MicrosoftAzure => return Err(invalid("needs the `azure` feature".to_string())),
+            _ => return Err(invalid("is not the URL of a supported store".to_string())),
+        };
+        let (opened, response) = mpsc::channel();
+        let (sender, chunks) = mpsc::sync_channel(PREFETCH);
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => { let _ = opened.send(Err(e)); return; },
+            };
+            runtime.block_on(async {
+                let mut body = match store.get(&key).await {
+                    Ok(object) => { let _ = opened.send(Ok(())); object.into_stream() },
+                    Err(e) => { let _ = opened.send(Err(e.into())); return; },
+                };
+                while let Some(chunk) = body.next().await {
+                    let chunk = chunk.map(Vec::from).map_err(io::Error::from);
+                    let failed = chunk.is_err();
+                    if sender.send(chunk).is_err() || failed {
+                        break;
+                    }
+                }
+            })
+        });
+        response.recv().map_err(io::Error::other)??;
+        Ok(ObjectReader { chunks, chunk: io::Cursor::default() })
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.chunk.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.recv() {
+                Ok(chunk) => self.chunk = io::Cursor::new(chunk?),
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::Options;
+    use rust_decimal_macros::dec;
+
+    const BODY: &str = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,5\nwithdrawal,1,3,4\n";
+
+    #[test]
+    fn test_object_reader_file() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("txns 2021.csv"), BODY)?;
+        let url = format!("file://{}/txns%202021.csv", dir.path().display());
+
+        /*
+         * When
+         */
+        let missing = ObjectReader::open(&format!("file://{}/missing.csv", dir.path().display())).err().map(|e| e.kind());
+        let (accounts, _) = futures::executor::block_on(crate::tx::process_paths(&[url.into()], &Options::default()))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(missing, Some(io::ErrorKind::NotFound));
+        assert_eq!(accounts.iter().map(|account| (account.client_id(), account.total())).collect::<Vec<_>>(),
+                   vec![(1, dec!(6)), (2, dec!(5))]);
+        assert!(is_object_url("gs://logs/txns.csv".as_ref()) && !is_object_url("logs/txns.csv".as_ref()));
+        Ok(())
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_object_reader_s3() -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        /*
+         * Given
+         */
+        let server = std::net::TcpListener::bind("127.0.0.1:0")?;
+        std::env::set_var("AWS_ENDPOINT", format!("http://{}", server.local_addr()?));
+        std::env::set_var("AWS_ALLOW_HTTP", "true");
+        std::env::set_var("AWS_REGION", "eu-north-1");
+        std::env::set_var("AWS_ACCESS_KEY_ID", "key");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "secret");
+        std::thread::spawn(move || -> io::Result<()> {
+            // Serves the body at /logs/2021/txns.csv, and nothing else
+            for stream in server.incoming() {
+                let mut stream = stream?;
+                let mut request = String::new();
+                while !request.ends_with("\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte)?;
+                    request.push(byte[0] as char);
+                }
+                let response = match request.starts_with("GET /logs/2021/txns.csv ") {
+                    true => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"1\"\r\nLast-Modified: Fri, 01 Jan 2021 00:00:00 GMT\r\n\r\n{}", BODY.len(), BODY),
+                    false => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes())?;
+            }
+            Ok(())
+        });
+
+        /*
+         * When
+         */
+        let missing = ObjectReader::open("s3://logs/2021/missing.csv").err().map(|e| e.kind());
+        let (accounts, _) = futures::executor::block_on(crate::tx::process_paths(&["s3://logs/2021/txns.csv".into()], &Options::default()))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(missing, Some(io::ErrorKind::NotFound));
+        assert_eq!(accounts.iter().map(|account| (account.client_id(), account.total())).collect::<Vec<_>>(),
+                   vec![(1, dec!(6)), (2, dec!(5))]);
+        Ok(())
+    }
+}
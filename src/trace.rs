@@ -0,0 +1,272 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A `tracing` subscriber that logs how long every span of the crate took
+/// once it is closed, e.g. `send done. Elapsed: 1.20ms`, with the `log`
+/// crate. The spans time the stages of a run, so that an application can
+/// collect them with a subscriber of its own instead. If it is profiling,
+/// the timings are kept for a `Profile` as well.
+#[derive(Debug, Default)]
+pub struct LogTimings {
+    next:    AtomicU64,
+    spans:   Mutex<HashMap<u64, Timing>>,
+    profile: Option<Profiling>,
+}
+
+/// The timings kept by `LogTimings::profiling`.
+#[derive(Debug)]
+struct Profiling {
+    started: Instant,
+    stages:  Mutex<Vec<Stage>>,
+}
+
+/// A report of a run, for tracking its performance across versions:
+/// its wall time, the rows it processed, and the memory it took at most.
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub elapsed_ms:        f64,
+    pub rows:              u64,
+    pub rows_per_sec:      f64,
+    /// The peak resident memory of the process, where it is known.
+    pub peak_memory_bytes: Option<u64>,
+    pub stages:            Vec<Stage>,
+}
+
+/// The timings of all spans of the same name, e.g. of `to_shard` for
+/// all shards, in the order the first of them closed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stage {
+    pub name:     &'static str,
+    pub spans:    u64,
+    /// The time the spans took, added up.
+    pub total_ms: f64,
+    /// The time the longest of the spans took.
+    pub max_ms:   f64,
+}
+
+/// A span that is not closed yet.
+#[derive(Debug)]
+struct Timing {
+    metadata: &'static Metadata<'static>,
+    fields:   String,
+    started:  Instant,
+    refs:     usize,
+}
+
+impl Timing {
+    /// Logs how long the span took, as if it was logged where the span
+    /// was created.
+    fn log(&self) {
+        let metadata = self.metadata;
+        log::logger().log(&log::Record::builder()
+            .args(format_args!("{}{} done. Elapsed: {:.2?}", metadata.name(), self.fields, self.started.elapsed()))
+            .level(log::Level::Info)
+            .target(metadata.target())
+            .module_path(metadata.module_path())
+            .file(metadata.file())
+            .line(metadata.line())
+            .build());
+    }
+}
+
+impl LogTimings {
+    /// Returns a subscriber that keeps the timings of all spans for
+    /// `profile`, whether they are logged or not.
+    pub fn profiling() -> LogTimings {
+        let profile = Profiling { started: Instant::now(), stages: Mutex::new(vec![]) };
+        LogTimings { profile: Some(profile), ..LogTimings::default() }
+    }
+
+    /// Returns the `Profile` of the run so far, given the rows it
+    /// processed, or `None` if it is not profiling.
+    pub fn profile(&self, rows: u64) -> Option<Profile> {
+        let profile = self.profile.as_ref()?;
+        let elapsed = profile.started.elapsed();
+        Some(Profile { elapsed_ms:        millis(elapsed)
+                     , rows
+                     , rows_per_sec:      rows as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+                     , peak_memory_bytes: peak_memory()
+                     , stages:            profile.stages.lock().unwrap_or_else(PoisonError::into_inner).clone()
+                     })
+    }
+
+    /// Keeps the time a span took in the stage of its name.
+    fn keep(&self, timing: &Timing) {
+        let Some(profile) = &self.profile else { return };
+        let elapsed = millis(timing.started.elapsed());
+        let mut stages = profile.stages.lock().unwrap_or_else(PoisonError::into_inner);
+        let name = timing.metadata.name();
+        match stages.iter_mut().find(|stage| stage.name == name) {
+            Some(stage) => {
+                stage.spans += 1;
+                stage.total_ms += elapsed;
+                stage.max_ms = stage.max_ms.max(elapsed);
+            },
+            None => stages.push(Stage { name, spans: 1, total_ms: elapsed, max_ms: elapsed }),
+        }
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Timing>> {
+        self.spans.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Subscriber for LogTimings {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span() && (self.profile.is_some() || log::max_level() >= log::LevelFilter::Info)
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        self.spans().insert(id, Timing { metadata: span.metadata(), fields: fields.0, started: Instant::now(), refs: 1 });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(timing) = self.spans().get_mut(&span.into_u64()) {
+            let mut fields = Fields(std::mem::take(&mut timing.fields));
+            values.record(&mut fields);
+            timing.fields = fields.0;
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(timing) = self.spans().get_mut(&span.into_u64()) {
+            timing.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closed = {
+            let mut spans = self.spans();
+            match spans.get_mut(&span.into_u64()) {
+                Some(timing) if timing.refs > 1 => { timing.refs -= 1; None },
+                Some(_) => spans.remove(&span.into_u64()),
+                None => None,
+            }
+        };
+        match closed {
+            Some(timing) => {
+                self.keep(&timing);
+                if log::max_level() >= log::LevelFilter::Info {
+                    timing.log();
+                }
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Returns the peak resident memory of the process, as `VmHWM` in
+/// `/proc/self/status` on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// The fields of a span, written like `{shard=0}`.
+#[derive(Default)]
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let separator = if self.0.is_empty() { "{" } else { ", " };
+        if self.0.ends_with('}') {
+            self.0.pop();
+        }
+        let _ = write!(self.0, "{}{}={:?}}}", separator, field.name(), value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::info_span;
+
+    #[test]
+    fn test_log_timings() {
+        /*
+         * Given
+         */
+        let timings = Arc::new(LogTimings::default());
+        log::set_max_level(log::LevelFilter::Info);
+
+        /*
+         * When
+         */
+        let open = tracing::subscriber::with_default(Arc::clone(&timings), || {
+            let span = info_span!("to_shard", shard = 1, path = "a.csv");
+            let clone = span.clone();
+            drop(span);
+            let open = timings.spans().values().map(|timing| timing.fields.clone()).collect::<Vec<_>>();
+            drop(clone);
+            open
+        });
+
+        /*
+         * Then
+         */
+        assert_eq!(open, vec![String::from("{shard=1, path=\"a.csv\"}")]);
+        assert!(timings.spans().is_empty());
+        assert!(timings.profile(0).is_none());
+    }
+
+    #[test]
+    fn test_profile() {
+        /*
+         * Given
+         */
+        let timings = Arc::new(LogTimings::profiling());
+
+        /*
+         * When
+         */
+        tracing::subscriber::with_default(Arc::clone(&timings), || {
+            info_span!("settle").in_scope(|| {
+                for shard in 0..3 {
+                    let _span = info_span!("to_shard", shard).entered();
+                    std::thread::sleep(Duration::from_millis(shard));
+                }
+            });
+        });
+        let profile = timings.profile(1000).unwrap();
+
+        /*
+         * Then
+         */
+        let stages = profile.stages.iter().map(|stage| (stage.name, stage.spans)).collect::<Vec<_>>();
+        assert_eq!(stages, vec![("to_shard", 3), ("settle", 1)]);
+        assert!(profile.stages[0].max_ms >= 2.0 && profile.stages[0].total_ms >= 3.0);
+        assert!(profile.stages[1].total_ms >= profile.stages[0].total_ms);
+        assert_eq!(profile.rows, 1000);
+        assert!(profile.rows_per_sec > 0.0);
+        #[cfg(target_os = "linux")]
+        assert!(profile.peak_memory_bytes.unwrap() > 0);
+    }
+}
@@ -1,18 +1,47 @@
 use crate::tx::TransactionKind::*;
-use anyhow::Context;
 use csv::{ReaderBuilder, Trim, WriterBuilder};
-use log::{debug, info};
-use rayon::prelude::*;
-use rand::{thread_rng, Rng};
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::channel::oneshot;
+use futures::executor::ThreadPool;
+use futures::future::{self, RemoteHandle};
+use futures::sink::SinkExt;
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
+use futures::task::{Spawn, SpawnExt};
+use log::{debug, info, warn};
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::{self, Error, ErrorKind::{InvalidInput}};
-use rand::seq::SliceRandom;
+use thiserror::Error;
+use tracing::{info_span, Instrument};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use crate::tx::clients::ClientFilter;
+use crate::tx::progress::{Counted, Progress};
+use crate::tx::quarantine::Quarantine;
+use crate::tx::threshold::{Limit, Threshold};
+use crate::tx::spill::{Handled, SpillFile};
+use crate::tx::owners::Owners;
+use rayon::prelude::*;
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
-struct Transaction {
+pub mod checkpoint;
+pub mod clients;
+pub mod generate;
+pub mod invariants;
+pub mod progress;
+pub mod quarantine;
+pub mod pipe;
+pub mod remote;
+pub mod snapshot;
+pub mod storage;
+pub mod threshold;
+mod owners;
+mod spill;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Transaction {
     #[serde(rename = "type")]
     kind:       TransactionKind,
     #[serde(rename = "client")]
@@ -20,9 +49,17 @@ struct Transaction {
     #[serde(rename = "tx")]
     tx_id:      u32,
     amount:     Option<Decimal>,
+    /// The client credited by a transfer.
+    #[serde(rename = "to", default)]
+    to_client:  Option<u16>,
+    /// The currency of the amount, or of the transaction referred to.
+    #[serde(default)]
+    currency:   Option<String>,
 }
 
 impl Transaction {
+    /// Returns a `Transaction` with an `amount` given as an integer
+    /// number of ten-thousandths, e.g. `10000` is `1.0000`.
     pub fn new( kind: TransactionKind
               , client_id: u16
               , tx_id: u32
@@ -32,25 +69,160 @@ impl Transaction {
             kind,
             client_id,
             tx_id,
-            amount: a.and_then(|x| Some(Decimal::new(x, 4)))
+            amount: a.map(|x| Decimal::new(x, 4)),
+            to_client: None,
+            currency: None,
+        }
+    }
+
+    /// Returns a deposit to the account of a client. The `amount` must
+    /// not be negative and must have at most four digits after decimal.
+    pub fn deposit(client_id: u16, tx_id: u32, amount: Decimal) -> Result<Transaction, TxError> {
+        Transaction::with_amount(Deposit, client_id, tx_id, amount)
+    }
+
+    /// Returns a withdrawal from the account of a client. The `amount` must
+    /// not be negative and must have at most four digits after decimal.
+    pub fn withdrawal(client_id: u16, tx_id: u32, amount: Decimal) -> Result<Transaction, TxError> {
+        Transaction::with_amount(Withdrawal, client_id, tx_id, amount)
+    }
+
+    /// Returns a dispute of the transaction `tx_id`.
+    pub fn dispute(client_id: u16, tx_id: u32) -> Transaction {
+        Transaction { kind: Dispute, client_id, tx_id, amount: None, to_client: None, currency: None }
+    }
+
+    /// Returns a resolve of the disputed transaction `tx_id`.
+    pub fn resolve(client_id: u16, tx_id: u32) -> Transaction {
+        Transaction { kind: Resolve, client_id, tx_id, amount: None, to_client: None, currency: None }
+    }
+
+    /// Returns a chargeback of the disputed transaction `tx_id`.
+    pub fn chargeback(client_id: u16, tx_id: u32) -> Transaction {
+        Transaction { kind: Chargeback, client_id, tx_id, amount: None, to_client: None, currency: None }
+    }
+
+    /// Returns a transfer of `amount` from the account of `client_id` to
+    /// the account of `to_client`. The `amount` must not be negative and
+    /// must have at most four digits after decimal, and the clients must
+    /// differ.
+    pub fn transfer(client_id: u16, to_client: u16, tx_id: u32, amount: Decimal) -> Result<Transaction, TxError> {
+        (client_id != to_client).then_some(()).ok_or(TxError::InvalidTransfer(tx_id))?;
+        Transaction::with_amount(Transfer, client_id, tx_id, amount)
+            .map(|txn| Transaction { to_client: Some(to_client), ..txn })
+    }
+
+    /// Returns an unlock of the account of a client, e.g. after a
+    /// manual review of a chargeback.
+    pub fn unlock(client_id: u16, tx_id: u32) -> Transaction {
+        Transaction { kind: Unlock, client_id, tx_id, amount: None, to_client: None, currency: None }
+    }
+
+    /// Returns an adjustment of the funds of a client by an admin. The
+    /// `amount` is a credit if positive and a debit if negative, and must
+    /// have at most four digits after decimal.
+    pub fn adjustment(client_id: u16, tx_id: u32, amount: Decimal) -> Result<Transaction, TxError> {
+        (amount.scale() <= 4).then_some(()).ok_or(TxError::InvalidScale(tx_id))?;
+        Ok(Transaction { kind: Adjustment, client_id, tx_id, amount: Some(amount), to_client: None, currency: None })
+    }
+
+    fn with_amount( kind: TransactionKind
+                  , client_id: u16
+                  , tx_id: u32
+                  , amount: Decimal
+                  ) -> Result<Transaction, TxError> {
+        amount.is_sign_positive().then_some(()).ok_or(TxError::NegativeAmount(tx_id))?;
+        (amount.scale() <= 4).then_some(()).ok_or(TxError::InvalidScale(tx_id))?;
+        Ok(Transaction { kind, client_id, tx_id, amount: Some(amount), to_client: None, currency: None })
+    }
+
+    pub fn kind(&self) -> TransactionKind {
+        self.kind
+    }
+
+    /// Returns `true` for a dispute, resolve or chargeback, which
+    /// refer to an earlier transaction by its id.
+    pub fn refers(&self) -> bool {
+        matches!(self.kind, Dispute | Resolve | Chargeback)
+    }
+
+    /// Returns `true` for a deposit or withdrawal, which a later
+    /// dispute can refer to by its id.
+    pub fn disputable(&self) -> bool {
+        matches!(self.kind, Deposit | Withdrawal)
+    }
+
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    pub fn tx_id(&self) -> u32 {
+        self.tx_id
+    }
+
+    pub fn amount(&self) -> Option<Decimal> {
+        self.amount
+    }
+
+    /// Returns the client credited by a transfer.
+    pub fn to_client(&self) -> Option<u16> {
+        self.to_client
+    }
+
+    /// Returns the currency of the transaction, if any.
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    /// Returns the transaction in a `currency`, instead of no currency.
+    pub fn with_currency(self, currency: &str) -> Transaction {
+        Transaction { currency: Some(currency.to_string()), ..self }
+    }
+
+    /// Returns the transaction with its amount read in `unit`. With
+    /// `AmountUnit::Minor` the amount is a number of ten-thousandths,
+    /// converted like `Transaction::new`, e.g. `10000` is `1.0000`. An
+    /// amount that is not a whole number was rejected when it was read.
+    pub fn in_unit(self, unit: AmountUnit) -> Transaction {
+        match unit {
+            AmountUnit::Decimal => self,
+            AmountUnit::Minor => {
+                let amount = self.amount.map(|a| Decimal::try_from_i128_with_scale(a.mantissa(), a.scale() + 4)
+                    .unwrap_or(a / dec!(10000)));
+                Transaction { amount, ..self }
+            },
         }
     }
+
+    /// Returns the client credited by a transfer, or
+    /// `TxError::InvalidTransfer` if it is missing or the same client.
+    pub(crate) fn credited(&self) -> Result<u16, TxError> {
+        self.to_client
+            .filter(|&to_client| to_client != self.client_id)
+            .ok_or(TxError::InvalidTransfer(self.tx_id))
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all(deserialize = "lowercase", serialize = "lowercase"))]
-enum TransactionKind {
+pub enum TransactionKind {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Unlock,
+    Adjustment,
+    Transfer,
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Account {
     #[serde(rename = "client")]
     client_id:  u16,
+    /// The currency of the funds, if the transactions have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    currency:   Option<String>,
     available:  Decimal,
     held:       Decimal,
     total:      Decimal,
@@ -58,351 +230,4257 @@ pub struct Account {
 }
 
 impl Account {
-    fn new(client_id: u16) -> Account {
+    pub(crate) fn new(client_id: u16) -> Account {
+        Account::new_in(client_id, None)
+    }
+
+    /// Returns an empty account of a client in a `currency`.
+    pub(crate) fn new_in(client_id: u16, currency: Option<&str>) -> Account {
         Account {
             client_id,
+            currency:  currency.map(String::from),
             available: dec!(0.0),
             held:      dec!(0.0),
             total:     dec!(0.0),
             locked:    false
         }
     }
+
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    pub fn available(&self) -> Decimal {
+        self.available
+    }
+
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    /// Returns `true` if the account has been frozen by a chargeback.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Adds the deltas to the available, held and total funds. If any of
+    /// them would overflow, the account is left unchanged and
+    /// `TxError::Overflow` is returned.
+    fn adjust(&mut self, tx_id: u32, available: Decimal, held: Decimal, total: Decimal) -> Result<(), TxError> {
+        // A zero delta leaves the funds as they are, including their scale
+        let add = |funds: Decimal, delta: Decimal| if delta.is_zero() { Some(funds) } else { funds.checked_add(delta) };
+        match ( add(self.available, available)
+              , add(self.held, held)
+              , add(self.total, total)
+              ) {
+            (Some(available), Some(held), Some(total)) => {
+                self.available = available;
+                self.held      = held;
+                self.total     = total;
+                Ok(())
+            },
+            _ => Err(TxError::Overflow(tx_id)),
+        }
+    }
+
+    /// Returns `true` if the total funds equal the sum of the
+    /// available and the held funds.
+    pub fn balance_check(&self) -> bool {
+        self.total == self.available + self.held
+    }
+
+    /// Returns the account with the funds of both accounts summed, e.g.
+    /// of the same client in the outputs of two shards or runs. It is
+    /// locked if either is, since a chargeback in one of them locks the
+    /// client. Returns `TxError::MergeMismatch` if the accounts are of
+    /// other clients or currencies, and `TxError::MergeOverflow` if the
+    /// funds would overflow.
+    pub fn merge(&self, other: &Account) -> Result<Account, TxError> {
+        if (self.client_id, &self.currency) != (other.client_id, &other.currency) {
+            return Err(TxError::MergeMismatch(self.client_id, other.client_id));
+        }
+        let mut merged = self.clone();
+        merged.adjust(0, other.available, other.held, other.total)
+            .map_err(|_| TxError::MergeOverflow(self.client_id))?;
+        merged.locked |= other.locked;
+        Ok(merged)
+    }
 }
 
-/// Reads the transactions from a file and writes the serialized results to
-/// `std::io::stdout()`.
-pub async fn read(path: &std::path::PathBuf) -> Result<(), anyhow::Error> {
-    let stdout = io::stdout();
-    let mut lock = stdout.lock();
-    read_with(&mut lock, path).await
+/// Serializes a `Decimal` with exactly four digits after decimal,
+/// e.g. `2` as `2.0000`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fixed(pub Decimal);
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.4}", self.0)
+    }
 }
 
-/// Reads the transactions from a file and writes the serialized results to
-/// a given `std::io::Write` writer.
-pub async fn read_with(writer: &mut impl io::Write, path: &std::path::PathBuf) -> Result<(), anyhow::Error> {
-    let now = std::time::Instant::now();
-    let accounts = accounts_from_path(path).await?;
-    info!("accounts_from_path done. Elapsed: {:.2?}", now.elapsed());
-
-    let now = std::time::Instant::now();
-    print_accounts_with(writer, &accounts).await;
-    info!("print_accounts_with done. Elapsed: {:.2?}", now.elapsed());
-    Ok(())
+impl Serialize for Fixed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
-/// Reads the transactions from a file and returns `Vec<Account>` that
-/// contains a list of parsed accounts.
-pub async fn accounts_from_path(path: &std::path::PathBuf) -> Result<Vec<Account>, anyhow::Error> {
-    let now = std::time::Instant::now();
-    let txns = read_txns(path).await
-        .with_context(|| format!("Could not read transactions from file `{:?}`", path))?;
-    info!("read_txns done. Elapsed: {:.2?}", now.elapsed());
+/// Serializes an `Account` like its derived `Serialize`, but with a
+/// `currency` column if `currency` is set, so that every row has the
+/// same columns, and with the funds as `Fixed` if `fixed` is set.
+struct AccountRow<'a> {
+    account:  &'a Account,
+    currency: bool,
+    fixed:    bool,
+}
+
+impl Serialize for AccountRow<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let funds = |state: &mut S::SerializeStruct, key, funds: Decimal| match self.fixed {
+            true  => state.serialize_field(key, &Fixed(funds)),
+            false => state.serialize_field(key, &funds),
+        };
+        let mut state = serializer.serialize_struct("Account", 5 + self.currency as usize)?;
+        state.serialize_field("client", &self.account.client_id)?;
+        if self.currency {
+            state.serialize_field("currency", &self.account.currency)?;
+        }
+        funds(&mut state, "available", self.account.available)?;
+        funds(&mut state, "held", self.account.held)?;
+        funds(&mut state, "total", self.account.total)?;
+        state.serialize_field("locked", &self.account.locked)?;
+        state.end()
+    }
+}
+
+/// The reasons a `Transaction` is rejected, or the transactions
+/// could not be read at all.
+#[derive(Debug, Error)]
+pub enum TxError {
+    #[error("account of client {0} is locked")]
+    AccountLocked(u16),
+    #[error("insufficient available funds for transaction {0}")]
+    InsufficientFunds(u32),
+    #[error("negative amount in transaction {0}")]
+    NegativeAmount(u32),
+    #[error("more than four digits after decimal in transaction {0}")]
+    InvalidScale(u32),
+    #[error("missing amount in transaction {0}")]
+    MissingAmount(u32),
+    #[error("transaction {0} not found")]
+    UnknownTx(u32),
+    #[error("transaction {0} is already under dispute")]
+    AlreadyDisputed(u32),
+    #[error("transaction {0} is not under dispute")]
+    NotDisputed(u32),
+    #[error("funds would overflow in transaction {0}")]
+    Overflow(u32),
+    #[error("transaction {0} is a withdrawal, which can not be disputed")]
+    WithdrawalDispute(u32),
+    #[error("transaction {tx} of client {client} refers to a transaction of client {owner}")]
+    ClientMismatch { tx: u32, client: u16, owner: u16 },
+    #[error("transaction {0} reuses the id of an earlier transaction")]
+    DuplicateTx(u32),
+    #[error("transfer {0} has no other client to credit")]
+    InvalidTransfer(u32),
+    #[error("transaction {0} refers to a transaction in another currency")]
+    CurrencyMismatch(u32),
+    #[error("the account of client {0} can not be merged with an account of client {1}, or in another currency")]
+    MergeMismatch(u16, u16),
+    #[error("funds of client {0} would overflow in a merge")]
+    MergeOverflow(u16),
+    #[error("could not parse line {line}{}: {source}", .field.as_ref().map(|f| format!(", field `{}`", f)).unwrap_or_default())]
+    Parse { line: u64, field: Option<String>, source: csv::Error },
+    #[error("could not parse line {line}, field `amount`: {amount} is not a whole number of minor units")]
+    FractionalAmount { line: u64, amount: Decimal },
+    #[error("could not read transactions from file `{path:?}`")]
+    Read { path: std::path::PathBuf, source: csv::Error },
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "xlsx")]
+    #[error(transparent)]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[cfg(feature = "avro")]
+    #[error(transparent)]
+    Avro(#[from] apache_avro::Error),
+    #[error("no files match `{0}`")]
+    NoMatch(String),
+    #[error(transparent)]
+    Pattern(#[from] glob::PatternError),
+    #[cfg(feature = "kafka")]
+    #[error(transparent)]
+    Kafka(#[from] kafka::Error),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "postgres")]
+    #[error(transparent)]
+    Postgres(#[from] postgres::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Spawn(#[from] futures::task::SpawnError),
+    #[error("could not read or write the engine state: {0}")]
+    Snapshot(#[from] serde_json::Error),
+    #[error("{errors} of {rows} rows were malformed or rejected, more than the limit of {limit}")]
+    TooManyErrors { errors: u64, rows: u64, limit: Limit },
+    #[error("could not start the server: {0}")]
+    Server(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// A `Transaction` that was rejected, together with where it was read
+/// and the reason why: its line in the input file `path`, which is the
+/// `file`th of the files that were read, if it was read from files.
+#[derive(Debug)]
+pub struct Rejection {
+    pub file:   usize,
+    pub path:   Option<std::path::PathBuf>,
+    pub line:   u64,
+    pub txn:    Transaction,
+    pub reason: TxError,
+}
+
+#[derive(Serialize)]
+struct RejectionRecord<'a> {
+    line:   u64,
+    #[serde(rename = "type")]
+    kind:   TransactionKind,
+    client: u16,
+    tx:     u32,
+    amount: Option<Decimal>,
+    reason: &'a str,
+}
+
+impl TxError {
+    /// Returns a short name for the reason a `Transaction` was
+    /// rejected, used to group the rejections in `Stats`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TxError::AccountLocked(_)     => "account_locked",
+            TxError::InsufficientFunds(_) => "insufficient_funds",
+            TxError::NegativeAmount(_)    => "negative_amount",
+            TxError::InvalidScale(_)      => "invalid_scale",
+            TxError::MissingAmount(_)     => "missing_amount",
+            TxError::UnknownTx(_)         => "unknown_tx",
+            TxError::AlreadyDisputed(_)   => "already_disputed",
+            TxError::NotDisputed(_)       => "not_disputed",
+            TxError::Overflow(_)          => "overflow",
+            TxError::WithdrawalDispute(_) => "withdrawal_dispute",
+            TxError::ClientMismatch { .. } => "client_mismatch",
+            TxError::DuplicateTx(_)       => "duplicate_tx",
+            TxError::InvalidTransfer(_)   => "invalid_transfer",
+            TxError::CurrencyMismatch(_)  => "currency_mismatch",
+            _                             => "error",
+        }
+    }
+}
+
+/// Summary statistics of a run: the transactions read per kind, the
+/// rejected transactions per reason, and the resulting accounts.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub read:      u64,
+    pub kinds:     BTreeMap<TransactionKind, u64>,
+    pub rejected:  BTreeMap<&'static str, u64>,
+    pub accounts:  u64,
+    pub locked:    u64,
+    pub saturated: u64,
+    pub held:      Decimal,
+}
+
+impl Stats {
+    fn count_read(&mut self, txn: &Transaction) {
+        self.read += 1;
+        *self.kinds.entry(txn.kind).or_default() += 1;
+    }
+
+    fn count_rejected(&mut self, reason: &TxError) {
+        *self.rejected.entry(reason.reason()).or_default() += 1;
+    }
+
+    fn count_accounts<'a>(&mut self, accounts: impl IntoIterator<Item = &'a Account>) {
+        accounts.into_iter().for_each(|account| {
+            self.accounts += 1;
+            self.locked   += u64::from(account.locked);
+            self.held      = saturating_add(self.held, account.held);
+        });
+    }
+
+    fn count_ledgers<'a>(&mut self, ledgers: impl IntoIterator<Item = &'a Ledger>) {
+        ledgers.into_iter().for_each(|ledger| {
+            self.count_accounts(ledger.accounts());
+            self.saturated += u64::from(ledger.saturated);
+        });
+    }
+
+    fn merge(&mut self, other: Stats) {
+        self.read += other.read;
+        other.kinds.into_iter().for_each(|(kind, n)| *self.kinds.entry(kind).or_default() += n);
+        other.rejected.into_iter().for_each(|(reason, n)| *self.rejected.entry(reason).or_default() += n);
+        self.accounts  += other.accounts;
+        self.locked    += other.locked;
+        self.saturated += other.saturated;
+        self.held       = saturating_add(self.held, other.held);
+    }
+}
+
+/// Returns `a + b`, or `Decimal::MAX` or `Decimal::MIN` if it overflows.
+fn saturating_add(a: Decimal, b: Decimal) -> Decimal {
+    a.checked_add(b).unwrap_or(if b.is_sign_negative() { Decimal::MIN } else { Decimal::MAX })
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "transactions read: {}", self.read)?;
+        for (kind, n) in &self.kinds {
+            writeln!(f, "  {}: {}", format!("{:?}", kind).to_lowercase(), n)?;
+        }
+        writeln!(f, "rejected: {}", self.rejected.values().sum::<u64>())?;
+        for (reason, n) in &self.rejected {
+            writeln!(f, "  {}: {}", reason, n)?;
+        }
+        writeln!(f, "accounts: {}", self.accounts)?;
+        writeln!(f, "locked accounts: {}", self.locked)?;
+        writeln!(f, "saturated accounts: {}", self.saturated)?;
+        writeln!(f, "held funds: {}", self.held)
+    }
+}
+
+/// What a run reports besides the accounts: the rejected transactions,
+/// if `report` is set, and the summary `Stats`.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub rejections: Vec<Rejection>,
+    pub stats:      Stats,
+}
+
+impl Report {
+    /// Returns the report with the path of every rejection set to the
+    /// path of its file among `files`, the files that were read.
+    pub(crate) fn named(mut self, files: &[std::path::PathBuf]) -> Report {
+        self.rejections.iter_mut().for_each(|r| r.path = files.get(r.file).cloned());
+        self
+    }
+
+    /// Counts the `reason`, and if the `txn` is given, quarantines it
+    /// and keeps the `Rejection` as set in `options`.
+    fn reject(&mut self, line: Line, txn: Option<Transaction>, reason: TxError, options: &Options) {
+        self.stats.count_rejected(&reason);
+        if let Some(threshold) = &options.max_errors {
+            threshold.count_error();
+        }
+        let Some(txn) = txn else { return };
+        if let Some(quarantine) = &options.quarantine {
+            quarantine.rejected(&txn, &reason, options);
+        }
+        if options.report {
+            self.rejections.push(Rejection { file: line.file, path: None, line: line.line, txn, reason });
+        }
+    }
+}
 
-    let now = std::time::Instant::now();
-    let txns_map = txns_to_map(txns);
-    info!("txns_to_map done. Elapsed: {:.2?}", now.elapsed());
+/// Options for reading and applying the transactions.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Aborts on the first record that can not be parsed,
+    /// instead of skipping it.
+    pub strict: bool,
+    /// Collects the rejected transactions into `Report::rejections`.
+    pub report: bool,
+    /// How the amounts are rounded before they are applied.
+    pub rounding: Rounding,
+    /// Which transactions can be disputed.
+    pub dispute_policy: DisputePolicy,
+    /// Does not check that a dispute, resolve or chargeback comes from
+    /// the client of the transaction it refers to. It is then rejected
+    /// as an unknown transaction instead of a `TxError::ClientMismatch`.
+    pub allow_client_mismatch: bool,
+    /// What to do with a deposit or withdrawal that reuses the id of
+    /// an earlier one of the same client.
+    pub duplicates: DuplicatePolicy,
+    /// Checks the ids of the deposits and withdrawals for duplicates
+    /// across all clients, instead of per client.
+    pub global_unique_tx: bool,
+    /// Whether a locked account still accepts disputes, resolves
+    /// and chargebacks.
+    pub locked_policy: LockedPolicy,
+    /// Whether the amounts are read as decimals, or as integer
+    /// numbers of ten-thousandths.
+    pub amounts: AmountUnit,
+    /// How the columns of the csv input are named.
+    pub csv: CsvFormat,
+    /// Number of transactions buffered for the worker of a shard of clients
+    /// before reading waits for it, or `CAPACITY` if not set.
+    pub capacity: Option<usize>,
+    /// Number of threads that apply the transactions, and of shards the
+    /// clients are split into, or one per core if not set.
+    pub threads: Option<usize>,
+    /// Whether the input files are memory-mapped instead of read through
+    /// a buffer. A file must not be changed while it is mapped.
+    pub mmap: bool,
+    /// Whether the csv records are parsed by hand instead of with serde,
+    /// which is faster and gives the same transactions and errors.
+    pub fast_parse: bool,
+    /// Whether the csv files are parsed in chunks on all cores, which
+    /// reads a whole file into memory unless `mmap` is set as well.
+    pub parallel_parse: bool,
+    /// Number of bytes the transactions buffered for the workers, the
+    /// state of the handled transactions and the clients of the
+    /// transaction ids read may take in memory, split evenly between the
+    /// shards and the ids. It bounds `capacity`, and a shard spills the
+    /// state, and the reading the ids, to temporary files once they take
+    /// more than their share. All is kept in memory if not set.
+    pub max_memory: Option<usize>,
+    /// Counts the bytes of the csv input as they are read, so that the
+    /// progress of a run can be drawn while it runs.
+    pub progress: Option<Arc<Progress>>,
+    /// Writes the rows that are skipped as malformed or rejected to a
+    /// file, so that they can be fixed and submitted again.
+    pub quarantine: Option<Arc<Quarantine>>,
+    /// Aborts a run with `TxError::TooManyErrors` once more rows than
+    /// its limit were malformed or rejected.
+    pub max_errors: Option<Arc<Threshold>>,
+    /// Applies only the transactions of these clients, and skips the
+    /// others as they are read.
+    pub clients: Option<ClientFilter>,
+    /// Stops reading once this number of transactions were read.
+    pub limit: Option<u64>,
+    /// Stops reading after the first transaction with this id.
+    pub until_tx: Option<u32>,
+    /// Number of times the download of an input URL is resumed after
+    /// it failed part way.
+    pub retries: u32,
+}
+
+impl Options {
+    /// Returns `true` if the rejected transactions are needed, for
+    /// `report` or `quarantine`.
+    pub(crate) fn keeps_rejected(&self) -> bool {
+        self.report || self.quarantine.is_some()
+    }
+}
+
+/// Number of transactions buffered for the worker of a shard, unless
+/// `Options::capacity` is set.
+pub const CAPACITY: usize = 1024;
+
+/// The part of `Options::max_memory` left to the transactions buffered
+/// for the workers, e.g. a quarter. The rest is for the state of the
+/// handled transactions.
+const BUFFERED_SHARE: usize = 4;
+
+/// How the columns of the csv input are named, so that the slightly
+/// different formats of partners can be read without preprocessing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CsvFormat {
+    /// The files have no header row, so every row is a record.
+    pub no_headers: bool,
+    /// The names of the columns in order, in place of the header row,
+    /// e.g. `type,client,tx,amount`.
+    pub columns: Option<Vec<String>>,
+    /// Renames columns, from the name of a field to the name of the
+    /// column in the files, e.g. `client` to `client_id`.
+    pub map: Vec<(String, String)>,
+    /// The delimiter of the columns, or `,` unless the file has the
+    /// `.tsv` extension, in which case it is a tab.
+    pub delimiter: Option<u8>,
+    /// The amounts have a decimal comma, with dots that group the
+    /// thousands, e.g. `1.234,56`.
+    pub decimal_comma: bool,
+}
+
+impl CsvFormat {
+    /// The columns of a file without a header row, unless `columns`
+    /// is given.
+    const COLUMNS: &'static [&'static str] = &["type", "client", "tx", "amount", "to", "currency"];
+
+    /// Returns the delimiter of the columns of the file at `path`, if
+    /// the input is a file.
+    fn delimiter(&self, path: Option<&std::path::Path>) -> u8 {
+        match self.delimiter {
+            Some(delimiter) => delimiter,
+            None if path.is_some_and(is_tsv) => b'\t',
+            None => b',',
+        }
+    }
+
+    /// Returns the `record` with its amount in the form `Decimal` parses,
+    /// e.g. `1234.56` for `1.234,56` if `decimal_comma` is set.
+    fn normalize<'a>(&self, record: &'a csv::StringRecord, headers: Option<&csv::StringRecord>) -> Cow<'a, csv::StringRecord> {
+        if !self.decimal_comma {
+            return Cow::Borrowed(record);
+        }
+        let amount = headers.map_or(Some(3), |h| h.iter().position(|header| header == "amount"));
+        let mut normalized: csv::StringRecord =
+            record.iter()
+                .enumerate()
+                .map(|(i, field)| match Some(i) == amount {
+                    true  => Cow::Owned(field.replace('.', "").replace(',', ".")),
+                    false => Cow::Borrowed(field),
+                })
+                .collect();
+        normalized.set_position(record.position().cloned());
+        Cow::Owned(normalized)
+    }
+
+    /// Returns the headers to deserialize the records of `rdr` with:
+    /// the header row of the file, or `columns` in place of it, with
+    /// the names renamed by `map`.
+    pub(crate) fn headers<R: io::Read>(&self, rdr: &mut csv::Reader<R>) -> csv::Result<csv::StringRecord> {
+        let headers: Vec<String> = match (&self.columns, self.no_headers) {
+            (Some(columns), _) => columns.clone(),
+            (None, true) => CsvFormat::COLUMNS.iter().map(|c| c.to_string()).collect(),
+            (None, false) => rdr.headers()?.iter().map(String::from).collect(),
+        };
+        Ok(headers.iter()
+            .map(|header| self.map.iter()
+                .find(|(_, column)| column == header)
+                .map_or(header.as_str(), |(field, _)| field.as_str()))
+            .collect())
+    }
+}
+
+/// Which transactions can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisputePolicy {
+    /// Only deposits can be disputed, as in the spec.
+    DepositsOnly,
+    /// Withdrawals can be disputed as well, which holds the withdrawn
+    /// funds until the dispute is resolved or charged back.
+    #[default]
+    All,
+}
+
+impl DisputePolicy {
+    pub const NAMES: &'static [&'static str] = &["deposits-only", "all"];
+}
+
+impl std::str::FromStr for DisputePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DisputePolicy, String> {
+        match s {
+            "deposits-only" => Ok(DisputePolicy::DepositsOnly),
+            "all"           => Ok(DisputePolicy::All),
+            _               => Err(format!("unknown dispute policy `{}`", s)),
+        }
+    }
+}
+
+/// How the amounts of the transactions are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmountUnit {
+    /// Decimals, e.g. `1.5`.
+    #[default]
+    Decimal,
+    /// Integer numbers of ten-thousandths, e.g. `15000` for `1.5`, as
+    /// exported by systems that keep minor units.
+    Minor,
+}
+
+impl AmountUnit {
+    pub const NAMES: &'static [&'static str] = &["decimal", "minor"];
+}
+
+impl std::str::FromStr for AmountUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AmountUnit, String> {
+        match s {
+            "decimal" => Ok(AmountUnit::Decimal),
+            "minor"   => Ok(AmountUnit::Minor),
+            _         => Err(format!("unknown amount unit `{}`", s)),
+        }
+    }
+}
+
+/// Which transactions a locked account accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockedPolicy {
+    /// Deposits and withdrawals are rejected, while disputes, resolves
+    /// and chargebacks of earlier transactions still apply.
+    #[default]
+    AllowDisputes,
+    /// Disputes, resolves and chargebacks are rejected as well, until
+    /// the account is unlocked.
+    Frozen,
+}
+
+impl LockedPolicy {
+    pub const NAMES: &'static [&'static str] = &["allow-disputes", "frozen"];
+}
+
+impl std::str::FromStr for LockedPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LockedPolicy, String> {
+        match s {
+            "allow-disputes" => Ok(LockedPolicy::AllowDisputes),
+            "frozen"         => Ok(LockedPolicy::Frozen),
+            _                => Err(format!("unknown locked policy `{}`", s)),
+        }
+    }
+}
+
+/// What to do with a deposit or withdrawal that reuses a transaction id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Rejects it as a `TxError::DuplicateTx`.
+    #[default]
+    Reject,
+    /// Logs a warning and applies it anyway, so a later dispute refers
+    /// to the first transaction with the id.
+    Warn,
+}
+
+impl DuplicatePolicy {
+    pub const NAMES: &'static [&'static str] = &["reject", "warn"];
+}
+
+impl std::str::FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DuplicatePolicy, String> {
+        match s {
+            "reject" => Ok(DuplicatePolicy::Reject),
+            "warn"   => Ok(DuplicatePolicy::Warn),
+            _        => Err(format!("unknown duplicate policy `{}`", s)),
+        }
+    }
+}
+
+/// How the digits of an amount beyond the precision are rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Rounds a midpoint away from zero, e.g. `0.00005` to `0.0001`.
+    HalfUp,
+    /// Rounds a midpoint to the nearest even digit, e.g. `0.00005`
+    /// to `0.0000`, also known as banker's rounding.
+    #[default]
+    HalfEven,
+    /// Drops the extra digits, e.g. `0.00009` to `0.0000`.
+    Truncate,
+}
+
+impl RoundingMode {
+    pub const NAMES: &'static [&'static str] = &["half-up", "half-even", "truncate"];
+}
+
+impl std::str::FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RoundingMode, String> {
+        match s {
+            "half-up"   => Ok(RoundingMode::HalfUp),
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "truncate"  => Ok(RoundingMode::Truncate),
+            _           => Err(format!("unknown rounding mode `{}`", s)),
+        }
+    }
+}
+
+/// How the amounts of the transactions are rounded before they are
+/// applied. Defaults to half-even with four digits after decimal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    pub mode:      RoundingMode,
+    /// Number of digits after decimal.
+    pub precision: u32,
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        Rounding { mode: RoundingMode::HalfEven, precision: 4 }
+    }
+}
+
+impl Rounding {
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        let strategy = match self.mode {
+            RoundingMode::HalfUp   => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::Truncate => RoundingStrategy::ToZero,
+        };
+        amount.round_dp_with_strategy(self.precision, strategy)
+    }
+}
+
+/// Where a transaction was read: the index of its file among the files
+/// that are read as one log, and its line in that file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Line {
+    pub file: usize,
+    pub line: u64,
+}
+
+impl From<u64> for Line {
+    fn from(line: u64) -> Line {
+        Line { file: 0, line }
+    }
+}
+
+/// A parsed `Transaction` together with where it was read.
+pub(crate) type Parsed = Result<(Line, Transaction), TxError>;
+
+/// The ledgers of a shard of clients and their rejected transactions,
+/// as returned by a shard worker.
+type Settled = (Ledgers, Report);
+
+/// The ledgers of the clients, keyed by client id.
+type Ledgers = HashMap<u16, Ledger>;
+
+/// Work forwarded to the worker of the shard of a client.
+enum Job {
+    /// Opens the ledger of a client the first time it is seen, so that
+    /// the client has an account even if all its transactions are
+    /// rejected before they reach the worker.
+    Open(u16),
+    /// A `Transaction` together with its line in the input, and the
+    /// `Leg` of a client if it is a transfer with a client of another
+    /// shard.
+    Apply(Line, Transaction, Option<Leg>),
+}
+
+/// The side of a transfer that is applied to the account of a client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Debit,
+    Credit,
+}
+
+/// One side of a transfer, forwarded to the worker of the shard of a
+/// client. The workers of both shards `tell` each other whether their
+/// side can be applied, so the transfer is applied to both accounts or
+/// to neither.
+struct Leg {
+    side: Side,
+    tell: oneshot::Sender<bool>,
+    hear: oneshot::Receiver<bool>,
+}
+
+impl Leg {
+    /// Returns the debit and the credit leg of a transfer.
+    fn pair() -> (Leg, Leg) {
+        let (debit_tell, credit_hear) = oneshot::channel();
+        let (credit_tell, debit_hear) = oneshot::channel();
+        ( Leg { side: Side::Debit,  tell: debit_tell,  hear: debit_hear }
+        , Leg { side: Side::Credit, tell: credit_tell, hear: credit_hear }
+        )
+    }
+
+    /// Returns the client of a transfer this leg is applied to.
+    fn client(&self, txn: &Transaction) -> u16 {
+        match self.side {
+            Side::Debit => txn.client_id,
+            Side::Credit => txn.to_client.expect("a transfer leg has a credited client"),
+        }
+    }
+}
+
+/// Applies transactions one at a time to the accounts of all clients,
+/// using the same dispute and chargeback rules as `accounts_from_path`.
+/// The state can be saved and loaded with `tx::snapshot`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Engine {
+    ledgers:     Ledgers,
+    #[serde(skip)]
+    options:     Options,
+    #[serde(skip)]
+    stats:       Stats,
+    #[serde(skip)]
+    chargebacks: u64,
+    /// The clients of the transaction ids, built from the ledgers on the
+    /// first transaction applied with the options.
+    #[serde(skip)]
+    owners:      Option<Owners>,
+    /// Spills the handled transactions past `Options::max_memory`, like
+    /// the workers of a shard, built on the first transaction applied
+    /// with the options.
+    #[serde(skip)]
+    spill:       Option<Spill>,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    /// Returns an engine that starts from the given balances, e.g. as read
+    /// by `read_accounts_from_path`. Since the transactions behind them are
+    /// not known, they can not be disputed.
+    pub fn from_accounts(accounts: impl IntoIterator<Item = Account>) -> Engine {
+        let mut engine = Engine::default();
+        accounts.into_iter().for_each(|account| ledger(&mut engine.ledgers, account.client_id).keep(account));
+        engine
+    }
+
+    /// Returns the engine with the transactions applied with the rounding
+    /// and the dispute policy of `options` from now on. The options are
+    /// not part of the saved state.
+    pub fn with_options(self, options: Options) -> Engine {
+        Engine { options, owners: None, spill: None, ..self }
+    }
+
+    /// Applies a `Transaction` to the account of its client. An invalid
+    /// transaction leaves the account unchanged and returns an error.
+    pub fn apply(&mut self, txn: Transaction) -> Result<(), TxError> {
+        self.stats.count_read(&txn);
+        let kind = txn.kind;
+        let result = self.apply_txn(txn);
+        match &result {
+            Ok(()) if kind == Chargeback => self.chargebacks += 1,
+            Ok(()) => {},
+            Err(reason) => self.stats.count_rejected(reason),
+        }
+        result
+    }
+
+    fn apply_txn(&mut self, txn: Transaction) -> Result<(), TxError> {
+        let options = &self.options;
+        self.spill.get_or_insert_with(|| Spill::new(options)).check(&mut self.ledgers);
+        let client_id = txn.client_id;
+        self.ledgers.entry(client_id).or_insert_with(|| Ledger::new(client_id));
+        if !self.options.allow_client_mismatch || self.options.global_unique_tx {
+            let owners = match &mut self.owners {
+                Some(owners) => owners,
+                None => self.owners.insert(Owners::of(self.ledgers.values(), state_budget(&self.options))?),
+            };
+            owners.check(&txn, &self.options)??;
+        }
+        if txn.kind == Transfer {
+            return transfer(&mut self.ledgers, txn, &self.options).map_err(|(e, _)| e);
+        }
+        self.ledgers.get_mut(&client_id)
+            .expect("ledger was just inserted")
+            .apply(txn, &self.options)
+            .map_err(|(e, _)| e)
+    }
+
+    /// Returns the `Account` of a client, if the client has been seen.
+    pub fn account(&self, client_id: u16) -> Option<&Account> {
+        self.account_in(client_id, None)
+    }
+
+    /// Returns the `Account` of a client in a `currency`, if the client
+    /// has been seen, or has funds in the currency.
+    pub fn account_in(&self, client_id: u16, currency: Option<&str>) -> Option<&Account> {
+        let ledger = self.ledgers.get(&client_id)?;
+        match currency {
+            None => Some(&ledger.account),
+            Some(currency) => ledger.currencies.get(currency),
+        }
+    }
+
+    /// Returns `true` if a transaction of the client was rejected
+    /// because the funds of its account would overflow.
+    pub fn is_saturated(&self, client_id: u16) -> bool {
+        self.ledgers.get(&client_id).is_some_and(|ledger| ledger.saturated)
+    }
+
+    /// Returns the accounts of all clients seen so far.
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.ledgers.values().flat_map(Ledger::accounts)
+    }
+
+    /// Returns the `Stats` of the transactions applied with `apply` since
+    /// the engine was created or loaded, and of the accounts so far.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.count_ledgers(self.ledgers.values());
+        stats
+    }
+
+    /// Returns the number of chargebacks applied with `apply` since the
+    /// engine was created or loaded.
+    pub fn chargebacks(&self) -> u64 {
+        self.chargebacks
+    }
+
+    /// Consumes the engine and returns the accounts of all clients,
+    /// ordered by client id and currency.
+    pub fn into_accounts(self) -> Vec<Account> {
+        let mut accounts: Vec<Account> = self.ledgers.into_values().flat_map(Ledger::into_accounts).collect();
+        accounts.sort_by_key(Account::client_id);
+        accounts
+    }
+}
+
+/// Follows a csv file that is appended to, like `tail -f`, and applies
+/// the new rows to an `Engine` each time it is polled. Only complete
+/// lines are read, so a row that is still being written is picked up
+/// by a later poll.
+#[derive(Debug)]
+pub struct Tail {
+    path:    std::path::PathBuf,
+    offset:  u64,
+    line:    u64,
+    headers: Option<csv::StringRecord>,
+}
+
+impl Tail {
+    pub fn new(path: &std::path::Path) -> Tail {
+        Tail { path: path.to_path_buf(), offset: 0, line: 0, headers: None }
+    }
+
+    /// Reads the rows appended since the last poll and applies them to
+    /// the engine. Returns the number of rows that were read. Malformed
+    /// rows are skipped like by `process_reader`, or returned as an error
+    /// if `strict` is set. The rows before the error are not read again,
+    /// while the row of the error is read again by the next poll.
+    pub fn poll(&mut self, engine: &mut Engine, strict: bool) -> Result<usize, TxError> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let complete = match buf.iter().rposition(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok(0),
+        };
+        buf.truncate(complete);
+
+        let options = engine.options.clone();
+        let mut rdr = reader_builder(&options.csv, Some(&self.path))
+            .has_headers(self.headers.is_none() && !options.csv.no_headers)
+            .from_reader(buf.as_slice());
+        let headers = match &self.headers {
+            Some(headers) => headers.clone(),
+            None => options.csv.headers(&mut rdr).map_err(|e| parse_error(e, None))?,
+        };
+        let mut record = csv::StringRecord::new();
+        let mut rows = 0;
+        let mut read = rdr.position().byte() as usize;
+        let line = self.line;
+        while let Some(parsed) = read_parsed(&mut rdr, &mut record, Some(&headers), None, line, &options) {
+            if let Err(e) = apply_polled(parsed, engine, strict, &options) {
+                self.advance(&buf[..read], headers);
+                return Err(e);
+            }
+            rows += 1;
+            read = rdr.position().byte() as usize;
+        }
+        self.advance(&buf, headers);
+        Ok(rows)
+    }
+
+    /// Moves past the `read` bytes, which end with a complete line.
+    fn advance(&mut self, read: &[u8], headers: csv::StringRecord) {
+        self.headers = Some(headers);
+        self.offset += read.len() as u64;
+        self.line += read.iter().filter(|&&b| b == b'\n').count() as u64;
+    }
+}
+
+/// Applies a row read by `Tail::poll` to the engine, unless it is
+/// skipped, after counting it by the error threshold of the options.
+fn apply_polled(parsed: Parsed, engine: &mut Engine, strict: bool, options: &Options) -> Result<(), TxError> {
+    if let Some(threshold) = &options.max_errors {
+        if parsed.is_ok() {
+            threshold.count_row();
+        }
+        threshold.check(false)?;
+    }
+    if keep(&parsed, strict) && selected(&parsed, options.clients.as_ref()) {
+        let (_, txn) = parsed?;
+        let _ = engine.apply(txn.in_unit(options.amounts));
+    }
+    Ok(())
+}
+
+/// What a later dispute, resolve or chargeback needs to know of the
+/// handled transactions of a client with the same id: the deposit or
+/// withdrawal they refer to, and whether it is under dispute.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredTxState")]
+pub struct TxState {
+    original:      Option<Transaction>,
+    open_disputes: u32,
+    charged_back:  bool,
+}
+
+impl TxState {
+    /// Returns the state after the given transactions, e.g. as kept in
+    /// the order they were handled by a `Storage`.
+    pub fn of(txns: impl IntoIterator<Item = Transaction>) -> TxState {
+        txns.into_iter().fold(TxState::default(), |mut state, txn| { state.record(txn); state })
+    }
+
+    /// Updates the state with a handled transaction with its id.
+    pub fn record(&mut self, txn: Transaction) {
+        match txn.kind {
+            Deposit | Withdrawal if self.original.is_none() => self.original = Some(txn),
+            Dispute => self.open_disputes += 1,
+            Resolve => self.open_disputes = self.open_disputes.saturating_sub(1),
+            Chargeback => self.charged_back = true,
+            _ => (),
+        }
+    }
+
+    /// Returns the deposit or withdrawal that was handled with the id.
+    pub fn original(&self) -> Option<&Transaction> {
+        self.original.as_ref()
+    }
+
+    /// Returns `true` if there are more disputes than resolves,
+    /// and if there has been no chargebacks.
+    fn is_under_dispute(&self) -> bool {
+        self.open_disputes > 0 && !self.charged_back
+    }
+}
+
+/// A `TxState` as saved, or the handled transactions it was kept as
+/// before, so that older snapshots can still be loaded.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTxState {
+    State { original: Option<Transaction>, open_disputes: u32, charged_back: bool },
+    History(Vec<Transaction>),
+}
+
+impl From<StoredTxState> for TxState {
+    fn from(stored: StoredTxState) -> TxState {
+        match stored {
+            StoredTxState::State { original, open_disputes, charged_back } => TxState { original, open_disputes, charged_back },
+            StoredTxState::History(txns) => TxState::of(txns),
+        }
+    }
+}
+
+/// The `Account` of a client together with the state of the
+/// transactions that have been handled so far, keyed by transaction
+/// id, which are spilled to disk past `Options::max_memory`. The funds in a currency are kept in an account of their own.
+#[derive(Debug, Serialize, Deserialize)]
+struct Ledger {
+    account:    Account,
+    handled:    Handled,
+    /// Set once a transaction was rejected because the funds of the
+    /// account would overflow.
+    #[serde(default)]
+    saturated:  bool,
+    /// The accounts of the client in each currency.
+    #[serde(default)]
+    currencies: BTreeMap<String, Account>,
+}
+
+impl Ledger {
+    fn new(client_id: u16) -> Ledger {
+        Ledger {
+            account:    Account::new(client_id),
+            handled:    Handled::default(),
+            saturated:  false,
+            currencies: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the accounts of the client, ordered by currency. The
+    /// account in no currency is left out if it is empty, and the client
+    /// has accounts in currencies.
+    fn accounts(&self) -> impl Iterator<Item = &Account> {
+        let unused = !self.currencies.is_empty() && self.account == Account::new(self.account.client_id);
+        std::iter::once(&self.account)
+            .filter(move |_| !unused)
+            .chain(self.currencies.values())
+    }
+
+    /// Consumes the ledger and returns the accounts of the client,
+    /// like `accounts`.
+    fn into_accounts(self) -> impl Iterator<Item = Account> {
+        self.accounts().cloned().collect::<Vec<Account>>().into_iter()
+    }
+
+    /// Returns the `Account` of the client in a `currency`, or an empty
+    /// one if the client has no funds in it yet.
+    fn account(&self, currency: Option<&str>) -> Account {
+        match currency {
+            None => self.account.clone(),
+            Some(c) => self.currencies.get(c).cloned()
+                .unwrap_or_else(|| Account::new_in(self.account.client_id, currency)),
+        }
+    }
+
+    /// Keeps an `Account` of the client in place of the one in its currency.
+    fn keep(&mut self, account: Account) {
+        match account.currency.clone() {
+            None => self.account = account,
+            Some(currency) => { self.currencies.insert(currency, account); },
+        }
+    }
+
+    /// Handles a `Transaction` and keeps it for later disputes
+    /// if it was valid. Otherwise the error is returned together
+    /// with the transaction, and the ledger is marked as saturated
+    /// if the funds would overflow.
+    fn apply(&mut self, txn: Transaction, options: &Options) -> Result<(), (TxError, Transaction)> {
+        if let Err(e) = self.restore(txn.tx_id) {
+            return Err(self.rejected(e, txn));
+        }
+        let mut account = self.account(txn.currency());
+        match handle_txn(&mut account, self.handled.get(&txn.tx_id), &txn, options) {
+            // only insert when txn ok
+            Ok(()) => { self.commit(account, txn); Ok(()) },
+            Err(e) => Err(self.rejected(e, txn)),
+        }
+    }
+
+    /// Applies the `leg` of a transfer once the worker of the other
+    /// client told whether its side can be applied as well. Only the
+    /// side that can not be applied returns the error, so a rejected
+    /// transfer is reported once.
+    async fn apply_leg(&mut self, txn: Transaction, leg: Leg, options: &Options) -> Result<(), (TxError, Transaction)> {
+        let Leg { side, tell, hear } = leg;
+        if let Err(e) = self.restore(txn.tx_id) {
+            let _ = tell.send(false);
+            return Err(self.rejected(e, txn));
+        }
+        let (applied, other) = match side {
+            Side::Debit => {
+                let debited = self.leg(&txn, side, options);
+                let _ = tell.send(debited.is_ok());
+                (debited, hear.await.unwrap_or(false))
+            },
+            Side::Credit => {
+                if !hear.await.unwrap_or(false) {
+                    let _ = tell.send(false);
+                    return Ok(());
+                }
+                let credited = self.leg(&txn, side, options);
+                let _ = tell.send(credited.is_ok());
+                (credited, true)
+            },
+        };
+        match applied {
+            Ok(account) if other => { self.commit(account, txn); Ok(()) },
+            Ok(_) => Ok(()),
+            Err(e) => Err(self.rejected(e, txn)),
+        }
+    }
+
+    /// Returns the `Account` as it would be after the `side` of a
+    /// transfer, without changing the ledger.
+    fn leg(&self, txn: &Transaction, side: Side, options: &Options) -> Result<Account, TxError> {
+        let mut account = self.account(txn.currency());
+        match side {
+            Side::Debit => handle_txn(&mut account, self.handled.get(&txn.tx_id), txn, options)?,
+            Side::Credit => handle_credit(&mut account, txn, options)?,
+        }
+        Ok(account)
+    }
+
+    /// Keeps the `Account` after a transaction, and the transaction.
+    fn commit(&mut self, account: Account, txn: Transaction) {
+        self.keep(account);
+        self.handled.record(txn);
+    }
+
+    /// Moves the state of the transactions `tx_id` back to memory if it
+    /// was spilled to disk, which must be done before handling one.
+    fn restore(&mut self, tx_id: u32) -> Result<(), TxError> {
+        Ok(self.handled.restore(tx_id)?)
+    }
+
+    /// Returns the error together with the transaction, and marks the
+    /// ledger as saturated if the funds would overflow.
+    fn rejected(&mut self, e: TxError, txn: Transaction) -> (TxError, Transaction) {
+        if let TxError::Overflow(_) = e {
+            warn!("Funds of client {} would overflow, ignoring transaction: {:?}", self.account.client_id, txn);
+            self.saturated = true;
+        } else {
+            debug!("Ignoring invalid transaction: {:?}", txn);
+        }
+        (e, txn)
+    }
+}
+
+/// Reads the transactions from a file and writes the serialized results to
+/// `std::io::stdout()`.
+pub async fn read(path: &std::path::PathBuf) -> Result<(), TxError> {
+    let stdout = io::stdout();
+    let mut lock = stdout.lock();
+    read_with(&mut lock, path).await
+}
+
+/// Reads the transactions from a file and writes the serialized results to
+/// a given `std::io::Write` writer.
+pub async fn read_with(writer: &mut impl io::Write, path: &std::path::PathBuf) -> Result<(), TxError> {
+    let accounts = accounts_from_path(path).instrument(info_span!("accounts_from_path")).await?;
+    print_accounts_with(writer, &accounts).instrument(info_span!("print_accounts_with")).await;
+    Ok(())
+}
+
+/// Reads the transactions from a `std::io::Read` reader and writes the
+/// serialized results to a given `std::io::Write` writer.
+pub async fn read_from_reader_with( writer: &mut impl io::Write
+                                  , reader: impl io::Read + Send + 'static
+                                  ) -> Result<(), TxError> {
+    let accounts = accounts_from_reader(reader).instrument(info_span!("accounts_from_reader")).await?;
+    print_accounts_with(writer, &accounts).instrument(info_span!("print_accounts_with")).await;
+    Ok(())
+}
+
+/// Reads the transactions from a file and returns `Vec<Account>` that
+/// contains a list of parsed accounts, ordered by client id. The records
+/// are forwarded to the shard workers as they are parsed, so the
+/// file is never held in memory as a whole.
+pub async fn accounts_from_path(path: &std::path::PathBuf) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_path(path, &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Like `accounts_from_path`, but runs the workers and the reading on
+/// `spawner`, e.g. the executor of an application, instead of on the
+/// thread pool of the crate. The reading blocks its task while it
+/// waits for the file.
+pub async fn accounts_from_path_with_spawner(spawner: &impl Spawn, path: &std::path::PathBuf) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_paths_with_spawner(spawner, std::slice::from_ref(path), &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Like `accounts_from_path`, but for synchronous code, without any
+/// futures executor. See `process_paths_blocking`.
+pub fn accounts_from_path_blocking(path: &std::path::PathBuf) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_paths_blocking(std::slice::from_ref(path), &Options::default())?;
+    Ok(accounts)
+}
+
+/// Like `process_paths`, but for synchronous code, without any futures
+/// executor. The files are read on a thread of their own, which stays up
+/// to `options.capacity` transactions ahead, while the transactions are
+/// applied in order on the calling thread.
+pub fn process_paths_blocking(paths: &[std::path::PathBuf], options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    let files = expand_paths(paths)?;
+    let txns = txns_from_files(&files, options)?;
+    let mut engine = Engine::new().with_options(options.clone());
+    let report = std::thread::scope(|scope| {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(capacity(options, 1));
+        scope.spawn(move || {
+            for parsed in txns {
+                if sender.send(parsed).is_err() {
+                    break;
+                }
+            }
+        });
+        storage::process(receiver.into_iter(), options, &mut engine)
+    })?;
+    Ok((engine.into_accounts(), report.named(&files)))
+}
+
+/// Reads the transactions from a file and returns `Vec<Account>` that
+/// contains a list of parsed accounts, together with `Vec<Rejection>`
+/// that contains the rejected transactions ordered by line.
+pub async fn accounts_and_rejections_from_path(path: &std::path::PathBuf) -> Result<(Vec<Account>, Vec<Rejection>), TxError> {
+    let (accounts, report) = process_path(path, &Options { report: true, ..Options::default() }).await?;
+    Ok((accounts, report.rejections))
+}
+
+/// Reads the transactions from a file with the given `Options`, and
+/// returns `Vec<Account>` that contains a list of parsed accounts,
+/// together with a `Report` of the run. With the `parquet`
+/// feature, a file with the `.parquet` extension is read as Parquet.
+pub async fn process_path(path: &std::path::PathBuf, options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    process_paths(std::slice::from_ref(path), options).await
+}
+
+/// Reads the transactions from several files as one ordered log, so a
+/// dispute in a later file can refer to a deposit in an earlier one. The
+/// line of a `Rejection` is the line within its own file, whose path it
+/// has, and the rejections are ordered by file and line. Directories and
+/// glob patterns are expanded with `expand_paths`.
+pub async fn process_paths(paths: &[std::path::PathBuf], options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    let files = expand_paths(paths)?;
+    let (accounts, report) = process(txns_from_files(&files, options)?, options).await?;
+    Ok((accounts, report.named(&files)))
+}
+
+/// Like `process_paths`, but runs the workers and the reading on
+/// `spawner` instead of on the thread pool of the crate.
+pub async fn process_paths_with_spawner( spawner: &impl Spawn
+                                       , paths:   &[std::path::PathBuf]
+                                       , options: &Options
+                                       ) -> Result<(Vec<Account>, Report), TxError> {
+    let files = expand_paths(paths)?;
+    let (accounts, report) = process_with(spawner, txns_from_files(&files, options)?, options).await?;
+    Ok((accounts, report.named(&files)))
+}
+
+/// Like `process_paths`, but applies the transactions on top of the
+/// state of `engine`, e.g. as loaded by `snapshot::load`, and returns
+/// the new state.
+pub async fn resume_paths(paths: &[std::path::PathBuf], options: &Options, engine: Engine) -> Result<(Engine, Report), TxError> {
+    let files = expand_paths(paths)?;
+    let (engine, report) = resume(txns_from_files(&files, options)?, options, engine).await?;
+    Ok((engine, report.named(&files)))
+}
+
+/// Like `process_reader`, but applies the transactions on top of the
+/// state of `engine`, and returns the new state.
+pub async fn resume_reader(reader: impl io::Read + Send + 'static, options: &Options, engine: Engine) -> Result<(Engine, Report), TxError> {
+    resume(txns_from_reader(reader, options), options, engine).await
+}
+
+/// Reads the accounts from a csv file as written by `print_accounts_with`,
+/// e.g. the output of an earlier run.
+pub fn read_accounts_from_path(path: &std::path::PathBuf) -> Result<Vec<Account>, TxError> {
+    let mut rdr = reader_from_path(path, &CsvFormat::default())
+        .map_err(|source| TxError::Read { path: path.clone(), source })?;
+    let headers = rdr.headers().map_err(|e| parse_error(e, None))?.clone();
+    rdr.deserialize()
+        .map(|account| account.map_err(|e| parse_error(e, Some(&headers))))
+        .collect()
+}
+
+/// Merges the accounts of several shards or runs, e.g. as read by
+/// `read_accounts_from_path`, with `Account::merge`, and returns one
+/// account per client and currency, ordered by client id and currency.
+pub fn merge_accounts(accounts: Vec<Vec<Account>>) -> Result<Vec<Account>, TxError> {
+    let mut merged: BTreeMap<(u16, Option<String>), Account> = BTreeMap::new();
+    for account in accounts.into_iter().flatten() {
+        let key = (account.client_id, account.currency.clone());
+        let account = match merged.remove(&key) {
+            Some(earlier) => earlier.merge(&account)?,
+            None => account,
+        };
+        merged.insert(key, account);
+    }
+    Ok(merged.into_values().collect())
+}
+
+/// Reads the transactions from a `std::io::Read` reader and returns
+/// `Vec<Account>` that contains a list of parsed accounts.
+pub async fn accounts_from_reader(reader: impl io::Read + Send + 'static) -> Result<Vec<Account>, TxError> {
+    let (accounts, _) = process_reader(reader, &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Reads the transactions from a `std::io::Read` reader with the given
+/// `Options`, and returns `Vec<Account>` that contains a list of parsed
+/// accounts, together with a `Report` of the run.
+pub async fn process_reader(reader: impl io::Read + Send + 'static, options: &Options) -> Result<(Vec<Account>, Report), TxError> {
+    process(txns_from_reader(reader, options), options).await
+}
+
+/// Like `process_reader`, but runs the workers and the reading on
+/// `spawner` instead of on the thread pool of the crate.
+pub async fn process_reader_with_spawner( spawner: &impl Spawn
+                                        , reader:  impl io::Read + Send + 'static
+                                        , options: &Options
+                                        ) -> Result<(Vec<Account>, Report), TxError> {
+    process_with(spawner, txns_from_reader(reader, options), options).await
+}
+
+/// Forwards already parsed transactions to the shard workers, and
+/// returns `Vec<Account>` that contains a list of accounts once all
+/// workers are done.
+pub async fn accounts_from_iter<I>(txns: I) -> Result<Vec<Account>, TxError>
+where
+    I: IntoIterator<Item = Transaction>,
+    I::IntoIter: Send + 'static,
+{
+    let (accounts, _) = process(numbered(txns.into_iter()), &Options::default()).await?;
+    Ok(accounts)
+}
+
+/// Reads the transactions from a file and returns a `Stream` that yields
+/// every `Account` as soon as its worker is done, so the results can be
+/// written while the slower clients are still being processed.
+pub async fn account_stream_from_path(path: &std::path::PathBuf) -> Result<impl Stream<Item = Account>, TxError> {
+    stream(txns_from_path(path, &Options::default())?).await
+}
+
+/// Forwards already parsed transactions to the shard workers, and
+/// returns a `Stream` that yields every `Account` as soon as its worker
+/// is done.
+pub async fn account_stream_from_iter<I>(txns: I) -> Result<impl Stream<Item = Account>, TxError>
+where
+    I: IntoIterator<Item = Transaction>,
+    I::IntoIter: Send + 'static,
+{
+    stream(numbered(txns.into_iter())).await
+}
+
+/// Wraps the `writer` in a `csv::Writer` and writes the accounts.
+/// The `csv::Writer` is already buffered so there is no need to wrap
+/// `writer` in a `io::BufWriter`.
+pub async fn print_accounts_with(writer: &mut impl io::Write, accounts: &[Account]) {
+    write_csv(writer, accounts, false);
+}
+
+/// Like `print_accounts_with`, but writes the funds with exactly four
+/// digits after decimal, e.g. `2.0000`.
+pub async fn print_fixed_accounts_with(writer: &mut impl io::Write, accounts: &[Account]) {
+    write_csv(writer, accounts, true);
+}
+
+/// Writes a `currency` column for all the accounts if any of them has
+/// a currency.
+pub(crate) fn write_csv(writer: &mut impl io::Write, accounts: &[Account], fixed: bool) {
+    let currency = accounts.iter().any(|a| a.currency.is_some());
+    let mut wtr = WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    accounts.iter()
+        .map(|account| AccountRow { account, currency, fixed })
+        .for_each(|row| wtr.serialize(row).unwrap());
+}
+
+/// Writes the accounts to the `writer` as a JSON array.
+pub async fn print_accounts_json(writer: &mut impl io::Write, accounts: &[Account]) {
+    serde_json::to_writer(&mut *writer, accounts).unwrap();
+    writeln!(writer).unwrap();
+}
+
+/// Like `print_accounts_json`, but writes the funds with exactly four
+/// digits after decimal.
+pub async fn print_fixed_accounts_json(writer: &mut impl io::Write, accounts: &[Account]) {
+    let accounts: Vec<AccountRow> =
+        accounts.iter()
+            .map(|account| AccountRow { account, currency: account.currency.is_some(), fixed: true })
+            .collect();
+    serde_json::to_writer(&mut *writer, &accounts).unwrap();
+    writeln!(writer).unwrap();
+}
+
+/// Writes the accounts to the `writer` as newline-delimited JSON, an
+/// object like those of `print_accounts_json` on every line.
+pub async fn print_accounts_jsonl(writer: &mut impl io::Write, accounts: &[Account]) {
+    accounts.iter().for_each(|account| {
+        serde_json::to_writer(&mut *writer, account).unwrap();
+        writeln!(writer).unwrap();
+    });
+}
+
+/// Like `print_accounts_jsonl`, but writes the funds with exactly four
+/// digits after decimal.
+pub async fn print_fixed_accounts_jsonl(writer: &mut impl io::Write, accounts: &[Account]) {
+    accounts.iter().for_each(|account| {
+        serde_json::to_writer(&mut *writer, &AccountRow { account, currency: account.currency.is_some(), fixed: true }).unwrap();
+        writeln!(writer).unwrap();
+    });
+}
+
+/// Writes the accounts to the `writer` as a table, with every
+/// column padded to the width of its widest cell.
+pub async fn print_accounts_table(writer: &mut impl io::Write, accounts: &[Account]) {
+    write_table(writer, accounts, TableStyle::default());
+}
+
+/// Like `print_accounts_table`, but writes the funds with exactly four
+/// digits after decimal.
+pub async fn print_fixed_accounts_table(writer: &mut impl io::Write, accounts: &[Account]) {
+    write_table(writer, accounts, TableStyle { fixed: true, ..TableStyle::default() });
+}
+
+/// Like `print_accounts_table`, but in `style`.
+pub async fn print_accounts_table_styled(writer: &mut impl io::Write, accounts: &[Account], style: TableStyle) {
+    write_table(writer, accounts, style);
+}
+
+/// How `print_accounts_table_styled` writes the table of the accounts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TableStyle {
+    /// Writes the funds with exactly four digits after decimal.
+    pub fixed:  bool,
+    /// Ends the table with a footer row of the total funds of the
+    /// accounts and the number of locked accounts, one for every currency.
+    pub totals: bool,
+    /// Highlights the accounts that need a look with ANSI colors, for a
+    /// terminal: locked accounts and negative available funds in red,
+    /// and held funds in yellow.
+    pub colors: bool,
+}
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+fn write_table(writer: &mut impl io::Write, accounts: &[Account], style: TableStyle) {
+    let funds = |funds: Decimal| if style.fixed { Fixed(funds).to_string() } else { funds.to_string() };
+    let currency = accounts.iter().any(|a| a.currency.is_some());
+    let header = ["client", "currency", "available", "held", "total", "locked"].map(String::from);
+    let rows: Vec<[String; 6]> =
+        std::iter::once(header)
+            .chain(accounts.iter().map(|a| [ a.client_id.to_string()
+                                           , a.currency.clone().unwrap_or_default()
+                                           , funds(a.available)
+                                           , funds(a.held)
+                                           , funds(a.total)
+                                           , a.locked.to_string()
+                                           ]))
+            .collect();
+    let footer: Vec<[String; 6]> = match style.totals {
+        true => totals(accounts).into_iter().map(|(currency, sum)| [ String::from("total")
+                                                                   , currency.unwrap_or_default()
+                                                                   , funds(sum.available)
+                                                                   , funds(sum.held)
+                                                                   , funds(sum.total)
+                                                                   , sum.locked.to_string()
+                                                                   ]).collect(),
+        false => vec![],
+    };
+    let widths = rows.iter().chain(&footer).fold([0; 6], |mut widths, row| {
+        row.iter().enumerate().for_each(|(i, cell)| widths[i] = widths[i].max(cell.len()));
+        widths
+    });
+    let rule = widths.map(|w| "-".repeat(w));
+    let colors = std::iter::once(None).chain(accounts.iter().map(Some)).map(|account| account.filter(|_| style.colors));
+    let footer = (!footer.is_empty()).then_some(&rule).into_iter().chain(&footer).map(|row| (row, None));
+    rows.iter().zip(colors).chain(footer).for_each(|(row, account)| {
+        let line: Vec<String> =
+            row.iter()
+                .zip(widths.iter())
+                .enumerate()
+                .filter(|(i, _)| currency || *i != 1)
+                .map(|(i, (cell, w))| match account.and_then(|account| color(account, i)) {
+                    Some(color) => format!("{}{:>w$}{}", color, cell, RESET, w = w),
+                    None => format!("{:>w$}", cell, w = w),
+                })
+                .collect();
+        writeln!(writer, "{}", line.join("  ")).unwrap();
+    });
+}
+
+/// Returns the color of the cell of an account in the `column` of the
+/// table, if it needs a look.
+fn color(account: &Account, column: usize) -> Option<&'static str> {
+    match column {
+        2 if account.available.is_sign_negative() && !account.available.is_zero() => Some(RED),
+        3 if !account.held.is_zero() => Some(YELLOW),
+        _ if account.locked => Some(RED),
+        _ => None,
+    }
+}
+
+/// The sums of the funds of the accounts in a currency, and the
+/// number of the accounts and of those that are locked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Totals {
+    pub(crate) available: Decimal,
+    pub(crate) held:      Decimal,
+    pub(crate) total:     Decimal,
+    pub(crate) accounts:  usize,
+    pub(crate) locked:    usize,
+}
+
+/// Returns the `Totals` of the accounts per currency. A sum that would
+/// overflow stays at the largest, or the smallest, funds.
+pub(crate) fn totals(accounts: &[Account]) -> BTreeMap<Option<String>, Totals> {
+    let add = |sum: Decimal, funds: Decimal| sum.checked_add(funds).unwrap_or(if funds.is_sign_negative() { Decimal::MIN } else { Decimal::MAX });
+    accounts.iter().fold(BTreeMap::new(), |mut sums, account| {
+        let sum = sums.entry(account.currency.clone()).or_insert_with(Totals::default);
+        sum.available = add(sum.available, account.available);
+        sum.held      = add(sum.held, account.held);
+        sum.total     = add(sum.total, account.total);
+        sum.accounts += 1;
+        sum.locked   += account.locked as usize;
+        sums
+    })
+}
+
+/// Wraps the `writer` in a `csv::Writer` and writes the rejected
+/// transactions, with the reason as the last column.
+pub async fn print_rejections_with(writer: &mut impl io::Write, rejections: &[Rejection]) {
+    let mut wtr = WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    rejections.iter().for_each(|r| {
+        let reason = r.reason.to_string();
+        let record = RejectionRecord { line:   r.line
+                                     , kind:   r.txn.kind
+                                     , client: r.txn.client_id
+                                     , tx:     r.txn.tx_id
+                                     , amount: r.txn.amount
+                                     , reason: &reason
+                                     };
+        wtr.serialize(record).unwrap()
+    });
+}
+
+/// Generate and print a list of random transactions.
+pub async fn generate_txns(num_txns: u32, num_clients: u16) {
+    let generator = generate::Generator { num_txns, num_clients, ..generate::Generator::default() };
+    generate::write_rows(io::stdout().lock(), &generator.columns(), generator.rows()).unwrap();
+}
+
+/// Returns the `csv::ReaderBuilder` used for all transaction input,
+/// from the file at `path` if the input is a file.
+fn reader_builder(format: &CsvFormat, path: Option<&std::path::Path>) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .has_headers(!format.no_headers)
+        .delimiter(format.delimiter(path))
+        .trim(Trim::All);
+    builder
+}
+
+/// Opens the file from path in a `csv::Reader`.
+pub(crate) fn reader_from_path(path: &std::path::PathBuf, format: &CsvFormat) -> csv::Result<csv::Reader<std::fs::File>> {
+    reader_builder(format, Some(path)).from_path(path)
+}
+
+/// Returns the paths of `shards` files: `path` for one, or `path` with
+/// the number of the shard appended to its stem, padded to sort in order,
+/// e.g. `txns-01.csv` to `txns-12.csv`.
+pub fn shard_paths(path: &std::path::Path, shards: usize) -> Vec<std::path::PathBuf> {
+    if shards <= 1 {
+        return vec![path.to_path_buf()];
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let width = shards.to_string().len();
+    (1..=shards)
+        .map(|n| {
+            let name = match path.extension() {
+                Some(ext) => format!("{}-{:0width$}.{}", stem, n, ext.to_string_lossy(), width = width),
+                None => format!("{}-{:0width$}", stem, n, width = width),
+            };
+            path.with_file_name(name)
+        })
+        .collect()
+}
+
+/// Splits the accounts, ordered by client id, into `shards` parts of
+/// about as many clients each, so that every part has a range of client
+/// ids of its own, with all the accounts of its clients. Parts past the
+/// last client are empty.
+pub fn shard_accounts(accounts: &[Account], shards: usize) -> Vec<&[Account]> {
+    let starts = (0..accounts.len())
+        .filter(|&i| i == 0 || accounts[i].client_id != accounts[i - 1].client_id)
+        .collect::<Vec<_>>();
+    let size = starts.len().div_ceil(shards.max(1)).max(1);
+    let start = |shard: usize| starts.get(shard * size).copied().unwrap_or(accounts.len());
+    (0..shards.max(1)).map(|shard| &accounts[start(shard)..start(shard + 1)]).collect()
+}
+
+/// Expands the directories and glob patterns among the paths into the
+/// files they contain, in lexicographic order. A directory expands to its
+/// input files, that is `.csv` and `.tsv` files, and `.parquet` files with
+/// the `parquet` feature. Any other path, or an `http://` or `https://`
+/// URL, or the URL of an object in a store with the `object-store`
+/// feature, is kept as it is.
+pub fn expand_paths(paths: &[std::path::PathBuf]) -> Result<Vec<std::path::PathBuf>, TxError> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        let mut files = if path.is_dir() {
+            std::fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .filter(|entry| entry.as_ref().map_or(true, |p| p.is_file() && is_input_file(p)))
+                .collect::<Result<Vec<_>, io::Error>>()?
+        } else if is_remote(path) {
+            vec![path.clone()]
+        } else if !path.exists() && is_pattern(path) {
+            let pattern = path.to_string_lossy();
+            let files = glob::glob(&pattern)?
+                .map(|entry| entry.map_err(io::Error::from))
+                .filter(|entry| entry.as_ref().map_or(true, |p| p.is_file()))
+                .collect::<Result<Vec<_>, io::Error>>()?;
+            if files.is_empty() {
+                return Err(TxError::NoMatch(pattern.into_owned()));
+            }
+            files
+        } else {
+            vec![path.clone()]
+        };
+        files.sort();
+        expanded.append(&mut files);
+    }
+    Ok(expanded)
+}
+
+/// Returns `true` if the path is a URL that is downloaded instead of
+/// opened as a file.
+pub(crate) fn is_remote(path: &std::path::Path) -> bool {
+    #[cfg(feature = "object-store")]
+    if crate::store::is_object_url(path) {
+        return true;
+    }
+    remote::is_url(path)
+}
+
+fn is_pattern(path: &std::path::Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_input_file(path: &std::path::Path) -> bool {
+    #[cfg(feature = "parquet")]
+    if crate::columnar::is_parquet(path) {
+        return true;
+    }
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) || is_tsv(path)
+}
+
+/// Returns `true` if the file has the `.tsv` extension.
+fn is_tsv(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tsv"))
+}
+
+/// Expands the paths with `expand_paths`, and returns the iterator of
+/// `txns_from_files` over the files.
+pub(crate) fn txns_from_paths(paths: &[std::path::PathBuf], options: &Options) -> Result<impl Iterator<Item = Parsed> + Send, TxError> {
+    txns_from_files(&expand_paths(paths)?, options)
+}
+
+/// Opens all files up front, so a missing file fails before any
+/// transaction is applied, except for the named pipes, which are opened
+/// once they are reached as their writers may open them one after
+/// another, and returns an iterator that lazily reads
+/// their transactions one file after another.
+pub(crate) fn txns_from_files(files: &[std::path::PathBuf], options: &Options) -> Result<impl Iterator<Item = Parsed> + Send, TxError> {
+    let txns = files.iter()
+        .map(|path| txns_from_path(path, options))
+        .collect::<Result<Vec<_>, TxError>>()?;
+    Ok(limited(in_files(txns), options))
+}
+
+/// Chains the transactions of several files, with the index of its file
+/// in the `Line` of every transaction.
+pub(crate) fn in_files<I>(files: Vec<I>) -> impl Iterator<Item = Parsed>
+where
+    I: Iterator<Item = Parsed>,
+{
+    files.into_iter()
+        .enumerate()
+        .flat_map(|(file, txns)| txns.map(move |parsed| parsed.map(|(line, txn)| (Line { file, ..line }, txn))))
+}
+
+/// Ends the transactions once `Options::limit` of them were read, or
+/// after the first one with the id of `Options::until_tx`, so that the
+/// accounts are those at that point of the input. The malformed records
+/// that are skipped are not counted.
+pub(crate) fn limited<I: Iterator<Item = Parsed>>(mut txns: I, options: &Options) -> impl Iterator<Item = Parsed> {
+    let (limit, until_tx) = (options.limit.unwrap_or(u64::MAX), options.until_tx);
+    let (mut read, mut done) = (0, false);
+    std::iter::from_fn(move || {
+        if read >= limit || done {
+            return None;
+        }
+        let parsed = txns.next()?;
+        if let Ok((line, txn)) = &parsed {
+            read += 1;
+            done = until_tx == Some(txn.tx_id);
+            if read == limit || done {
+                info!("Stopping after line {}", line.line);
+            }
+        }
+        Some(parsed)
+    })
+}
+
+/// Opens the file from path, or downloads it if it is a URL, and returns
+/// an iterator that lazily reads its transactions.
+pub(crate) fn txns_from_path(path: &std::path::PathBuf, options: &Options) -> Result<Box<dyn Iterator<Item = Parsed> + Send>, TxError> {
+    #[cfg(feature = "parquet")]
+    if crate::columnar::is_parquet(path) {
+        let clients = options.clients.clone();
+        return Ok(Box::new(crate::columnar::txns_from_parquet(path, options.strict)?.filter(move |parsed| selected(parsed, clients.as_ref()))));
+    }
+
+    let _span = info_span!("txns_from_path", path = %path.display()).entered();
+    if pipe::is_fifo(path) {
+        return Ok(txns_from_pipe(path, options));
+    }
+    if remote::is_url(path) {
+        let reader = remote::UrlReader::open(&path.to_string_lossy(), options.retries)
+            .map_err(|e| TxError::Read { path: path.clone(), source: e.into() })?;
+        return txns_from_download(reader, path, options);
+    }
+    #[cfg(feature = "object-store")]
+    if crate::store::is_object_url(path) {
+        let reader = crate::store::ObjectReader::open(&path.to_string_lossy())
+            .map_err(|e| TxError::Read { path: path.clone(), source: e.into() })?;
+        return txns_from_download(reader, path, options);
+    }
+    if options.parallel_parse {
+        return match options.mmap {
+            true => txns_from_chunks(map_file(path), path, options, CHUNK),
+            false => txns_from_chunks(std::fs::read(path), path, options, CHUNK),
+        };
+    }
+    if options.mmap {
+        let map = map_file(path).map_err(|e| TxError::Read { path: path.clone(), source: e.into() })?;
+        let reader = Counted::new(io::Cursor::new(map), options.progress.as_ref());
+        return Ok(Box::new(deserialize(reader_builder(&options.csv, Some(path)).from_reader(reader), options)));
+    }
+    let file = std::fs::File::open(path)
+        .map_err(|e| TxError::Read { path: path.clone(), source: e.into() })?;
+    let rdr = reader_builder(&options.csv, Some(path)).from_reader(Counted::new(file, options.progress.as_ref()));
+    Ok(Box::new(deserialize(rdr, options)))
+}
+
+/// Returns an iterator that reads the transactions of a file as it is
+/// downloaded by `reader`. A failed download ends the transactions with
+/// `TxError::Read`, even if `options.strict` is not set.
+fn txns_from_download<R>(mut reader: R, path: &std::path::Path, options: &Options) -> Result<Box<dyn Iterator<Item = Parsed> + Send>, TxError>
+where R: io::Read + Send + 'static
+{
+    if options.parallel_parse {
+        let mut bytes = Vec::new();
+        return txns_from_chunks(io::Read::read_to_end(&mut reader, &mut bytes).map(|_| bytes), path, options, CHUNK);
+    }
+    Ok(Box::new(txns_from_stream(reader, path, options)))
+}
+
+/// Returns an iterator that opens the named pipe once it is first
+/// advanced, and reads its records as they are written, even with
+/// `options.parallel_parse` or `options.mmap`, which need the whole file.
+/// A failed read ends the transactions with `TxError::Read`.
+fn txns_from_pipe(path: &std::path::Path, options: &Options) -> Box<dyn Iterator<Item = Parsed> + Send> {
+    if options.parallel_parse || options.mmap {
+        debug!("Reading named pipe {} as its records arrive", path.display());
+    }
+    let (path, options) = (path.to_path_buf(), options.clone());
+    let mut txns: Option<Box<dyn Iterator<Item = Parsed> + Send>> = None;
+    Box::new(std::iter::from_fn(move || {
+        txns.get_or_insert_with(|| match pipe::Pipe::open(&path) {
+            Ok(reader) => Box::new(txns_from_stream(reader, &path, &options)),
+            Err(e) => Box::new(std::iter::once(Err(TxError::Read { path: path.clone(), source: e.into() }))),
+        }).next()
+    }))
+}
+
+/// Returns an iterator that reads the transactions of a file as its
+/// bytes arrive from `reader`. An I/O error ends the transactions with
+/// `TxError::Read`, even if `options.strict` is not set.
+fn txns_from_stream<R: io::Read>(reader: R, path: &std::path::Path, options: &Options) -> impl Iterator<Item = Parsed> {
+    let rdr = reader_builder(&options.csv, Some(path)).from_reader(Counted::new(reader, options.progress.as_ref()));
+    let path = path.to_path_buf();
+    deserialize(rdr, options).map(move |parsed| match parsed {
+        Err(TxError::Parse { source, .. }) if source.is_io_error() => Err(TxError::Read { path: path.clone(), source }),
+        parsed => parsed,
+    })
+}
+
+/// Number of bytes of a file parsed by each thread at a time, with
+/// `Options::parallel_parse`.
+const CHUNK: usize = 1 << 20;
+
+/// Returns an iterator over the transactions of a file read into
+/// `bytes`, which splits them into line-aligned chunks of about `chunk`
+/// bytes, parses as many chunks as there are threads in parallel, and
+/// yields their transactions in the order of the file. Every chunk is
+/// parsed after the first line of the file, the header row or the first
+/// record, so that its records are checked against its length. A file
+/// with quotes, whose fields may span lines, is parsed in one piece.
+fn txns_from_chunks<B>( bytes:   io::Result<B>
+                      , path:    &std::path::Path
+                      , options: &Options
+                      , chunk:   usize
+                      ) -> Result<Box<dyn Iterator<Item = Parsed> + Send>, TxError>
+where
+    B: AsRef<[u8]> + Send + Sync + 'static,
+{
+    let bytes = bytes.map_err(|e| TxError::Read { path: path.to_path_buf(), source: e.into() })?;
+    if bytes.as_ref().contains(&b'"') {
+        let reader = Counted::new(io::Cursor::new(bytes), options.progress.as_ref());
+        return Ok(Box::new(deserialize(reader_builder(&options.csv, Some(path)).from_reader(reader), options)));
+    }
+    let first_end = bytes.as_ref().iter().position(|&b| b == b'\n').map_or(bytes.as_ref().len(), |i| i + 1);
+    let (mut start, mut line) = match options.csv.no_headers {
+        true => (0, 1),
+        false => (first_end, 2),
+    };
+    if let Some(progress) = &options.progress {
+        progress.add(&bytes.as_ref()[..start]);
+    }
+    let (path, options, threads) = (path.to_path_buf(), options.clone(), rayon::current_num_threads());
+    let batches = std::iter::from_fn(move || {
+        let data = bytes.as_ref();
+        if start >= data.len() {
+            return None;
+        }
+        let mut chunks = Vec::with_capacity(threads);
+        while chunks.len() < threads && start < data.len() {
+            let end = data[(start + chunk).min(data.len())..].iter()
+                .position(|&b| b == b'\n')
+                .map_or(data.len(), |i| start + chunk + i + 1);
+            chunks.push((start..end, line));
+            line += data[start..end].iter().filter(|&&b| b == b'\n').count() as u64;
+            if let Some(progress) = &options.progress {
+                progress.add(&data[start..end]);
+            }
+            start = end;
+        }
+        let parsed: Vec<Vec<Parsed>> =
+            chunks.into_par_iter()
+                .map(|(range, first)| {
+                    let rdr = reader_builder(&options.csv, Some(&path))
+                        .has_headers(true)
+                        .from_reader(io::Read::chain(&data[..first_end], &data[range]));
+                    deserialize(rdr, &options).map(|parsed| shifted(parsed, first)).collect()
+                })
+                .collect();
+        Some(parsed.into_iter().flatten())
+    });
+    Ok(Box::new(batches.flatten()))
+}
+
+/// Returns `parsed` from a chunk of a file, read after the first line,
+/// with its line, or the line of its error, in the file, given the line
+/// `first` the chunk starts at.
+fn shifted(mut parsed: Parsed, first: u64) -> Parsed {
+    match &mut parsed {
+        Ok((Line { line, .. }, _)) | Err(TxError::Parse { line, .. }) | Err(TxError::FractionalAmount { line, .. })
+            if *line > 0 => *line = *line + first - 2,
+        _ => (),
+    }
+    parsed
+}
+
+/// Maps the file from path into memory, so its records are parsed
+/// straight from the mapped bytes without read calls.
+fn map_file(path: &std::path::Path) -> io::Result<memmap2::Mmap> {
+    let file = std::fs::File::open(path)?;
+    // Safety: the file is only read, and `Options::mmap` documents
+    // that it must not be changed while it is mapped
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+/// Returns an iterator that lazily reads the csv transactions
+/// from a `std::io::Read` reader.
+pub(crate) fn txns_from_reader<R: io::Read>(reader: R, options: &Options) -> impl Iterator<Item = Parsed> {
+    limited(deserialize(reader_builder(&options.csv, None).from_reader(Counted::new(reader, options.progress.as_ref())), options), options)
+}
+
+/// Returns an iterator that lazily reads the csv transactions from
+/// a reader of rows that may leave out the trailing columns, e.g. the
+/// `to` and `currency` of a deposit, such as the messages of a topic.
+#[cfg(feature = "kafka")]
+pub(crate) fn txns_from_rows<R: io::Read>(reader: R, options: &Options) -> impl Iterator<Item = Parsed> {
+    let mut builder = reader_builder(&options.csv, None);
+    builder.flexible(true);
+    limited(deserialize(builder.from_reader(Counted::new(reader, options.progress.as_ref())), options), options)
+}
+
+/// Returns an iterator that lazily deserializes the records of the
+/// reader into `Transaction`, together with the line of each record.
+/// The records that can not be parsed are skipped, unless
+/// `options.strict` is set in which case they are returned as
+/// `TxError::Parse`. With `options.fast_parse`, the fields are parsed
+/// by `Columns::parse` and only the records it does not take are
+/// deserialized with serde.
+fn deserialize<R: io::Read>(mut rdr: csv::Reader<R>, options: &Options) -> impl Iterator<Item = Parsed> {
+    let strict = options.strict;
+    let format = options.csv.clone();
+    let (headers, unread) = match format.headers(&mut rdr) {
+        Ok(headers) => (Some(headers), None),
+        Err(e) if e.is_io_error() => (None, Some(Err(parse_error(e, None)))),
+        Err(_) => (None, None),
+    };
+    let columns = headers.as_ref().filter(|_| options.fast_parse).and_then(Columns::of);
+    let clients = options.clients.clone();
+    if let (Some(quarantine), Some(headers)) = (&options.quarantine, &headers) {
+        quarantine.columns(rdr.has_headers().then(|| rdr.headers().ok()).flatten(), headers);
+    }
+    let options = options.clone();
+    let mut record = csv::StringRecord::new();
+    unread.into_iter()
+        .chain(std::iter::from_fn(move || read_parsed(&mut rdr, &mut record, headers.as_ref(), columns.as_ref(), 0, &options)))
+        .filter(move |parsed| keep(parsed, strict) && selected(parsed, clients.as_ref()))
+}
+
+/// Reads the next record into `record` and parses it, or returns `None`
+/// at the end of the input. The lines of the records are counted from
+/// `line`. A record that can not be read, e.g. with the wrong number of
+/// fields, or parsed is written to the quarantine of the options and
+/// counted by their error threshold, and returned as an error for
+/// `keep` to skip unless the run is strict.
+pub(crate) fn read_parsed<R: io::Read>( rdr:     &mut csv::Reader<R>
+                                      , record:  &mut csv::StringRecord
+                                      , headers: Option<&csv::StringRecord>
+                                      , columns: Option<&Columns>
+                                      , line:    u64
+                                      , options: &Options
+                                      ) -> Option<Parsed> {
+    let parsed = match rdr.read_record(record) {
+        Ok(false) => return None,
+        Ok(true) => {
+            let line = line + record.position().map_or(0, |pos| pos.line());
+            let parsed = columns.and_then(|columns| columns.parse(options.csv.normalize(record, headers).as_byte_record()));
+            let parsed = parsed.map_or_else(|| parse_record(record, headers, line, &options.csv), |txn| Ok((Line::from(line), txn)))
+                .and_then(|parsed| whole(parsed, options.amounts));
+            if let (Some(quarantine), Err(reason)) = (&options.quarantine, &parsed) {
+                quarantine.malformed(record, reason);
+            }
+            parsed
+        },
+        Err(e) => {
+            let unequal = matches!(e.kind(), csv::ErrorKind::UnequalLengths { .. });
+            let error = parse_error(e, None);
+            if let (Some(quarantine), true) = (&options.quarantine, unequal) {
+                quarantine.malformed(record, &error);
+            }
+            Err(error)
+        },
+    };
+    if let (Some(threshold), Err(_)) = (&options.max_errors, &parsed) {
+        threshold.count_malformed();
+    }
+    Some(parsed)
+}
+
+/// The positions of the fields of a `Transaction` among the columns
+/// of the csv input, for parsing the records without serde.
+pub(crate) struct Columns {
+    kind:      usize,
+    client_id: usize,
+    tx_id:     usize,
+    amount:    Option<usize>,
+    to_client: Option<usize>,
+    currency:  Option<usize>,
+}
+
+impl Columns {
+    /// Returns the positions of the fields among the `headers`, or `None`
+    /// if a field is missing or named twice, which is left to serde.
+    fn of(headers: &csv::StringRecord) -> Option<Columns> {
+        let position = |name: &str| -> Option<Option<usize>> {
+            let mut found = headers.iter().enumerate().filter(|(_, header)| *header == name).map(|(i, _)| i);
+            match (found.next(), found.next()) {
+                (found, None) => Some(found),
+                _ => None,
+            }
+        };
+        Some(Columns { kind:      position("type")??
+                     , client_id: position("client")??
+                     , tx_id:     position("tx")??
+                     , amount:    position("amount")?
+                     , to_client: position("to")?
+                     , currency:  position("currency")?
+                     })
+    }
+
+    /// Parses a record into a `Transaction` the way serde would, or
+    /// returns `None` if a field is not in the plain form taken here,
+    /// e.g. a kind in capitals or an amount with an exponent, so the
+    /// record is deserialized with serde, which also reports the errors.
+    fn parse(&self, record: &csv::ByteRecord) -> Option<Transaction> {
+        let kind = match record.get(self.kind)? {
+            b"deposit"    => Deposit,
+            b"withdrawal" => Withdrawal,
+            b"dispute"    => Dispute,
+            b"resolve"    => Resolve,
+            b"chargeback" => Chargeback,
+            b"unlock"     => Unlock,
+            b"adjustment" => Adjustment,
+            b"transfer"   => Transfer,
+            _             => return None,
+        };
+        let optional = |column: Option<usize>| column.and_then(|i| record.get(i)).filter(|field| !field.is_empty());
+        let amount = match optional(self.amount) {
+            Some(field) => Some(parse_amount(field)?),
+            None => None,
+        };
+        let to_client = match optional(self.to_client) {
+            Some(field) => Some(parse_int(field)?),
+            None => None,
+        };
+        let currency = match optional(self.currency) {
+            Some(field) => Some(String::from_utf8(field.to_vec()).ok()?),
+            None => None,
+        };
+        Some(Transaction { kind
+                         , client_id: parse_int(record.get(self.client_id)?)?
+                         , tx_id:     parse_int(record.get(self.tx_id)?)?
+                         , amount
+                         , to_client
+                         , currency
+                         })
+    }
+}
+
+/// Parses an unsigned integer made of ascii digits only, or returns
+/// `None` if it has any other byte or does not fit.
+fn parse_int<T: std::convert::TryFrom<u64>>(field: &[u8]) -> Option<T> {
+    if field.is_empty() || field.len() > 19 {
+        return None;
+    }
+    field.iter()
+        .try_fold(0u64, |n, &b| b.is_ascii_digit().then(|| n * 10 + u64::from(b - b'0')))
+        .and_then(|n| T::try_from(n).ok())
+}
+
+/// Parses an amount such as `-1.50` into the `Decimal` that serde
+/// reads, which goes through a `f64` and so drops the trailing zeros
+/// of the fraction. Returns `None` for any other form, e.g. with an
+/// exponent, and for more significant digits than a `f64` keeps.
+fn parse_amount(field: &[u8]) -> Option<Decimal> {
+    let (negative, digits) = match field.strip_prefix(b"-") {
+        Some(digits) => (true, digits),
+        None => (false, field),
+    };
+    let (int, frac) = match digits.iter().position(|&b| b == b'.') {
+        Some(dot) if dot + 1 < digits.len() => (&digits[..dot], &digits[dot + 1..]),
+        Some(_) => return None,
+        None => (digits, &b""[..]),
+    };
+    let plain = !int.is_empty() && int.iter().chain(frac).all(u8::is_ascii_digit);
+    let significant = int.iter().chain(frac).skip_while(|&&b| b == b'0').count();
+    if !plain || significant > 15 || frac.len() > 28 {
+        return None;
+    }
+    let mut mantissa = int.iter().chain(frac).fold(0i64, |n, &b| n * 10 + i64::from(b - b'0'));
+    let mut scale = frac.len() as u32;
+    while scale > 0 && mantissa % 10 == 0 {
+        mantissa /= 10;
+        scale -= 1;
+    }
+    // serde reads `-0` as an integer and `-0.0` as a negative zero
+    if negative && mantissa == 0 {
+        return None;
+    }
+    Some(Decimal::new(if negative { -mantissa } else { mantissa }, scale))
+}
+
+/// Deserializes a record into a `Transaction`, together with `line`
+/// in place of the line of the record, with its amount in `format`.
+pub(crate) fn parse_record( record:  &csv::StringRecord
+                          , headers: Option<&csv::StringRecord>
+                          , line:    u64
+                          , format:  &CsvFormat
+                          ) -> Parsed {
+    let mut parsed = format.normalize(record, headers).deserialize(headers)
+        .map(|txn| (Line::from(line), txn))
+        .map_err(|e| parse_error(e, headers));
+    if let Err(TxError::Parse { line: l, .. }) = &mut parsed {
+        *l = line;
+    }
+    parsed
+}
+
+/// Returns `TxError::FractionalAmount` for a transaction whose amount
+/// is read in `AmountUnit::Minor` but is not a whole number, e.g. `100.5`,
+/// since it can not be converted without dropping the fraction.
+fn whole(parsed: (Line, Transaction), unit: AmountUnit) -> Parsed {
+    match (unit, parsed.1.amount) {
+        (AmountUnit::Minor, Some(amount)) if !amount.fract().is_zero() =>
+            Err(TxError::FractionalAmount { line: parsed.0.line, amount }),
+        _ => Ok(parsed),
+    }
+}
+
+/// Returns `false` for a record that could not be parsed, so it
+/// is skipped, unless `strict` is set. The input could not be read
+/// at all on an I/O error, e.g. a failed download, which is kept.
+pub(crate) fn keep(parsed: &Parsed, strict: bool) -> bool {
+    match parsed {
+        Err(TxError::Parse { source, .. }) if source.is_io_error() => true,
+        Err(e) if !strict => { debug!("Skipping malformed record: {}", e); false },
+        _ => true
+    }
+}
+
+/// Returns `false` for a transaction of a client that is not selected
+/// in `Options::clients`, so it is skipped.
+pub(crate) fn selected(parsed: &Parsed, clients: Option<&ClientFilter>) -> bool {
+    match (parsed, clients) {
+        (Ok((_, txn)), Some(clients)) => clients.contains(txn.client_id),
+        _ => true,
+    }
+}
+
+/// Returns `TxError::Parse` with the line, and the name of the
+/// offending field if known.
+pub(crate) fn parse_error(source: csv::Error, headers: Option<&csv::StringRecord>) -> TxError {
+    let line = source.position().map_or(0, |pos| pos.line());
+    let field = match source.kind() {
+        csv::ErrorKind::Deserialize { err, .. } =>
+            err.field()
+                .and_then(|i| headers.and_then(|h| h.get(i as usize)))
+                .map(String::from),
+        _ => None
+    };
+    TxError::Parse { line, field, source }
+}
+
+/// Numbers already parsed transactions from 1, in place of a line.
+fn numbered<I>(txns: I) -> impl Iterator<Item = Parsed>
+where
+    I: Iterator<Item = Transaction>,
+{
+    (1..).map(Line::from).zip(txns).map(Ok)
+}
+
+/// Forwards the transactions to the shard workers, and returns the
+/// accounts ordered by client id once all workers are done, so the output
+/// does not depend on which worker finishes first. The rejected
+/// transactions are only collected if `options.report` is set.
+pub(crate) async fn process<I>(txns: I, options: &Options) -> Result<(Vec<Account>, Report), TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    process_with(&pool(threads(options))?, txns, options).await
+}
+
+/// Like `process`, but runs the workers and the reading on `spawner`.
+pub(crate) async fn process_with<I>(spawner: &impl Spawn, txns: I, options: &Options) -> Result<(Vec<Account>, Report), TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    let (ledgers, report) = settle(spawner, txns, options, HashMap::new()).await?;
+    Ok((ledgers.into_iter().flat_map(Ledger::into_accounts).collect(), report))
+}
+
+/// Like `process`, but starts from the state of `engine` instead of
+/// from empty accounts, and returns the new state, which applies the
+/// transactions with `options` from now on.
+async fn resume<I>(txns: I, options: &Options, engine: Engine) -> Result<(Engine, Report), TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    let (ledgers, report) = settle(&pool(threads(options))?, txns, options, engine.ledgers).await?;
+    let ledgers = ledgers.into_iter().map(|ledger| (ledger.account.client_id, ledger)).collect();
+    Ok((Engine { ledgers, options: options.clone(), ..Engine::default() }, report))
+}
+
+/// Forwards the transactions to the shard workers spawned on `spawner`,
+/// seeded with the given ledgers, and returns the ledgers of all clients
+/// once all workers are done, ordered by client id. The `Stats` of the
+/// `Report` count all the ledgers.
+async fn settle<I>( spawner: &impl Spawn
+                  , txns:    I
+                  , options: &Options
+                  , ledgers: Ledgers
+                  ) -> Result<(Vec<Ledger>, Report), TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    let span = info_span!("settle");
+    let owners = (!options.allow_client_mismatch || options.global_unique_tx)
+        .then(|| Owners::of(ledgers.values(), state_budget(options)))
+        .transpose()?;
+    let (workers, sender) = span.in_scope(|| spawn(spawner, txns, options, ledgers, owners))?;
+    let results = future::join_all(workers).instrument(span.clone()).await;
+    let sent = sender.instrument(span).await?;
+    if let Some(threshold) = &options.max_errors {
+        threshold.check(true)?;
+    }
+
+    let (mut ledgers, mut report) =
+        results.into_iter().fold(
+            (vec![], sent),
+            |(mut ledgers, mut report), (shard_ledgers, shard_report)| {
+                ledgers.extend(shard_ledgers.into_values());
+                report.rejections.extend(shard_report.rejections);
+                report.stats.merge(shard_report.stats);
+                (ledgers, report)
+            });
+    ledgers.sort_by_key(|ledger| ledger.account.client_id);
+    report.rejections.sort_by_key(|r: &Rejection| (r.file, r.line));
+    report.stats.count_ledgers(&ledgers);
+    Ok((ledgers, report))
+}
+
+/// Forwards the transactions to the shard workers, and returns a
+/// `Stream` that yields the accounts of a shard as soon as its worker
+/// is done.
+async fn stream<I>(txns: I) -> Result<impl Stream<Item = Account>, TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    let options = Options::default();
+    let (workers, sender) = spawn(&pool(threads(&options))?, txns, &options, HashMap::new(), Some(Owners::default()))?;
+    sender.await?;
+    Ok(workers.into_iter()
+        .collect::<FuturesUnordered<RemoteHandle<Settled>>>()
+        .flat_map(|(ledgers, _)| futures::stream::iter(ledgers.into_values().flat_map(Ledger::into_accounts))))
+}
+
+/// Spawns a worker for every shard of the clients on `spawner`, starting
+/// from their ledgers in `ledgers`, and the reading of the transactions,
+/// which forwards them to the workers. Returns the handles of the
+/// workers and of the reading.
+#[allow(clippy::type_complexity)]
+fn spawn<I>( spawner: &impl Spawn
+           , txns:    I
+           , options: &Options
+           , ledgers: Ledgers
+           , owners:  Option<Owners>
+           ) -> Result<(Vec<RemoteHandle<Settled>>, RemoteHandle<Result<Report, TxError>>), TxError>
+where
+    I: Iterator<Item = Parsed> + Send + 'static,
+{
+    let shards = threads(options);
+    let capacity = capacity(options, shards);
+    let mut seeded: Vec<Ledgers> = (0..shards).map(|_| HashMap::new()).collect();
+    ledgers.into_iter().for_each(|(client_id, ledger)| { seeded[shard(client_id, shards)].insert(client_id, ledger); });
+    let (senders, receivers): (Vec<Sender<Job>>, Vec<Receiver<Job>>) = (0..shards).map(|_| mpsc::channel(capacity)).unzip();
+    let workers =
+        seeded.into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(shard, (ledgers, rx))| {
+                let options = options.clone();
+                let span = info_span!("to_shard", shard);
+                spawner.spawn_with_handle(async move { to_shard(ledgers, rx, &options).await }.instrument(span))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+    let sender = spawner.spawn_with_handle(send(txns, senders, owners, options.clone()).instrument(info_span!("send")))?;
+    Ok((workers, sender))
+}
+
+/// Returns the number of threads, and of shards the clients are split
+/// into, which is one per core unless `options.threads` is set.
+fn threads(options: &Options) -> usize {
+    options.threads
+        .filter(|&threads| threads > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get))
+}
+
+/// Returns the number of transactions buffered for the worker of
+/// each of the `shards`, which is `options.capacity` bounded by their
+/// share of `options.max_memory`, but at least one.
+pub(crate) fn capacity(options: &Options, shards: usize) -> usize {
+    let capacity = options.capacity.unwrap_or(CAPACITY);
+    match options.max_memory {
+        Some(bytes) => capacity.min(bytes / BUFFERED_SHARE / shards / std::mem::size_of::<Job>()).max(1),
+        None => capacity,
+    }
+}
+
+/// Returns the `ThreadPool` with the given number of threads. It is
+/// created on first use and shared by all later runs in the process.
+fn pool(threads: usize) -> Result<ThreadPool, TxError> {
+    static POOLS: OnceLock<Mutex<HashMap<usize, ThreadPool>>> = OnceLock::new();
+    let mut pools = POOLS.get_or_init(Mutex::default).lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = ThreadPool::builder().pool_size(threads).name_prefix("txreader-").create()?;
+    pools.insert(threads, pool.clone());
+    Ok(pool)
+}
+
+/// Returns the shard of a client among `shards`.
+fn shard(client_id: u16, shards: usize) -> usize {
+    usize::from(client_id) % shards
+}
+
+/// Reads the transactions one by one and forwards every `Transaction` to
+/// the worker of the shard of its client. The first time a client id is
+/// seen, its ledger is opened in the worker. Stops at the first error.
+///
+/// If the clients of the transactions are given in `owners`, a dispute,
+/// resolve or chargeback that only refers to transactions of other clients
+/// is rejected here, since its worker can not know about it, and so is a
+/// deposit or withdrawal that reuses the id of another client's if
+/// `options.global_unique_tx` is set. Returns the `Stats` of the
+/// transactions read, together with these rejections, which are only
+/// kept if `options.report` is set.
+async fn send( txns:       impl Iterator<Item = Parsed>
+             , mut shards: Vec<Sender<Job>>
+             , mut owners: Option<Owners>
+             , options:    Options
+             ) -> Result<Report, TxError> {
+    let mut seen: HashSet<u16> = HashSet::new();
+    let mut sent = Report::default();
+    for parsed in txns {
+        let (line, txn) = parsed?;
+        if let Some(threshold) = &options.max_errors {
+            threshold.count_row();
+            threshold.check(false)?;
+        }
+        let txn = txn.in_unit(options.amounts);
+        sent.stats.count_read(&txn);
+        let client_id = txn.client_id;
+        if seen.insert(client_id) {
+            forward(channel(&mut shards, client_id), Job::Open(client_id)).await;
+        }
+        if let Some(owners) = owners.as_mut() {
+            if let Err(reason) = owners.check(&txn, &options)? {
+                sent.reject(line, options.keeps_rejected().then_some(txn), reason, &options);
+                continue;
+            }
+        }
+        if txn.kind == Transfer {
+            // A transfer between clients of different shards is split
+            // into a debit and a credit leg, which both workers apply
+            // together
+            match txn.credited() {
+                Ok(to_client) if shard(to_client, shards.len()) != shard(client_id, shards.len()) => {
+                    let (debit, credit) = Leg::pair();
+                    forward(channel(&mut shards, client_id), Job::Apply(line, txn.clone(), Some(debit))).await;
+                    forward(channel(&mut shards, to_client), Job::Apply(line, txn, Some(credit))).await;
+                },
+                Ok(_) => forward(channel(&mut shards, client_id), Job::Apply(line, txn, None)).await,
+                Err(reason) => sent.reject(line, options.keeps_rejected().then_some(txn), reason, &options),
+            }
+            continue;
+        }
+        forward(channel(&mut shards, client_id), Job::Apply(line, txn, None)).await;
+    }
+    Ok(sent)
+}
+
+/// Returns the channel to the worker of the shard of a client.
+fn channel(shards: &mut [Sender<Job>], client_id: u16) -> &mut Sender<Job> {
+    let shard = shard(client_id, shards.len());
+    &mut shards[shard]
+}
+
+/// Sends a job to the worker of a shard, and waits while its channel
+/// is full, so that reading never gets far ahead of the workers.
+async fn forward(sender: &mut Sender<Job>, job: Job) {
+    if let Err(e) = sender.send(job).await {
+        debug!("Worker is gone, dropping transaction: {}", e);
+    }
+}
+
+/// Returns the `Ledger` of a client, which is created the first
+/// time the client is seen.
+fn ledger(ledgers: &mut Ledgers, client_id: u16) -> &mut Ledger {
+    ledgers.entry(client_id).or_insert_with(|| Ledger::new(client_id))
+}
+
+/// Applies a transfer to the ledgers of both clients, or to neither
+/// if either side can not be applied.
+fn transfer(ledgers: &mut Ledgers, txn: Transaction, options: &Options) -> Result<(), (TxError, Transaction)> {
+    let client_id = txn.client_id;
+    let to_client = match txn.credited() {
+        Ok(to_client) => to_client,
+        Err(e) => return Err(ledger(ledgers, client_id).rejected(e, txn)),
+    };
+    let restored = ledger(ledgers, client_id).restore(txn.tx_id)
+        .and_then(|()| ledger(ledgers, to_client).restore(txn.tx_id));
+    if let Err(e) = restored {
+        return Err(ledger(ledgers, client_id).rejected(e, txn));
+    }
+    let debited = ledger(ledgers, client_id).leg(&txn, Side::Debit, options);
+    let credited = ledger(ledgers, to_client).leg(&txn, Side::Credit, options);
+    match (debited, credited) {
+        (Ok(debited), Ok(credited)) => {
+            ledger(ledgers, to_client).commit(credited, txn.clone());
+            ledger(ledgers, client_id).commit(debited, txn);
+            Ok(())
+        },
+        (Err(e), _) => Err(ledger(ledgers, client_id).rejected(e, txn)),
+        (_, Err(e)) => Err(ledger(ledgers, to_client).rejected(e, txn)),
+    }
+}
+
+/// Applies an ordered stream of jobs to the ledgers of a shard
+/// of clients, and returns them together with the rejections
+/// counted per reason. The rejected transactions are returned
+/// as well if `options.report` is set. A transfer with a client
+/// of another shard waits for its worker, without blocking a
+/// thread.
+async fn to_shard( mut ledgers: Ledgers
+                 , jobs:        impl Stream<Item = Job>
+                 , options:     &Options
+                 ) -> Settled {
+    futures::pin_mut!(jobs);
+    let mut rejected = Report::default();
+    let mut spill = Spill::new(options);
+    while let Some(job) = jobs.next().await {
+        spill.check(&mut ledgers);
+        let (line, txn, leg) = match job {
+            Job::Open(client_id) => { ledger(&mut ledgers, client_id); continue; },
+            Job::Apply(line, txn, leg) => (line, txn, leg),
+        };
+        let applied = match leg {
+            Some(leg) => ledger(&mut ledgers, leg.client(&txn)).apply_leg(txn, leg, options).await,
+            None if txn.kind == Transfer => transfer(&mut ledgers, txn, options),
+            None => ledger(&mut ledgers, txn.client_id).apply(txn, options),
+        };
+        if let Err((reason, txn)) = applied {
+            rejected.reject(line, options.keeps_rejected().then_some(txn), reason, options);
+        }
+    }
+    (ledgers, rejected)
+}
+
+/// Number of jobs a shard, or transactions an `Engine`, applies between
+/// two checks of the memory its handled transactions take.
+const SPILL_EVERY: usize = 1024;
+
+/// Returns the share of `Options::max_memory` of the state of the
+/// handled transactions of every shard, and of the `Owners` of the
+/// transactions, which split what the buffered transactions leave of it.
+fn state_budget(options: &Options) -> Option<usize> {
+    options.max_memory.map(|bytes| (bytes - bytes / BUFFERED_SHARE) / (threads(options) + 1))
+}
+
+/// Spills the state of the handled transactions of a shard, or of an
+/// `Engine`, to a temporary file whenever it takes more than its share
+/// of `Options::max_memory`.
+#[derive(Debug)]
+struct Spill {
+    budget: Option<usize>,
+    file:   Option<Arc<Mutex<SpillFile>>>,
+    jobs:   usize,
+}
+
+impl Spill {
+    fn new(options: &Options) -> Spill {
+        Spill {
+            budget: state_budget(options),
+            file:   None,
+            jobs:   0,
+        }
+    }
+
+    /// Counts a job, and spills the ledgers every `SPILL_EVERY` jobs
+    /// if they take more than the budget. A failure to spill is
+    /// logged, and the ledgers are then kept in memory.
+    fn check(&mut self, ledgers: &mut Ledgers) {
+        self.jobs += 1;
+        let budget = match self.budget {
+            Some(budget) if self.jobs.is_multiple_of(SPILL_EVERY) => budget,
+            _ => return,
+        };
+        let in_memory: usize = ledgers.values().map(|ledger| ledger.handled.in_memory()).sum();
+        if in_memory <= budget {
+            return;
+        }
+        if let Err(e) = self.spill(ledgers) {
+            warn!("Could not spill handled transactions to disk: {}", e);
+        }
+    }
+
+    fn spill(&mut self, ledgers: &mut Ledgers) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => self.file.insert(SpillFile::new()?),
+        };
+        debug!("Spilling the handled transactions of {} clients to disk", ledgers.len());
+        ledgers.values_mut().try_for_each(|ledger| ledger.handled.spill(file))
+    }
+}
+
+/// Handles a `Transaction` and updates the client's
+/// `Account`, given the `TxState` of the handled transactions
+/// with the same id, if any. The `amount` is rounded with
+/// `options.rounding`, a dispute is only allowed if
+/// `options.dispute_policy` allows it, a deposit or withdrawal
+/// that reuses an id is handled with `options.duplicates`, and a
+/// locked account only accepts what `options.locked_policy` allows.
+/// A dispute, resolve or chargeback must be in the currency of the
+/// transaction it refers to.
+pub(crate) fn handle_txn( account:  &mut Account
+                        , history:  Option<&TxState>
+                        , txn:      &Transaction
+                        , options:  &Options
+                        ) -> Result<(), TxError> {
+    let rounding = &options.rounding;
+    if txn.refers() && account.locked && options.locked_policy == LockedPolicy::Frozen {
+        return Err(TxError::AccountLocked(txn.client_id));
+    }
+    let initial_txn = history.and_then(|state| state.original.as_ref());
+    if txn.disputable() && initial_txn.is_some() {
+        check_duplicate(txn, options)?;
+    }
+    if txn.refers() && initial_txn.is_some_and(|initial| initial.currency != txn.currency) {
+        return Err(TxError::CurrencyMismatch(txn.tx_id));
+    }
+    match *txn {
+        Transaction{ kind: Deposit, client_id, tx_id, amount: Some(amount), .. } => {
+            (!account.locked).then_some(()).ok_or(TxError::AccountLocked(client_id))?;
+            amount.is_sign_positive().then_some(()).ok_or(TxError::NegativeAmount(tx_id))?;
+            // A deposit is a credit to the client's asset account,
+            // meaning it should increase the available and total
+            // funds of the client account
+            account.adjust(tx_id, rounding.round(amount), Decimal::ZERO, rounding.round(amount))?;
+            Ok(())
+        },
+        Transaction{ kind: Withdrawal, client_id, tx_id, amount: Some(amount), .. } => {
+            (!account.locked).then_some(()).ok_or(TxError::AccountLocked(client_id))?;
+            amount.is_sign_positive().then_some(()).ok_or(TxError::NegativeAmount(tx_id))?;
+            // If a client does not have sufficient available funds
+            // the withdrawal should fail and the total amount of
+            // funds should not change
+            (account.available >= amount).then_some(()).ok_or(TxError::InsufficientFunds(tx_id))?;
+            // A withdraw is a debit to the client's asset account,
+            // meaning it should decrease the available and total
+            // funds of the client account
+            account.adjust(tx_id, -rounding.round(amount), Decimal::ZERO, -rounding.round(amount))?;
+            Ok(())
+        },
+        Transaction{ kind: Dispute, tx_id, .. } => {
+            // Notice that a dispute does not state the amount disputed.
+            // Instead a dispute references the transaction that is
+            // disputed by ID.
+            let state = history.ok_or(TxError::UnknownTx(tx_id))?;
+            // If the tx specified by the dispute doesn't exist you can
+            // ignore it and assume this is an error on our partners side.
+            match (state.is_under_dispute(), initial_txn) {
+                (false, Some(&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
+                    // A dispute represents a client's claim that a
+                    // transaction was erroneous and should be reversed.
+                    // The transaction shouldn't be reversed yet but
+                    // the associated funds should be held. This means
+                    // that the clients available funds should decrease
+                    // by the amount disputed, their held funds should
+                    // increase by the amount disputed, while their
+                    // total funds should remain the same.
+                    account.adjust(tx_id, -rounding.round(amount), rounding.round(amount), Decimal::ZERO)?;
+                    Ok(())
+                },
+                (false, Some(&Transaction{ kind: Withdrawal, .. })) if options.dispute_policy == DisputePolicy::DepositsOnly =>
+                    Err(TxError::WithdrawalDispute(tx_id)),
+                (false, Some(&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
+                    // NOTE: Assumes a dispute on a withdrawal temporarily
+                    // puts funds into the client's held funds, unless the
+                    // dispute policy only allows disputes on deposits.
+                    account.adjust(tx_id, Decimal::ZERO, rounding.round(amount), rounding.round(amount))?;
+                    Ok(())
+                },
+                (true, _) => Err(TxError::AlreadyDisputed(tx_id)),
+                _ => Err(TxError::UnknownTx(tx_id))
+            }
+        },
+        Transaction{ kind: Resolve, tx_id, .. } => {
+            // Like disputes, resolves do not specify an amount. Instead
+            // they refer to a transaction that was under dispute by ID.
+            let state = history.ok_or(TxError::UnknownTx(tx_id))?;
+            // If the tx specified doesn't exist, or the tx isn't under
+            // dispute, you can ignore the resolve and assume this is an
+            // error on our partner's side.
+            match (state.is_under_dispute(), initial_txn) {
+                (true, Some(&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
+                    // A resolve represents a resolution to a dispute,
+                    // releasing the associated held funds. Funds that
+                    // were previously disputed are no longer disputed.
+                    // This means that the clients held funds should
+                    // decrease by the amount no longer disputed, their
+                    // available funds should increase by the amount no
+                    // longer disputed, and their total funds should
+                    // remain the same.
+                    account.adjust(tx_id, rounding.round(amount), -rounding.round(amount), Decimal::ZERO)?;
+                    Ok(())
+                },
+                (true, Some(&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
+                    // NOTE: Assumes a resolve removes the temporarily
+                    // increased funds from the client's held funds.
+                    account.adjust(tx_id, Decimal::ZERO, -rounding.round(amount), -rounding.round(amount))?;
+                    Ok(())
+                },
+                (false, _) => Err(TxError::NotDisputed(tx_id)),
+                _ => Err(TxError::UnknownTx(tx_id))
+            }
+        },
+        Transaction{ kind: Chargeback, tx_id, .. } => {
+            // Like a dispute and a resolve a chargeback refers to the
+            // transaction by ID (tx) and does not specify an amount.
+            let state = history.ok_or(TxError::UnknownTx(tx_id))?;
+            // Like a resolve, if the tx specified doesn't exist, or
+            // the tx isn't under dispute, you can ignore chargeback
+            // and assume this is an error on our partner's side.
+            match (state.is_under_dispute(), initial_txn) {
+                (true, Some(&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
+                    // A chargeback is the final state of a dispute and
+                    // represents the client reversing a transaction.
+                    // Funds that were held have now been withdrawn.
+                    // This means that the clients held funds and total
+                    // funds should decrease by the amount previously
+                    // disputed. If a chargeback occurs the client's
+                    // account should be immediately frozen.
+                    account.adjust(tx_id, Decimal::ZERO, -rounding.round(amount), -rounding.round(amount))?;
+                    account.locked = true;
+                    Ok(())
+                },
+                (true, Some(&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
+                    // NOTE: Assumes a chargeback to a withdrawal reverses
+                    // a withdrawal, and puts the temporarily held funds
+                    // back to the client available funds.
+                    account.adjust(tx_id, rounding.round(amount), -rounding.round(amount), Decimal::ZERO)?;
+                    account.locked = true;
+                    Ok(())
+                },
+                (false, _) => Err(TxError::NotDisputed(tx_id)),
+                _ => Err(TxError::UnknownTx(tx_id))
+            }
+        },
+        Transaction{ kind: Transfer, client_id, tx_id, amount: Some(amount), .. } => {
+            (!account.locked).then_some(()).ok_or(TxError::AccountLocked(client_id))?;
+            amount.is_sign_positive().then_some(()).ok_or(TxError::NegativeAmount(tx_id))?;
+            // The debit side of a transfer is a withdrawal from the
+            // account of the client, while the credit side is handled
+            // by `handle_credit`
+            (account.available >= amount).then_some(()).ok_or(TxError::InsufficientFunds(tx_id))?;
+            account.adjust(tx_id, -rounding.round(amount), Decimal::ZERO, -rounding.round(amount))?;
+            Ok(())
+        },
+        Transaction{ kind: Unlock, .. } => {
+            // An unlock clears the lock of the account after a manual
+            // review, so it accepts deposits and withdrawals again.
+            account.locked = false;
+            Ok(())
+        },
+        Transaction{ kind: Adjustment, tx_id, amount: Some(amount), .. } => {
+            // An adjustment is a credit or debit by an admin. Unlike a
+            // withdrawal, a debit is not checked against the available
+            // funds, and it applies to a locked account as well.
+            account.adjust(tx_id, rounding.round(amount), Decimal::ZERO, rounding.round(amount))?;
+            Ok(())
+        },
+        Transaction{ tx_id, .. } => Err(TxError::MissingAmount(tx_id))
+    }
+}
+
+/// Handles the credit side of a transfer, which is a deposit to the
+/// account of `txn.to_client`.
+pub(crate) fn handle_credit(account: &mut Account, txn: &Transaction, options: &Options) -> Result<(), TxError> {
+    let amount = options.rounding.round(txn.amount.ok_or(TxError::MissingAmount(txn.tx_id))?);
+    (!account.locked).then_some(()).ok_or(TxError::AccountLocked(account.client_id))?;
+    account.adjust(txn.tx_id, amount, Decimal::ZERO, amount)
+}
+
+/// Returns `TxError::DuplicateTx` for a deposit or withdrawal that reuses
+/// the id of an earlier transaction, or only logs a warning if
+/// `options.duplicates` is `DuplicatePolicy::Warn`.
+pub(crate) fn check_duplicate(txn: &Transaction, options: &Options) -> Result<(), TxError> {
+    match options.duplicates {
+        DuplicatePolicy::Reject => Err(TxError::DuplicateTx(txn.tx_id)),
+        DuplicatePolicy::Warn => {
+            warn!("Transaction {} of client {} reuses the id of an earlier transaction", txn.tx_id, txn.client_id);
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use common_macros::hash_map;
+    use crate::tx::*;
+    use tempfile::NamedTempFile;
+    use std::io::Write;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_read_with() -> Result<(), anyhow::Error> {
+        let path = &std::path::PathBuf::from("transactions_simple.csv");
+        let mut result = Vec::new();
+        block_on(read_with(&mut result, path))?;
+        let mut lines = std::str::from_utf8(&result)?.lines();
+        let expected = [ "client,available,held,total,locked"
+                       , "1,1.4996,0.0,1.4996,false"
+                       , "2,2,0.0,2,false"
+                       , "4,0.0,0.0,0.0,false"
+                       , "5,0.0,0.0,0.0,false"
+                       ];
+        assert!(lines.all(|l| expected.contains(&l)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_accounts_from_reader() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let reader = io::Cursor::new("type,client,tx,amount
+                                      deposit,1,1,10.0
+                                      withdrawal,1,2,2.5
+                                      bad line
+                                      deposit,2,3,1.0");
+
+        /*
+         * When
+         */
+        let accounts = block_on(accounts_from_reader(reader))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
+                                          , available: dec!(7.5)
+                                          , held:      dec!(0)
+                                          , total:     dec!(7.5)
+                                          , locked:    false
+                                          }
+                                 , Account{ client_id: 2
+                                          , currency:  None
+                                          , available: dec!(1.0)
+                                          , held:      dec!(0)
+                                          , total:     dec!(1.0)
+                                          , locked:    false
+                                          }
+                                 ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accounts_from_iter() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let txns = vec![ Transaction{ kind: Deposit,    client_id: 1, tx_id: 1, amount: Some(dec!(10.0)), to_client: None, currency: None }
+                       , Transaction{ kind: Deposit,    client_id: 2, tx_id: 2, amount: Some(dec!(5.0)), to_client: None, currency: None }
+                       , Transaction{ kind: Dispute,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None }
+                       , Transaction{ kind: Chargeback, client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None }
+                       , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 3, amount: Some(dec!(6.0)), to_client: None, currency: None }
+                       ];
+
+        /*
+         * When
+         */
+        let accounts = block_on(accounts_from_iter(txns))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
+                                          , available: dec!(0)
+                                          , held:      dec!(0)
+                                          , total:     dec!(0)
+                                          , locked:    true
+                                          }
+                                 , Account{ client_id: 2
+                                          , currency:  None
+                                          , available: dec!(5.0)
+                                          , held:      dec!(0)
+                                          , total:     dec!(5.0)
+                                          , locked:    false
+                                          }
+                                 ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_stream_from_path() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let path = &std::path::PathBuf::from("transactions_simple.csv");
+
+        /*
+         * When
+         */
+        let stream = block_on(account_stream_from_path(path))?;
+        let mut accounts: Vec<Account> = block_on(stream.collect());
+
+        /*
+         * Then
+         */
+        accounts.sort_by_key(|a| a.client_id);
+        assert_eq!(accounts.iter().map(|a| a.client_id).collect::<Vec<u16>>(), vec![1, 2, 4, 5]);
+        assert_eq!(accounts[0], Account{ client_id: 1
+                                       , currency:  None
+                                       , available: dec!(1.4996)
+                                       , held:      dec!(0)
+                                       , total:     dec!(1.4996)
+                                       , locked:    false
+                                       });
+        Ok(())
+    }
+
+    #[test]
+    fn test_accounts_and_rejections_from_path() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,100
+                        withdrawal,1,2,500
+                        bad line
+                        dispute,2,3,
+                        dispute,1,1,
+                        dispute,1,1,")?;
+        let path = file.path().to_str().unwrap();
+
+        /*
+         * When
+         */
+        let (accounts, rejections) = block_on(accounts_and_rejections_from_path(&std::path::PathBuf::from(path)))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(rejections.iter().map(|r| (r.line, r.txn.tx_id)).collect::<Vec<(u64, u32)>>(), vec![(3, 2), (5, 3), (7, 1)]);
+        assert!(matches!(rejections[0].reason, TxError::InsufficientFunds(2)));
+        assert!(matches!(rejections[1].reason, TxError::UnknownTx(3)));
+        assert!(matches!(rejections[2].reason, TxError::AlreadyDisputed(1)));
+
+        let mut result = Vec::new();
+        block_on(print_rejections_with(&mut result, &rejections));
+        let mut lines = std::str::from_utf8(&result)?.lines();
+        assert_eq!(lines.next(), Some("line,type,client,tx,amount,reason"));
+        assert_eq!(lines.next(), Some("3,withdrawal,1,2,500,insufficient available funds for transaction 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_path_strict() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,100
+                        deposit,x,2,100
+                        deposit,1,3,100")?;
+        let path = std::path::PathBuf::from(file.path().to_str().unwrap());
+
+        /*
+         * When
+         */
+        let lenient = block_on(process_path(&path, &Options::default()));
+        let strict = block_on(process_path(&path, &Options { strict: true, ..Options::default() }));
+
+        /*
+         * Then
+         */
+        assert_eq!(lenient?.0[0].total, dec!(200));
+        match strict {
+            Err(e @ TxError::Parse { line: 3, .. }) =>
+                assert!(e.to_string().starts_with("could not parse line 3, field `client`"), "{}", e),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_json() -> Result<(), Box<dyn std::error::Error>> {
+        let accounts = vec![ Account{ client_id: 1, currency: None, available: dec!(1.5), held: dec!(0), total: dec!(1.5), locked: false } ];
+        let mut result = Vec::new();
+        block_on(print_accounts_json(&mut result, &accounts));
+        assert_eq!(std::str::from_utf8(&result)?, "[{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_jsonl() -> Result<(), Box<dyn std::error::Error>> {
+        let accounts = vec![ Account{ client_id: 1, currency: None,                     available: dec!(1.5), held: dec!(0), total: dec!(1.5), locked: false }
+                           , Account{ client_id: 2, currency: Some(String::from("EUR")), available: dec!(2),   held: dec!(1), total: dec!(3),   locked: true }
+                           ];
+        let mut result = Vec::new();
+        let mut fixed = Vec::new();
+        block_on(print_accounts_jsonl(&mut result, &accounts));
+        block_on(print_fixed_accounts_jsonl(&mut fixed, &accounts[1..]));
+        assert_eq!(std::str::from_utf8(&result)?, "{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}\n\
+                                                   {\"client\":2,\"currency\":\"EUR\",\"available\":\"2\",\"held\":\"1\",\"total\":\"3\",\"locked\":true}\n");
+        assert_eq!(std::str::from_utf8(&fixed)?, "{\"client\":2,\"currency\":\"EUR\",\"available\":\"2.0000\",\"held\":\"1.0000\",\"total\":\"3.0000\",\"locked\":true}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_table() -> Result<(), Box<dyn std::error::Error>> {
+        let accounts = vec![ Account{ client_id: 1,  currency: None, available: dec!(1.5),     held: dec!(0), total: dec!(1.5),     locked: false }
+                           , Account{ client_id: 12, currency: None, available: dec!(-1.2345), held: dec!(2), total: dec!(0.7655), locked: true }
+                           ];
+        let mut result = Vec::new();
+        block_on(print_accounts_table(&mut result, &accounts));
+        let mut lines = std::str::from_utf8(&result)?.lines();
+        assert_eq!(lines.next(), Some("client  available  held   total  locked"));
+        assert_eq!(lines.next(), Some("     1        1.5     0     1.5   false"));
+        assert_eq!(lines.next(), Some("    12    -1.2345     2  0.7655    true"));
+        assert_eq!(lines.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_accounts_table_styled() -> Result<(), Box<dyn std::error::Error>> {
+        let accounts = vec![ Account{ client_id: 1,  currency: None,                    available: dec!(1.5),  held: dec!(0), total: dec!(1.5), locked: false }
+                           , Account{ client_id: 12, currency: None,                    available: dec!(-1.2), held: dec!(2), total: dec!(0.8), locked: true }
+                           , Account{ client_id: 12, currency: Some(String::from("EUR")), available: Decimal::MAX, held: dec!(0), total: Decimal::MAX, locked: false }
+                           , Account{ client_id: 13, currency: Some(String::from("EUR")), available: dec!(1),    held: dec!(0), total: dec!(1),   locked: false }
+                           ];
+        let mut result = Vec::new();
+        block_on(print_accounts_table_styled(&mut result, &accounts[..2], TableStyle { fixed: true, totals: true, colors: false }));
+        let mut lines = std::str::from_utf8(&result)?.lines();
+        assert_eq!(lines.next(), Some("client  available    held   total  locked"));
+        assert_eq!(lines.next(), Some("     1     1.5000  0.0000  1.5000   false"));
+        assert_eq!(lines.next(), Some("    12    -1.2000  2.0000  0.8000    true"));
+        assert_eq!(lines.next(), Some("------  ---------  ------  ------  ------"));
+        assert_eq!(lines.next(), Some(" total     0.3000  2.0000  2.3000       1"));
+        assert_eq!(lines.next(), None);
+        let mut result = Vec::new();
+        block_on(print_accounts_table_styled(&mut result, &accounts, TableStyle { totals: true, ..TableStyle::default() }));
+        let lines = std::str::from_utf8(&result)?.lines().skip(6).collect::<Vec<_>>();
+        assert_eq!(lines, vec![ " total                                      0.3     2                            2.3       1"
+                              , " total       EUR  79228162514264337593543950335     0  79228162514264337593543950335       0"
+                              ]);
+        let mut result = Vec::new();
+        block_on(print_accounts_table_styled(&mut result, &accounts[..2], TableStyle { colors: true, ..TableStyle::default() }));
+        let mut lines = std::str::from_utf8(&result)?.lines().skip(1);
+        assert_eq!(lines.next(), Some("     1        1.5     0    1.5   false"));
+        assert_eq!(lines.next(), Some("\x1b[31m    12\x1b[0m  \x1b[31m     -1.2\x1b[0m  \x1b[33m   2\x1b[0m  \x1b[31m  0.8\x1b[0m  \x1b[31m  true\x1b[0m"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_fixed_accounts() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let accounts = vec![ Account{ client_id: 1, currency: None, available: dec!(2), held: dec!(0.0), total: dec!(2), locked: false }
+                           , Account{ client_id: 2, currency: None, available: dec!(1.4996), held: dec!(-0.5), total: dec!(0.99960), locked: true }
+                           ];
+
+        /*
+         * When
+         */
+        let mut csv = Vec::new();
+        let mut json = Vec::new();
+        block_on(print_fixed_accounts_with(&mut csv, &accounts));
+        block_on(print_fixed_accounts_json(&mut json, &accounts[..1]));
+
+        /*
+         * Then
+         */
+        assert_eq!(std::str::from_utf8(&csv)?, "client,available,held,total,locked\n\
+                                                1,2.0000,0.0000,2.0000,false\n\
+                                                2,1.4996,-0.5000,0.9996,true\n");
+        assert_eq!(std::str::from_utf8(&json)?, "[{\"client\":1,\"available\":\"2.0000\",\"held\":\"0.0000\",\"total\":\"2.0000\",\"locked\":false}]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_rounding() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,1.00005
+                     deposit,2,2,1.00015
+                     deposit,3,3,1.00019";
+        let rounded = |mode, precision| -> Result<Vec<Decimal>, TxError> {
+            let options = Options { rounding: Rounding { mode, precision }, ..Options::default() };
+            let (accounts, _) = block_on(process_reader(io::Cursor::new(input), &options))?;
+            Ok(accounts.iter().map(Account::total).collect())
+        };
+
+        /*
+         * When
+         */
+        let half_even = rounded(RoundingMode::HalfEven, 4)?;
+        let half_up = rounded(RoundingMode::HalfUp, 4)?;
+        let truncate = rounded(RoundingMode::Truncate, 4)?;
+        let cents = rounded(RoundingMode::HalfUp, 2)?;
+
+        /*
+         * Then
+         */
+        assert_eq!(half_even, vec![dec!(1.0000), dec!(1.0002), dec!(1.0002)]);
+        assert_eq!(half_up, vec![dec!(1.0001), dec!(1.0002), dec!(1.0002)]);
+        assert_eq!(truncate, vec![dec!(1.0000), dec!(1.0001), dec!(1.0001)]);
+        assert_eq!(cents, vec![dec!(1.00), dec!(1.00), dec!(1.00)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_engine_overflow() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        engine.apply(Transaction::deposit(1, 1, Decimal::MAX)?)?;
+        engine.apply(Transaction::deposit(2, 2, dec!(1))?)?;
+
+        /*
+         * When
+         */
+        let overflow = engine.apply(Transaction::deposit(1, 3, dec!(1))?);
+        let withdrawal = engine.apply(Transaction::withdrawal(1, 4, dec!(1))?);
+
+        /*
+         * Then
+         */
+        assert!(matches!(overflow, Err(TxError::Overflow(3))));
+        assert!(withdrawal.is_ok());
+        assert_eq!(engine.account(1).map(Account::total), Some(Decimal::MAX - dec!(1)));
+        assert!(engine.is_saturated(1));
+        assert!(!engine.is_saturated(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_policy_deposits_only() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let options = Options { dispute_policy: DisputePolicy::DepositsOnly, ..Options::default() };
+        let mut engine = Engine::new().with_options(options);
+        engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+        engine.apply(Transaction::withdrawal(1, 2, dec!(4))?)?;
+
+        /*
+         * When
+         */
+        let withdrawal = engine.apply(Transaction::dispute(1, 2));
+        let deposit = engine.apply(Transaction::dispute(1, 1));
+
+        /*
+         * Then
+         */
+        assert!(matches!(withdrawal, Err(TxError::WithdrawalDispute(2))));
+        assert!(deposit.is_ok());
+        assert_eq!(engine.account(1).map(|a| (a.available(), a.held(), a.total())), Some((dec!(-4), dec!(10), dec!(6))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_locked_policy_frozen() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut allowed = Engine::new();
+        let mut frozen = Engine::new().with_options(Options { locked_policy: LockedPolicy::Frozen, ..Options::default() });
+        for engine in [&mut allowed, &mut frozen] {
+            engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+            engine.apply(Transaction::deposit(1, 2, dec!(5))?)?;
+            engine.apply(Transaction::dispute(1, 1))?;
+            engine.apply(Transaction::chargeback(1, 1))?;
+        }
+
+        /*
+         * When
+         */
+        let allowed_dispute = allowed.apply(Transaction::dispute(1, 2));
+        let frozen_dispute = frozen.apply(Transaction::dispute(1, 2));
+
+        /*
+         * Then
+         */
+        assert!(allowed_dispute.is_ok());
+        assert!(matches!(frozen_dispute, Err(TxError::AccountLocked(1))));
+        assert_eq!(allowed.account(1).map(Account::held), Some(dec!(5)));
+        assert_eq!(frozen.account(1).map(Account::held), Some(dec!(0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     dispute,2,1,
+                     dispute,1,1,";
+        let relaxed = Options { report: true, allow_client_mismatch: true, ..Options::default() };
+        let mut engine = Engine::new();
+        engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+
+        /*
+         * When
+         */
+        let (accounts, strict) = block_on(process_reader(io::Cursor::new(input), &Options { report: true, ..Options::default() }))?;
+        let (_, relaxed) = block_on(process_reader(io::Cursor::new(input), &relaxed))?;
+        let applied = engine.apply(Transaction::dispute(2, 1));
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(Account::held).collect::<Vec<Decimal>>(), vec![dec!(10), dec!(0)]);
+        assert!(matches!(strict.rejections[..], [Rejection { line: 3, reason: TxError::ClientMismatch { tx: 1, client: 2, owner: 1 }, .. }]));
+        assert!(matches!(relaxed.rejections[..], [Rejection { line: 3, reason: TxError::UnknownTx(1), .. }]));
+        assert!(matches!(applied, Err(TxError::ClientMismatch { tx: 1, client: 2, owner: 1 })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_tx() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,1,1,5
+                     deposit,2,1,7
+                     dispute,1,1,";
+        let warn = Options { duplicates: DuplicatePolicy::Warn, ..Options::default() };
+        let global = Options { report: true, global_unique_tx: true, ..Options::default() };
+        let mut engine = Engine::new().with_options(global.clone());
+        engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+
+        /*
+         * When
+         */
+        let (rejected, report) = block_on(process_reader(io::Cursor::new(input), &Options { report: true, ..Options::default() }))?;
+        let (warned, _) = block_on(process_reader(io::Cursor::new(input), &warn))?;
+        let (unique, unique_report) = block_on(process_reader(io::Cursor::new(input), &global))?;
+        let applied = engine.apply(Transaction::deposit(2, 1, dec!(7))?);
+
+        /*
+         * Then
+         */
+        assert_eq!(rejected.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(10), dec!(7)]);
+        assert!(matches!(report.rejections[..], [Rejection { line: 3, reason: TxError::DuplicateTx(1), .. }]));
+        assert_eq!(warned.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(15), dec!(7)]);
+        assert_eq!(warned[0].held(), dec!(10));
+        assert_eq!(unique.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(10), dec!(0)]);
+        assert!(matches!(unique_report.rejections[..], [ Rejection { line: 3, reason: TxError::DuplicateTx(1), .. }
+                                                       , Rejection { line: 4, reason: TxError::DuplicateTx(1), .. }
+                                                       ]));
+        assert!(matches!(applied, Err(TxError::DuplicateTx(1))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_owners_of_rejected_txns() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,5
+                     withdrawal,1,2,10
+                     deposit,2,2,3
+                     dispute,3,2,";
+        let options = Options { report: true, global_unique_tx: true, threads: Some(2), ..Options::default() };
+        let mut engine = Engine::new().with_options(options.clone());
+
+        /*
+         * When
+         */
+        let (accounts, report) = block_on(process_reader(io::Cursor::new(input), &options))?;
+        let rejected = txns_from_reader(io::Cursor::new(input), &options)
+            .map(|parsed| parsed.map(|(_, txn)| engine.apply(txn).err()))
+            .collect::<Result<Vec<_>, TxError>>()?;
+
+        /*
+         * Then
+         */
+        let totals = |accounts: Vec<&Account>| accounts.iter().map(|a| (a.client_id(), a.total())).collect::<Vec<_>>();
+        let mut applied: Vec<&Account> = engine.accounts().collect();
+        applied.sort_by_key(|a| a.client_id());
+        assert_eq!(totals(accounts.iter().collect()), vec![(1, dec!(5)), (2, dec!(0)), (3, dec!(0))]);
+        assert_eq!(totals(applied), totals(accounts.iter().collect()));
+        assert!(matches!(report.rejections[..], [ Rejection { line: 3, reason: TxError::InsufficientFunds(2), .. }
+                                                , Rejection { line: 4, reason: TxError::DuplicateTx(2), .. }
+                                                , Rejection { line: 5, reason: TxError::ClientMismatch { tx: 2, client: 3, owner: 1 }, .. }
+                                                ]));
+        assert!(matches!(rejected[..], [ None
+                                       , Some(TxError::InsufficientFunds(2))
+                                       , Some(TxError::DuplicateTx(2))
+                                       , Some(TxError::ClientMismatch { tx: 2, client: 3, owner: 1 })
+                                       ]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_transfer() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to
+                     deposit,1,1,10,
+                     deposit,3,2,1,
+                     transfer,1,3,4,2
+                     transfer,2,4,5,1
+                     transfer,2,5,4,1
+                     dispute,3,2,,
+                     chargeback,3,2,,
+                     transfer,1,6,1,3
+                     transfer,1,7,1,
+                     transfer,1,8,1,1";
+        let options = Options { report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, report) = block_on(process_reader(io::Cursor::new(input), &options))?;
+        let mut engine = Engine::new();
+        let applied = txns_from_reader(io::Cursor::new(input), &Options::default())
+            .map(|parsed| engine.apply(parsed?.1))
+            .collect::<Vec<Result<(), TxError>>>();
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(10), dec!(0), dec!(0)]);
+        assert!(matches!(report.rejections[..], [ Rejection { line: 5, reason: TxError::InsufficientFunds(4), .. }
+                                                , Rejection { line: 9, reason: TxError::AccountLocked(3), .. }
+                                                , Rejection { line: 10, reason: TxError::InvalidTransfer(7), .. }
+                                                , Rejection { line: 11, reason: TxError::InvalidTransfer(8), .. }
+                                                ]));
+        assert_eq!(engine.into_accounts(), accounts);
+        assert_eq!(applied.iter().filter(|a| a.is_err()).count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_limited() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,2,x,5
+                     deposit,2,2,5
+                     withdrawal,1,3,4
+                     deposit,1,4,3";
+
+        /*
+         * When
+         */
+        let totals = |options: Options| -> Result<Vec<Decimal>, TxError> {
+            let (accounts, _) = block_on(process_reader(io::Cursor::new(input), &options))?;
+            Ok(accounts.iter().map(Account::total).collect())
+        };
+
+        /*
+         * Then
+         */
+        assert_eq!(totals(Options { limit: Some(2), ..Options::default() })?, vec![dec!(10), dec!(5)]);
+        assert_eq!(totals(Options { until_tx: Some(3), ..Options::default() })?, vec![dec!(6), dec!(5)]);
+        assert_eq!(totals(Options { limit: Some(1), until_tx: Some(3), ..Options::default() })?, vec![dec!(10)]);
+        assert_eq!(totals(Options::default())?, vec![dec!(9), dec!(5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_transfers_between_many_clients() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let clients: u16 = 64;
+        let deposits = (1..=clients).map(|c| format!("deposit,{},{},100,\n", c, c));
+        let transfers = (1..=1000u32).map(|t| format!("transfer,{},{},{},{}\n", t % 64 + 1, 1000 + t, t % 7, (t * 13) % 64 + 1));
+        let input = std::iter::once(String::from("type,client,tx,amount,to\n"))
+            .chain(deposits)
+            .chain(transfers)
+            .collect::<String>();
+
+        /*
+         * When
+         */
+        let (accounts, _) = block_on(process_reader(io::Cursor::new(input.clone()), &Options::default()))?;
+        let (bounded, _) = block_on(process_reader(io::Cursor::new(input.clone()), &Options { capacity: Some(1), ..Options::default() }))?;
+        let (sharded, _) = block_on(process_reader(io::Cursor::new(input.clone()), &Options { threads: Some(4), ..Options::default() }))?;
+        let mut engine = Engine::new();
+        txns_from_reader(io::Cursor::new(input), &Options::default())
+            .for_each(|parsed| { let _ = engine.apply(parsed.unwrap().1); });
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(Account::total).sum::<Decimal>(), dec!(6400));
+        assert_eq!(bounded, accounts);
+        assert_eq!(sharded, accounts);
+        assert_eq!(engine.into_accounts(), accounts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_transfers_across_shards() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let txns = vec![ Transaction::deposit(1, 1, dec!(10))?
+                       , Transaction::transfer(1, 2, 2, dec!(4))?   // shard 1 to shard 0
+                       , Transaction::transfer(2, 4, 3, dec!(1))?   // within shard 0
+                       , Transaction::transfer(2, 1, 4, dec!(100))? // insufficient funds
+                       , Transaction::transfer(4, 3, 5, dec!(1))?   // shard 0 to shard 1
+                       ];
+        let (senders, receivers): (Vec<Sender<Job>>, Vec<Receiver<Job>>) = (0..2).map(|_| mpsc::channel(1)).unzip();
+        let pool = pool(2)?;
+        let options = Options { report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let workers = receivers.into_iter()
+            .map(|rx| {
+                let options = options.clone();
+                pool.spawn_with_handle(async move { to_shard(HashMap::new(), rx, &options).await })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        block_on(send(numbered(txns.into_iter()), senders, None, options.clone()))?;
+        let settled = block_on(future::join_all(workers));
+
+        /*
+         * Then
+         */
+        let mut accounts = settled.iter()
+            .flat_map(|(ledgers, _)| ledgers.values().map(|l| (l.account.client_id, l.account.total())))
+            .collect::<Vec<(u16, Decimal)>>();
+        accounts.sort();
+        assert_eq!(accounts, vec![(1, dec!(6)), (2, dec!(3)), (3, dec!(1)), (4, dec!(0))]);
+        let rejections = settled.iter().flat_map(|(_, report)| report.rejections.iter()).collect::<Vec<_>>();
+        assert!(matches!(rejections[..], [Rejection { line: 4, reason: TxError::InsufficientFunds(4), .. }]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_with_spawner() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to
+                     deposit,1,1,10,
+                     deposit,2,2,5,
+                     transfer,1,3,4,2
+                     withdrawal,2,4,20,
+                     dispute,1,1,,";
+        let options = Options { report: true, threads: Some(3), ..Options::default() };
+        let mut local = futures::executor::LocalPool::new();
+        let spawner = local.spawner();
+
+        /*
+         * When
+         */
+        let (accounts, report) = local.run_until(process_reader_with_spawner(&spawner, io::Cursor::new(input), &options))?;
+        let (pooled, pooled_report) = block_on(process_reader(io::Cursor::new(input), &options))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, pooled);
+        assert_eq!(accounts.iter().map(|a| (a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![(dec!(-4), dec!(10)), (dec!(9), dec!(0))]);
+        assert!(matches!(report.rejections[..], [Rejection { line: 5, reason: TxError::InsufficientFunds(4), .. }]));
+        assert_eq!(report.stats, pooled_report.stats);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tx_state() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let history = vec![ Transaction::deposit(1, 1, dec!(2))?
+                          , Transaction::dispute(1, 1)
+                          , Transaction::resolve(1, 1)
+                          , Transaction::deposit(1, 1, dec!(5))?
+                          , Transaction::dispute(1, 1)
+                          ];
+
+        /*
+         * When
+         */
+        let disputed = TxState::of(history.clone());
+        let charged_back = TxState::of(history.into_iter().chain(std::iter::once(Transaction::chargeback(1, 1))));
+
+        /*
+         * Then
+         */
+        assert!(disputed.is_under_dispute());
+        assert_eq!(disputed.original, Some(Transaction::deposit(1, 1, dec!(2))?));
+        assert!(!charged_back.is_under_dispute());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_currencies() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to,currency
+                     deposit,1,1,10,,EUR
+                     deposit,1,2,5,,USD
+                     withdrawal,1,3,8,,USD
+                     deposit,2,4,3,,
+                     dispute,1,1,,,USD
+                     dispute,1,1,,,EUR
+                     transfer,1,5,5,2,USD";
+        let options = Options { report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, report) = block_on(process_reader(io::Cursor::new(input), &options))?;
+        let mut csv = Vec::new();
+        block_on(print_accounts_with(&mut csv, &accounts));
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(|a| (a.client_id(), a.currency(), a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![ (1, Some("EUR"), dec!(0),  dec!(10))
+                       , (1, Some("USD"), dec!(0),  dec!(0))
+                       , (2, None,        dec!(3),  dec!(0))
+                       , (2, Some("USD"), dec!(5),  dec!(0))
+                       ]);
+        assert!(matches!(report.rejections[..], [ Rejection { line: 4, reason: TxError::InsufficientFunds(3), .. }
+                                                , Rejection { line: 6, reason: TxError::CurrencyMismatch(1), .. }
+                                                ]));
+        assert_eq!(std::str::from_utf8(&csv)?, "client,currency,available,held,total,locked\n\
+                                                1,EUR,0,10,10,false\n\
+                                                1,USD,0,0.0,0,false\n\
+                                                2,,3,0.0,3,false\n\
+                                                2,USD,5,0.0,5,false\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_minor_units() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10000
+                     deposit,1,2,15
+                     withdrawal,1,3,2500
+                     deposit,2,4,1.5";
+        let options = Options { amounts: AmountUnit::Minor, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, _) = block_on(process_reader(io::Cursor::new(input), &options))?;
+        let (decimals, _) = block_on(process_reader(io::Cursor::new(input), &Options::default()))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(0.7515)]);
+        assert_eq!(decimals.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(7515), dec!(1.5)]);
+        assert_eq!(Transaction::deposit(1, 1, dec!(10000))?.in_unit(AmountUnit::Minor), Transaction::new(Deposit, 1, 1, Some(10000)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_fractional_minor_units() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10000
+                     deposit,1,2,100.5
+                     deposit,1,3,200.0";
+        let options = Options { amounts: AmountUnit::Minor, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, _) = block_on(process_reader(io::Cursor::new(input), &options))?;
+        let strict = Options { strict: true, ..options.clone() };
+        let failed = block_on(process_reader(io::Cursor::new(input), &strict));
+        let parallel = txns_from_chunks(Ok(input.as_bytes().to_vec()), std::path::Path::new("txns.csv"), &strict, 16)?
+            .filter_map(Result::err)
+            .collect::<Vec<TxError>>();
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(1.02)]);
+        assert!(matches!(failed, Err(TxError::FractionalAmount { line: 3, amount }) if amount == dec!(100.5)));
+        assert!(matches!(parallel.as_slice(), [TxError::FractionalAmount { line: 3, .. }]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_csv_format() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let headerless = "deposit,1,1,2.5
+                          withdrawal,1,2,1.0";
+        let reordered = "client_id,kind,tx,amount
+                         1,deposit,1,2.5
+                         1,withdrawal,2,1.0";
+        let expected = vec![ Account{ client_id: 1
+                                    , currency:  None
+                                    , available: dec!(1.5)
+                                    , held:      dec!(0)
+                                    , total:     dec!(1.5)
+                                    , locked:    false
+                                    }];
+        let no_headers = Options { csv: CsvFormat { no_headers: true, ..CsvFormat::default() }, ..Options::default() };
+        let columns = CsvFormat { no_headers: true
+                                , columns:    Some(vec!["client".into(), "type".into(), "tx".into(), "amount".into()])
+                                , ..CsvFormat::default()
+                                };
+        let map = CsvFormat { map: vec![ ("client".into(), "client_id".into())
+                                       , ("type".into(), "kind".into())
+                                       ]
+                            , ..CsvFormat::default()
+                            };
+
+        /*
+         * When
+         */
+        let (headerless, _) = block_on(process_reader(io::Cursor::new(headerless), &no_headers))?;
+        let (columns, _) = block_on(process_reader(io::Cursor::new("1,deposit,1,2.5\n1,withdrawal,2,1.0"), &Options { csv: columns, ..Options::default() }))?;
+        let (mapped, _) = block_on(process_reader(io::Cursor::new(reordered), &Options { csv: map, ..Options::default() }))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(headerless, expected);
+        assert_eq!(columns, expected);
+        assert_eq!(mapped, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_paths_delimiter() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut tsv = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+        writeln!(tsv, "type\tclient\ttx\tamount
+                       deposit\t1\t1\t2.5")?;
+        let semicolon = "type;client;tx;amount
+                         deposit;2;2;1.5";
+        let options = Options { csv: CsvFormat { delimiter: Some(b';'), ..CsvFormat::default() }, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (tsv, _) = block_on(process_paths(&[tsv.path().to_path_buf()], &Options::default()))?;
+        let (semicolon, _) = block_on(process_reader(io::Cursor::new(semicolon), &options))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(tsv.iter().map(|a| (a.client_id(), a.total())).collect::<Vec<_>>(), vec![(1, dec!(2.5))]);
+        assert_eq!(semicolon.iter().map(|a| (a.client_id(), a.total())).collect::<Vec<_>>(), vec![(2, dec!(1.5))]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_paths_mmap() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,2.5
+                        deposit,2,2,4
+                        withdrawal,1,3,1
+                        dispute,2,2,")?;
+        let tsv = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+        let paths = [file.path().to_path_buf(), tsv.path().to_path_buf()];
+        let options = Options { mmap: true, report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (mapped, report) = block_on(process_paths(&paths, &options))?;
+        let (read, _) = block_on(process_paths(&paths, &Options::default()))?;
+        let missing = block_on(process_path(&std::path::PathBuf::from("missing.csv"), &options));
+
+        /*
+         * Then
+         */
+        assert_eq!(mapped, read);
+        assert_eq!(mapped.iter().map(|a| (a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![(dec!(1.5), dec!(0)), (dec!(0), dec!(4))]);
+        assert!(report.rejections.is_empty());
+        assert!(matches!(missing, Err(TxError::Read { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_paths_blocking() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount,to
+                        deposit,1,1,2.5,
+                        deposit,2,2,4,
+                        transfer,2,3,1,1
+                        withdrawal,1,4,9,
+                        dispute,1,1,,
+                        deposit,3,5,bad,")?;
+        let paths = [file.path().to_path_buf()];
+        let options = Options { threads: Some(2), report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (blocking, report) = process_paths_blocking(&paths, &options)?;
+        let (pooled, pooled_report) = block_on(process_paths(&paths, &options))?;
+        let accounts = accounts_from_path_blocking(&paths[0])?;
+        let strict = process_paths_blocking(&paths, &Options { strict: true, ..options.clone() });
+        let missing = accounts_from_path_blocking(&std::path::PathBuf::from("missing.csv"));
+
+        /*
+         * Then
+         */
+        assert_eq!(blocking, pooled);
+        assert_eq!(accounts, pooled);
+        assert_eq!(blocking.iter().map(|a| (a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![(dec!(1), dec!(2.5)), (dec!(3), dec!(0))]);
+        assert_eq!(report.rejections.iter().map(|r| r.line).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(report.stats.rejected, pooled_report.stats.rejected);
+        assert!(matches!(strict, Err(TxError::Parse { line: 7, .. })));
+        assert!(matches!(missing, Err(TxError::Read { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_max_memory() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let deposits = (1..=3000).map(|t| format!("deposit,{},{},1.5\n", t % 5 + 1, t));
+        let disputes = (1..=3000).step_by(3).map(|t| format!("dispute,{},{},\n", t % 5 + 1, t));
+        let settled = (1..=3000).step_by(6).map(|t| match t % 12 {
+            1 => format!("chargeback,{},{},\n", t % 5 + 1, t),
+            _ => format!("resolve,{},{},\n", t % 5 + 1, t),
+        });
+        let input = std::iter::once(String::from("type,client,tx,amount\n"))
+            .chain(deposits)
+            .chain(disputes)
+            .chain(settled)
+            .collect::<String>();
+        let options = Options { threads: Some(2), report: true, ..Options::default() };
+        let spilled = Options { max_memory: Some(1), ..options.clone() };
+
+        /*
+         * When
+         */
+        let (in_memory, in_memory_report) = block_on(process_reader(io::Cursor::new(input.clone()), &options))?;
+        let (on_disk, on_disk_report) = block_on(process_reader(io::Cursor::new(input.clone()), &spilled))?;
+        let applied = |options: &Options| {
+            let mut engine = Engine::new().with_options(options.clone());
+            txns_from_reader(io::Cursor::new(input.clone()), options).flatten().for_each(|(_, txn)| { let _ = engine.apply(txn); });
+            engine
+        };
+        let (kept, spilled) = (applied(&options), applied(&spilled));
+        let in_memory_of = |engine: &Engine| engine.ledgers.values().map(|ledger| ledger.handled.in_memory()).sum::<usize>();
+        let (kept_bytes, spilled_bytes) = (in_memory_of(&kept), in_memory_of(&spilled));
+        let mut engine_accounts = spilled.into_accounts();
+        engine_accounts.sort_by_key(Account::client_id);
+
+        /*
+         * Then
+         */
+        assert_eq!(on_disk, in_memory);
+        assert_eq!(engine_accounts, in_memory);
+        assert!(spilled_bytes < kept_bytes / 4, "{} of {} bytes kept in memory", spilled_bytes, kept_bytes);
+        assert_eq!(on_disk_report.stats, in_memory_report.stats);
+        assert!(on_disk.iter().any(|account| account.held() > dec!(0)));
+        assert!(on_disk.iter().any(|account| account.locked));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_max_errors() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let rows = (1..=2000).map(|t| match t % 10 {
+            0 => format!("deposit,x,{},1.0\n", t),
+            5 => format!("withdrawal,{},{},100.0\n", t % 5 + 1, t),
+            _ => format!("deposit,{},{},1.0\n", t % 5 + 1, t),
+        });
+        let input = std::iter::once(String::from("type,client,tx,amount\n")).chain(rows).collect::<String>();
+        let limit = |limit: &str| Options { threads: Some(2), max_errors: Some(Arc::new(Threshold::new(limit.parse().unwrap()))), ..Options::default() };
+
+        /*
+         * When
+         */
+        let within = block_on(process_reader(io::Cursor::new(input.clone()), &limit("20%")));
+        let percent = block_on(process_reader(io::Cursor::new(input.clone()), &limit("15%")));
+        let count = block_on(process_reader(io::Cursor::new(input), &limit("10")));
+
+        /*
+         * Then
+         */
+        assert_eq!(within?.1.stats.read, 1800);
+        assert!(matches!(percent, Err(TxError::TooManyErrors { limit: Limit::Percent(_), .. })));
+        assert!(matches!(count, Err(TxError::TooManyErrors { errors, rows, .. }) if errors > 10 && rows < 200));
+        Ok(())
+    }
+
+    #[test]
+    fn test_capacity() {
+        /*
+         * Given
+         */
+        let job = std::mem::size_of::<Job>();
+        let bounded = |capacity, max_memory| Options { capacity, max_memory, ..Options::default() };
+
+        /*
+         * When
+         */
+        let capacities = vec![ capacity(&bounded(None, None), 2)
+                             , capacity(&bounded(Some(8), None), 2)
+                             , capacity(&bounded(None, Some(job * 400)), 2)
+                             , capacity(&bounded(Some(8), Some(job * 400)), 2)
+                             , capacity(&bounded(None, Some(1)), 2)
+                             ];
+
+        /*
+         * Then
+         */
+        assert_eq!(capacities, vec![CAPACITY, 8, 50, 8, 1]);
+    }
+
+    #[test]
+    fn test_deserialize_fast_parse() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to,currency
+                     deposit,1,1,2.50,,
+                     deposit,1,2,10,,
+                     deposit,1,3,10.0,,EUR
+                     withdrawal,1,4,0.0001,,
+                     adjustment,1,5,-5.5,,
+                     adjustment,1,6,-0.0,,
+                     adjustment,1,7,-0,,
+                     deposit,1,8,1e3,,
+                     deposit,1,9,.5,,
+                     deposit,1,10,5.,,
+                     deposit,1,11,+5,,
+                     deposit,1,12,123456789012345678.5,,
+                     deposit,1,13,0.12345678901234567,,
+                     deposit,1,14,0.000000000000000000000000000001,,
+                     deposit,1,15,1.2.3,,
+                     deposit,1,16,abc,,
+                     Deposit,1,17,1,,
+                     deposit,70000,18,1,,
+                     deposit,+1,19,1,,
+                     deposit,1,,1,,
+                     dispute,1,1,,,
+                     transfer,1,20,3.25,2,
+                     transfer,1,21,3.25,x,";
+        let headerless = "deposit,1,1,2.50
+                          withdrawal,1,2,1.0";
+        let duplicate = "type,client,tx,amount,amount
+                         deposit,1,1,2.5,3";
+        let formats = [ CsvFormat::default()
+                      , CsvFormat { no_headers: true, ..CsvFormat::default() }
+                      , CsvFormat::default()
+                      ];
+
+        /*
+         * When
+         */
+        let parsed = [input, headerless, duplicate].iter().zip(formats.iter())
+            .map(|(input, format)| {
+                let parse = |fast_parse| {
+                    let options = Options { strict: true, fast_parse, csv: format.clone(), ..Options::default() };
+                    txns_from_reader(io::Cursor::new(*input), &options)
+                        .map(|parsed| format!("{:?}", parsed))
+                        .collect::<Vec<String>>()
+                };
+                (parse(true), parse(false))
+            })
+            .collect::<Vec<_>>();
+
+        /*
+         * Then
+         */
+        for (fast, serde) in &parsed {
+            assert_eq!(fast, serde);
+        }
+        assert_eq!(parsed[0].0.len(), 23);
+        assert!(parsed[0].0[0].contains("amount: Some(2.5)"));
+        assert!(parsed[0].0[2].contains("amount: Some(10)"));
+        let columns = Columns::of(&csv::StringRecord::from(vec!["type", "client", "tx", "amount"])).unwrap();
+        assert_eq!(columns.parse(&csv::ByteRecord::from(vec!["deposit", "1", "1", "2.50"])), Some(Transaction::deposit(1, 1, dec!(2.5))?));
+        assert_eq!(columns.parse(&csv::ByteRecord::from(vec!["deposit", "1", "1", "1e3"])), None);
+        assert!(Columns::of(&csv::StringRecord::from(vec!["type", "client", "tx", "amount", "amount"])).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_txns_from_chunks() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let rows = (1..=200).map(|t| match t % 50 {
+            0 => String::from("deposit,1\n"),
+            7 => format!("deposit,1,{},bad\n", t),
+            _ => format!("deposit,{},{},{}.5\n", t % 7 + 1, t, t),
+        });
+        let input = std::iter::once(String::from("type,client,tx,amount\n")).chain(rows).collect::<String>();
+        let headerless = input.lines().skip(1).map(|row| format!("{}\n", row)).collect::<String>();
+        let quoted = "type,client,tx,amount\ndeposit,1,1,\"2.5\"\n";
+        let path = std::path::PathBuf::from("transactions.csv");
+        let strict = Options { strict: true, ..Options::default() };
+        let no_headers = Options { csv: CsvFormat { no_headers: true, ..CsvFormat::default() }, ..strict.clone() };
+        let lines = |txns: Vec<Parsed>| txns.into_iter()
+            .map(|parsed| match parsed {
+                Ok((line, txn)) => Ok((line.line, txn)),
+                Err(TxError::Parse { line, .. }) => Err(line),
+                Err(e) => panic!("unexpected error: {}", e),
+            })
+            .collect::<Vec<_>>();
+
+        /*
+         * When
+         */
+        let chunked = lines(txns_from_chunks(Ok(input.clone()), &path, &strict, 64)?.collect());
+        let sequential = lines(txns_from_reader(io::Cursor::new(input.clone()), &strict).collect());
+        let chunked_headerless = lines(txns_from_chunks(Ok(headerless.clone()), &path, &no_headers, 64)?.collect());
+        let sequential_headerless = lines(txns_from_reader(io::Cursor::new(headerless), &no_headers).collect());
+        let chunked_quoted = lines(txns_from_chunks(Ok(quoted), &path, &strict, 1)?.collect());
+        let mut file = NamedTempFile::new()?;
+        write!(file, "{}", input)?;
+        let paths = [file.path().to_path_buf()];
+        let (parallel, _) = block_on(process_paths(&paths, &Options { parallel_parse: true, ..Options::default() }))?;
+        let (mapped, _) = block_on(process_paths(&paths, &Options { parallel_parse: true, mmap: true, ..Options::default() }))?;
+        let (read, _) = block_on(process_paths(&paths, &Options::default()))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(chunked.len(), 200);
+        assert_eq!(chunked, sequential);
+        assert_eq!(chunked.iter().filter(|parsed| parsed.is_err()).count(), 8);
+        assert_eq!(chunked_headerless, sequential_headerless);
+        assert_eq!(chunked_quoted, vec![Ok((2, Transaction::deposit(1, 1, dec!(2.5))?))]);
+        assert_eq!(parallel, read);
+        assert_eq!(mapped, read);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_decimal_comma() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let semicolon = "type;client;tx;amount
+                         deposit;1;1;1.234,56
+                         withdrawal;1;2;0,5";
+        let quoted = "type,client,tx,amount
+                      deposit,2,3,\"2,25\"";
+        let format = CsvFormat { delimiter: Some(b';'), decimal_comma: true, ..CsvFormat::default() };
+        let options = Options { csv: format, ..Options::default() };
+        let comma = Options { csv: CsvFormat { decimal_comma: true, ..CsvFormat::default() }, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (semicolon, _) = block_on(process_reader(io::Cursor::new(semicolon), &options))?;
+        let (quoted, _) = block_on(process_reader(io::Cursor::new(quoted), &comma))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(semicolon.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(1234.06)]);
+        assert_eq!(quoted.iter().map(Account::total).collect::<Vec<Decimal>>(), vec![dec!(2.25)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_paths() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut jan = NamedTempFile::new()?;
+        writeln!(jan, "type,client,tx,amount
+                       deposit,1,1,100
+                       deposit,2,2,50
+                       withdrawal,1,5,500")?;
+        let mut feb = NamedTempFile::new()?;
+        writeln!(feb, "type,client,tx,amount
+                       withdrawal,2,3,20
+                       withdrawal,2,4,80
+                       dispute,1,1,
+                       resolve,2,2,")?;
+        let paths = vec![jan.path().to_path_buf(), feb.path().to_path_buf()];
+
+        /*
+         * When
+         */
+        let (accounts, report) = block_on(process_paths(&paths, &Options { report: true, ..Options::default() }))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
+                                          , available: dec!(0)
+                                          , held:      dec!(100)
+                                          , total:     dec!(100)
+                                          , locked:    false
+                                          }
+                                 , Account{ client_id: 2
+                                          , currency:  None
+                                          , available: dec!(30)
+                                          , held:      dec!(0)
+                                          , total:     dec!(30)
+                                          , locked:    false
+                                          }
+                                 ]);
+        assert_eq!(report.rejections.iter().map(|r| (r.path.as_deref(), r.line)).collect::<Vec<_>>(),
+                   vec![(Some(jan.path()), 4), (Some(feb.path()), 3), (Some(feb.path()), 5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_reader_stats() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount
+                     deposit,1,1,10
+                     deposit,2,2,5.5
+                     withdrawal,1,3,20
+                     dispute,1,1,
+                     dispute,2,9,
+                     dispute,2,2,
+                     chargeback,2,2,
+                     deposit,2,4,1";
+
+        /*
+         * When
+         */
+        let (accounts, report) = block_on(process_reader(io::Cursor::new(input), &Options::default()))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.len(), 2);
+        assert!(report.rejections.is_empty());
+        assert_eq!(report.stats.read, 8);
+        assert_eq!(report.stats.kinds.get(&Deposit), Some(&3));
+        assert_eq!(report.stats.kinds.get(&Dispute), Some(&3));
+        assert_eq!(report.stats.rejected.get("insufficient_funds"), Some(&1));
+        assert_eq!(report.stats.rejected.get("unknown_tx"), Some(&1));
+        assert_eq!(report.stats.rejected.get("account_locked"), Some(&1));
+        assert_eq!(report.stats.accounts, 2);
+        assert_eq!(report.stats.locked, 1);
+        assert_eq!(report.stats.held, dec!(10));
+        assert!(report.stats.to_string().starts_with("transactions read: 8\n  deposit: 3\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_paths() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        engine.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+        engine.apply(Transaction::deposit(2, 2, dec!(5))?)?;
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        dispute,1,1,
+                        deposit,3,3,1")?;
+
+        /*
+         * When
+         */
+        let (engine, _) = block_on(resume_paths(&[file.path().to_path_buf()], &Options::default(), engine))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(engine.account(1).map(Account::held), Some(dec!(10)));
+        assert_eq!(engine.account(2).map(Account::available), Some(dec!(5)));
+        assert_eq!(engine.account(3).map(Account::available), Some(dec!(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_accounts_from_path() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "client,available,held,total,locked
+                        1,1.5,0.0,1.5,false
+                        2,0,2,2,true")?;
+
+        /*
+         * When
+         */
+        let accounts = read_accounts_from_path(&file.path().to_path_buf())?;
+        let mut engine = Engine::from_accounts(accounts);
+        let deposit_1 = engine.apply(Transaction::deposit(1, 1, dec!(1))?);
+        let deposit_2 = engine.apply(Transaction::deposit(2, 2, dec!(1))?);
+
+        /*
+         * Then
+         */
+        assert!(deposit_1.is_ok());
+        assert!(matches!(deposit_2, Err(TxError::AccountLocked(2))));
+        assert_eq!(engine.account(1).map(Account::total), Some(dec!(2.5)));
+        assert_eq!(engine.account(2).map(Account::held), Some(dec!(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_accounts() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut first = Engine::new();
+        first.apply(Transaction::deposit(1, 1, dec!(10))?)?;
+        first.apply(Transaction::deposit(2, 2, dec!(5))?)?;
+        first.apply(Transaction::dispute(2, 2))?;
+        let mut second = Engine::new();
+        second.apply(Transaction::deposit(1, 3, dec!(2.5))?)?;
+        second.apply(Transaction::dispute(1, 3))?;
+        second.apply(Transaction::chargeback(1, 3))?;
+        second.apply(Transaction::deposit(3, 4, dec!(1))?.with_currency("EUR"))?;
+        second.apply(Transaction::deposit(2, 5, dec!(1))?.with_currency("EUR"))?;
+
+        /*
+         * When
+         */
+        let merged = merge_accounts(vec![first.into_accounts(), second.into_accounts()])?;
+        let mismatch = Account::new(1).merge(&Account::new(2));
+        let mut max = Account::new(1);
+        (max.available, max.total) = (Decimal::MAX, Decimal::MAX);
 
-    let now = std::time::Instant::now();
-    let accounts = txns_map_to_accounts(txns_map).await;
-    info!("txns_map_to_accounts done. Elapsed: {:.2?}", now.elapsed());
+        /*
+         * Then
+         */
+        let funds = merged.iter().map(|a| (a.client_id, a.currency(), a.available, a.held, a.total, a.locked)).collect::<Vec<_>>();
+        assert_eq!(funds, vec![ (1, None,        dec!(10), dec!(0), dec!(10), true)
+                              , (2, None,        dec!(0),  dec!(5), dec!(5),  false)
+                              , (2, Some("EUR"), dec!(1),  dec!(0), dec!(1),  false)
+                              , (3, Some("EUR"), dec!(1),  dec!(0), dec!(1),  false)
+                              ]);
+        assert!(matches!(mismatch, Err(TxError::MergeMismatch(1, 2))));
+        assert!(matches!(max.merge(&max), Err(TxError::MergeOverflow(1))));
+        Ok(())
+    }
 
-    Ok(accounts)
-}
+    #[test]
+    fn test_shard_accounts() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        for client in 1..=5 {
+            engine.apply(Transaction::deposit(client, client.into(), dec!(1))?)?;
+        }
+        engine.apply(Transaction::deposit(3, 6, dec!(1))?.with_currency("EUR"))?;
+        let accounts = engine.into_accounts();
 
-/// Wraps the `writer` in a `csv::Writer` and writes the accounts.
-/// The `csv::Writer` is already buffered so there is no need to wrap
-/// `writer` in a `io::BufWriter`.
-pub async fn print_accounts_with(writer: &mut impl io::Write, accounts: &Vec<Account>) {
-    let mut wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(writer);
-    accounts.iter().for_each(|account| wtr.serialize(account).unwrap());
-}
+        /*
+         * When
+         */
+        let shards = shard_accounts(&accounts, 2);
+        let many = shard_accounts(&accounts, 8);
 
-/// Generate and print a list of random transactions.
-pub async fn generate_txns(num_txns: u32, num_clients: u16) {
-    let txns =
-        (0..num_txns).fold(vec![], |mut acc, _| {
-            let txn = random_txn(&acc, &num_clients);
-            acc.push(txn);
-            acc
-        });
+        /*
+         * Then
+         */
+        let clients = |accounts: &[Account]| accounts.iter().map(Account::client_id).collect::<Vec<_>>();
+        assert_eq!(shards.iter().map(|shard| clients(shard)).collect::<Vec<_>>(), vec![vec![1, 2, 3, 3], vec![4, 5]]);
+        assert_eq!(many.len(), 8);
+        assert_eq!(many.iter().map(|shard| shard.len()).collect::<Vec<_>>(), vec![1, 1, 2, 1, 1, 0, 0, 0]);
+        assert_eq!(shard_paths(std::path::Path::new("out/accounts.csv"), 2),
+                   vec![std::path::PathBuf::from("out/accounts-1.csv"), std::path::PathBuf::from("out/accounts-2.csv")]);
+        Ok(())
+    }
 
-    let stdout = io::stdout();
-    let mut lock = stdout.lock();
-    print_txns_with(&mut lock, &txns).await;
-}
-
-fn random_txn(acc: &Vec<Transaction>, num_clients: &u16) -> Transaction {
-    let mut rng = thread_rng();
-    let (kind, client_id, tx_id, amount) =
-        match acc.choose(&mut rng) {
-            Some(txn) =>
-                match rng.gen_range(0..=4) {
-                    0 => (TransactionKind::Deposit, rng.gen_range(1..=*num_clients), rng.gen::<u32>(), Some(rng.gen::<i64>().abs())),
-                    1 => (TransactionKind::Withdrawal, rng.gen_range(1..=*num_clients), rng.gen::<u32>(), Some(rng.gen::<i64>().abs())),
-                    2 => (TransactionKind::Dispute, txn.client_id, txn.tx_id, None),
-                    3 => (TransactionKind::Resolve, txn.client_id, txn.tx_id, None),
-                    _ => (TransactionKind::Chargeback, txn.client_id, txn.tx_id, None),
-                },
-            None =>
-                match rng.gen_range(0..=1) {
-                    0 => (TransactionKind::Deposit, rng.gen_range(1..=*num_clients), rng.gen::<u32>(), Some(rng.gen::<i64>().abs())),
-                    _ => (TransactionKind::Withdrawal, rng.gen_range(1..=*num_clients), rng.gen::<u32>(), Some(rng.gen::<i64>().abs())),
-                }
-        };
-    Transaction::new(kind, client_id, tx_id, amount)
-}
+    #[test]
+    fn test_expand_paths() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        for name in ["feb.csv", "jan.csv", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "type,client,tx,amount")?;
+        }
+        std::fs::create_dir(dir.path().join("old.csv"))?;
 
-async fn print_txns_with(writer: &mut impl io::Write, txns: &Vec<Transaction>) {
-    let mut wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(writer);
-    txns.iter().for_each(|txn| wtr.serialize(txn).unwrap());
-}
+        /*
+         * When
+         */
+        let from_dir = expand_paths(&[dir.path().to_path_buf()])?;
+        let from_glob = expand_paths(&[dir.path().join("*.*")])?;
+        let no_match = expand_paths(&[dir.path().join("*.json")]);
 
-/// Reads the file from path into an ordered `Vec<Transaction>`.
-async fn read_txns(path: &std::path::PathBuf) -> io::Result<Vec<Transaction>> {
-    let now = std::time::Instant::now();
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(true)
-        .delimiter(b',')
-        .trim(Trim::All)
-        .from_path(path)?;
-    info!("ReaderBuilder::from_path done. Elapsed: {:.2?}", now.elapsed());
-
-    let now = std::time::Instant::now();
-    let all_txns: Vec<Transaction> =
-        rdr.deserialize::<Transaction>()
-            .filter_map(|record| record.ok())
-            .collect();
-    info!("reader::deserialize done. Elapsed: {:.2?}", now.elapsed());
-
-    Ok(all_txns)
-}
-
-/// Returns a `HashMap` where the key is a `u16` client id,
-/// and the value is a `Vec<Transaction>` that
-/// belongs to the client.
-fn txns_to_map(all_txns: Vec<Transaction>) -> HashMap<u16, Vec<Transaction>> {
-    all_txns.into_iter().fold(
-        HashMap::new(),
-        | mut acc
-        , txn: Transaction
-        | {
-            acc.entry(txn.client_id)
-                .or_insert(vec![])
-                .push(txn);
-            acc
-        })
-}
+        /*
+         * Then
+         */
+        assert_eq!(from_dir, vec![dir.path().join("feb.csv"), dir.path().join("jan.csv")]);
+        assert_eq!(from_glob, vec![ dir.path().join("feb.csv")
+                                  , dir.path().join("jan.csv")
+                                  , dir.path().join("notes.txt")
+                                  ]);
+        assert!(matches!(no_match, Err(TxError::NoMatch(_))));
+        Ok(())
+    }
 
-/// Reads the `HashMap` in parallel, and returns a list of
-/// accounts as `Vec<Account>`.
-async fn txns_map_to_accounts(txns_map: HashMap<u16, Vec<Transaction>>) -> Vec<Account> {
-    txns_map.into_par_iter()
-        .map(| (client_id, client_txns) | to_account(client_id, client_txns))
-        .collect()
-}
+    #[test]
+    fn test_tail_poll() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        let mut engine = Engine::new();
+        let mut tail = Tail::new(file.path());
+        write!(file, "type,client,tx,amount\ndeposit,1,1,100\ndeposit,1,2,")?;
+        file.flush()?;
 
-/// Reads a sorted list of `Transaction`, and returns an
-/// `Account` for a client.
-fn to_account(client_id: u16, client_txns: Vec<Transaction>) -> Account {
-    let (account, _) =
-        client_txns.iter().fold(
-            (Account::new(client_id), HashMap::new()),
-            | (mut account, mut handled): (Account, HashMap<u32, Vec<&Transaction>>)
-            , txn: &Transaction
-            | {
-                match handle_txn(&mut account, &handled, txn) {
-                    Ok(()) => handled.entry(txn.tx_id).or_insert(vec![]).push(&txn), // only insert when txn ok
-                    _ => debug!("Ignoring invalid transaction: {:?}", txn)
-                };
-                (account, handled)
-            }
-        );
-    account
-}
+        /*
+         * When
+         */
+        let first = tail.poll(&mut engine, false)?;
+        write!(file, "50\ndispute,1,1,\n")?;
+        file.flush()?;
+        let second = tail.poll(&mut engine, false)?;
+        let third = tail.poll(&mut engine, false)?;
 
-/// Handles a `Transaction` and updates the client's
-/// `Account`. The `amount` is rounded to four digits
-/// after decimal.
-fn handle_txn( account: &mut Account
-             , handled: &HashMap<u32, Vec<&Transaction>>
-             , txn:     &Transaction
-             ) -> io::Result<()> {
-    match txn {
-        &Transaction{ kind: Deposit, amount: Some(amount), .. } => {
-            (!account.locked && amount.is_sign_positive()).then(|| ())
-                .ok_or(Error::from(InvalidInput))?;
-            // A deposit is a credit to the client's asset account,
-            // meaning it should increase the available and total
-            // funds of the client account
-            account.available += amount.round_dp(4);
-            account.total     += amount.round_dp(4);
-            Ok(())
-        },
-        &Transaction{ kind: Withdrawal, amount: Some(amount), .. } => {
-            // If a client does not have sufficient available funds
-            // the withdrawal should fail and the total amount of
-            // funds should not change
-            (!account.locked
-                && account.available >= amount
-                && amount.is_sign_positive()).then(|| ()).ok_or(Error::from(InvalidInput))?;
-            // A withdraw is a debit to the client's asset account,
-            // meaning it should decrease the available and total
-            // funds of the client account
-            account.available -= amount.round_dp(4);
-            account.total     -= amount.round_dp(4);
-            Ok(())
-        },
-        &Transaction{ kind: Dispute, tx_id, .. } => {
-            // Notice that a dispute does not state the amount disputed.
-            // Instead a dispute references the transaction that is
-            // disputed by ID.
-            let txns = handled.get(&tx_id).ok_or(Error::from(InvalidInput))?;
-            // If the tx specified by the dispute doesn't exist you can
-            // ignore it and assume this is an error on our partners side.
-            let dispute = is_under_dispute(txns);
-            let initial_txn = initial_txn(txns);
-            match (dispute, initial_txn) {
-                (false, Some(&&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
-                    // A dispute represents a client's claim that a
-                    // transaction was erroneous and should be reversed.
-                    // The transaction shouldn't be reversed yet but
-                    // the associated funds should be held. This means
-                    // that the clients available funds should decrease
-                    // by the amount disputed, their held funds should
-                    // increase by the amount disputed, while their
-                    // total funds should remain the same.
-                    account.available -= amount.round_dp(4);
-                    account.held      += amount.round_dp(4);
-                    Ok(())
-                },
-                (false, Some(&&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
-                    // NOTE: Assumes a dispute on a withdrawal temporarily
-                    // puts funds into the client's held funds.
-                    account.held      += amount.round_dp(4);
-                    account.total     += amount.round_dp(4);
-                    Ok(())
-                },
-                _ => Err(Error::from(InvalidInput))
-            }
-        },
-        &Transaction{ kind: Resolve, tx_id, .. } => {
-            // Like disputes, resolves do not specify an amount. Instead
-            // they refer to a transaction that was under dispute by ID.
-            let txns = handled.get(&tx_id).ok_or(Error::from(InvalidInput))?;
-            // If the tx specified doesn't exist, or the tx isn't under
-            // dispute, you can ignore the resolve and assume this is an
-            // error on our partner's side.
-            let dispute = is_under_dispute(txns);
-            let initial_txn = initial_txn(txns);
-            match (dispute, initial_txn) {
-                (true, Some(&&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
-                    // A resolve represents a resolution to a dispute,
-                    // releasing the associated held funds. Funds that
-                    // were previously disputed are no longer disputed.
-                    // This means that the clients held funds should
-                    // decrease by the amount no longer disputed, their
-                    // available funds should increase by the amount no
-                    // longer disputed, and their total funds should
-                    // remain the same.
-                    account.available += amount.round_dp(4);
-                    account.held      -= amount.round_dp(4);
-                    Ok(())
-                },
-                (true, Some(&&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
-                    // NOTE: Assumes a resolve removes the temporarily
-                    // increased funds from the client's held funds.
-                    account.held      -= amount.round_dp(4);
-                    account.total     -= amount.round_dp(4);
-                    Ok(())
-                },
-                _ => Err(Error::from(InvalidInput))
-            }
-        },
-        &Transaction{ kind: Chargeback, tx_id, .. } => {
-            // Like a dispute and a resolve a chargeback refers to the
-            // transaction by ID (tx) and does not specify an amount.
-            let txns = handled.get(&tx_id).ok_or(Error::from(InvalidInput))?;
-            // Like a resolve, if the tx specified doesn't exist, or
-            // the tx isn't under dispute, you can ignore chargeback
-            // and assume this is an error on our partner's side.
-            let dispute = is_under_dispute(txns);
-            let initial_txn = initial_txn(txns);
-            match (dispute, initial_txn) {
-                (true, Some(&&Transaction{ kind: Deposit, amount: Some(amount), .. })) => {
-                    // A chargeback is the final state of a dispute and
-                    // represents the client reversing a transaction.
-                    // Funds that were held have now been withdrawn.
-                    // This means that the clients held funds and total
-                    // funds should decrease by the amount previously
-                    // disputed. If a chargeback occurs the client's
-                    // account should be immediately frozen.
-                    account.held   -= amount.round_dp(4);
-                    account.total  -= amount.round_dp(4);
-                    account.locked  = true;
-                    Ok(())
-                },
-                (true, Some(&&Transaction{ kind: Withdrawal, amount: Some(amount), .. })) => {
-                    // NOTE: Assumes a chargeback to a withdrawal reverses
-                    // a withdrawal, and puts the temporarily held funds
-                    // back to the client available funds.
-                    account.available += amount.round_dp(4);
-                    account.held      -= amount.round_dp(4);
-                    account.locked     = true;
-                    Ok(())
-                },
-                _ => Err(Error::from(InvalidInput))
-            }
-        },
-        _ => Err(Error::from(InvalidInput))
+        /*
+         * Then
+         */
+        assert_eq!((first, second, third), (1, 2, 0));
+        let account = engine.account(1).unwrap();
+        assert_eq!(account.available(), dec!(50));
+        assert_eq!(account.held(), dec!(100));
+        Ok(())
     }
-}
 
-/// Returns `true` if there are more disputes than resolves,
-/// and if there has been no chargebacks.
-fn is_under_dispute(txns: &Vec<&Transaction>) -> bool {
-    let n_dispute = txns.iter().filter(|t| t.kind == Dispute).count();
-    let n_resolve = txns.iter().filter(|t| t.kind == Resolve).count();
-    let chargeback = txns.iter().any(|t| t.kind == Chargeback);
-    let dispute = n_dispute > n_resolve;
-    dispute && !chargeback
-}
+    #[test]
+    fn test_tail_poll_malformed() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        let (mut engine, mut strict_engine) = (Engine::new(), Engine::new());
+        let (mut tail, mut strict_tail) = (Tail::new(file.path()), Tail::new(file.path()));
+        write!(file, "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal\ndeposit,1,2,50\n")?;
+        file.flush()?;
 
-/// Returns the first occurrence of a deposit or a
-/// withdrawal as `Some(&&Transaction)` if found.
-fn initial_txn<'a>(txns: &'a Vec<&'a Transaction>) -> Option<&'a &Transaction> {
-    txns.iter().filter(|t| t.kind == Withdrawal || t.kind == Deposit).next()
-}
+        /*
+         * When
+         */
+        let read = tail.poll(&mut engine, false)?;
+        let failed = strict_tail.poll(&mut strict_engine, true);
+        let failed_again = strict_tail.poll(&mut strict_engine, true);
 
-#[cfg(test)]
-mod test {
-    use common_macros::hash_map;
-    use crate::tx::*;
-    use tempfile::NamedTempFile;
-    use std::io::Write;
-    use futures::executor::block_on;
+        /*
+         * Then
+         */
+        assert_eq!(read, 3);
+        assert_eq!(engine.account(1).unwrap().available(), dec!(150));
+        assert!(matches!(failed, Err(TxError::Parse { .. })));
+        assert!(matches!(failed_again, Err(TxError::Parse { .. })));
+        Ok(())
+    }
 
     #[test]
-    fn test_read_with() -> Result<(), anyhow::Error> {
-        let path = &std::path::PathBuf::from("transactions_simple.csv");
-        let mut result = Vec::new();
-        block_on(read_with(&mut result, path))?;
-        let mut lines = std::str::from_utf8(&result)?.lines();
-        let expected = vec![ "client,available,held,total,locked"
-                           , "1,1.4996,0.0,1.4996,false"
-                           , "2,2,0.0,2,false"
-                           , "4,0.0,0.0,0.0,false"
-                           , "5,0.0,0.0,0.0,false"
-                           ];
-        assert_eq!(true, lines.all(|l| expected.contains(&l)));
+    fn test_tail_poll_strict_retry() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        let mut engine = Engine::new();
+        let mut tail = Tail::new(file.path());
+        write!(file, "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,30\ndeposit,x,3,5\n")?;
+        file.flush()?;
+
+        /*
+         * When
+         */
+        let failed = tail.poll(&mut engine, true);
+        let failed_again = tail.poll(&mut engine, true);
+        let after_failures = engine.account(1).map(Account::available);
+        let fixed = tail.poll(&mut engine, false)?;
+        writeln!(file, "deposit,1,4,1")?;
+        file.flush()?;
+        let appended = tail.poll(&mut engine, true)?;
+
+        /*
+         * Then
+         */
+        assert!(matches!(failed, Err(TxError::Parse { line: 4, .. })));
+        assert!(matches!(failed_again, Err(TxError::Parse { line: 4, .. })));
+        assert_eq!(after_failures, Some(dec!(70)));
+        assert_eq!((fixed, appended), (1, 1));
+        assert_eq!(engine.account(1).map(Account::available), Some(dec!(71)));
         Ok(())
     }
 
     #[test]
-    fn test_read_txns() -> Result<(), Box<dyn std::error::Error>> {
+    fn test_deserialize() -> Result<(), Box<dyn std::error::Error>> {
         /*
          * Given
          */
@@ -424,44 +4502,53 @@ mod test {
         /*
          * When
          */
-        let txns = block_on(read_txns(&std::path::PathBuf::from(path)))?;
+        let rdr = reader_from_path(&std::path::PathBuf::from(path), &CsvFormat::default())?;
+        let mut iter = deserialize(rdr, &Options::default()).map(|parsed| parsed.unwrap().1);
 
         /*
          * Then
          */
-        // txns.sort_by_key(|(i, _)| *i);
-        let mut iter = txns.into_iter();
         assert_eq!(iter.next(), Some(Transaction{ kind:      Deposit
                                                 , client_id: 1
                                                 , tx_id:     1
                                                 , amount:    Some(dec!(1.001))
+                                                , to_client: None
+                                                , currency:  None
                                                 }));
         assert_eq!(iter.next(), Some(Transaction{ kind:      Withdrawal
                                                 , client_id: 2
                                                 , tx_id:     2
                                                 , amount:    Some(dec!(2.0002))
+                                                , to_client: None
+                                                , currency:  None
                                                 }));
         assert_eq!(iter.next(), Some(Transaction{ kind:      Dispute
                                                 , client_id: 3
                                                 , tx_id:     3
                                                 , amount:    None
+                                                , to_client: None
+                                                , currency:  None
                                                 }));
         assert_eq!(iter.next(), Some(Transaction{ kind:      Resolve
                                                 , client_id: 4
                                                 , tx_id:     4
                                                 , amount:    None
+                                                , to_client: None
+                                                , currency:  None
                                                 }));
         assert_eq!(iter.next(), Some(Transaction{ kind: Chargeback
                                                 , client_id: 5
                                                 , tx_id:     5
                                                 , amount:    None
+                                                , to_client: None
+                                                , currency:  None
                                                 }));
         assert_eq!(iter.next(), None);
         Ok(())
     }
 
     #[test]
-    fn test_txns_to_map() -> Result<(), Box<dyn std::error::Error>> {
+    fn test_send() -> Result<(), Box<dyn std::error::Error>> {
         /*
          * Given
          */
@@ -482,79 +4569,95 @@ mod test {
         /*
          * When
          */
-        let txns = block_on(read_txns(&std::path::PathBuf::from(path)))?;
-        let txns_map = txns_to_map(txns);
+        let rdr = reader_from_path(&std::path::PathBuf::from(path), &CsvFormat::default())?;
+        let (shard_tx, shard_rx) = mpsc::channel(CAPACITY);
+        block_on(send(deserialize(rdr, &Options::default()), vec![shard_tx], None, Options::default()))?;
+        let mut opened = vec![];
+        let mut txns_map: HashMap<u16, Vec<Transaction>> = HashMap::new();
+        futures::executor::block_on_stream(shard_rx).for_each(|job| match job {
+            Job::Open(client_id) => opened.push(client_id),
+            Job::Apply(_, txn, _) => txns_map.entry(txn.client_id).or_default().push(txn),
+        });
 
         /*
          * Then
          */
-        // txns_map.iter_mut().for_each(|(_k, v)| v.sort_by_key(|(i, _)| *i) );
-        assert_eq!(txns_map.get(&1), Some(&vec![ Transaction{ kind: Deposit, client_id: 1, tx_id: 1, amount: Some(dec!(1.0)) }
-                                                , Transaction{ kind: Deposit, client_id: 1, tx_id: 3, amount: Some(dec!(2.0)) }
-                                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4, amount: Some(dec!(1.5)) }
+        assert_eq!(txns_map.get(&1), Some(&vec![ Transaction{ kind: Deposit, client_id: 1, tx_id: 1, amount: Some(dec!(1.0)), to_client: None, currency: None }
+                                                , Transaction{ kind: Deposit, client_id: 1, tx_id: 3, amount: Some(dec!(2.0)), to_client: None, currency: None }
+                                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4, amount: Some(dec!(1.5)), to_client: None, currency: None }
                                                 ]));
-        assert_eq!(txns_map.get(&2), Some(&vec![ Transaction{ kind: Deposit, client_id: 2, tx_id: 2, amount: Some(dec!(2.0)) }
-                                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 5, amount: Some(dec!(3.0)) }
+        assert_eq!(txns_map.get(&2), Some(&vec![ Transaction{ kind: Deposit, client_id: 2, tx_id: 2, amount: Some(dec!(2.0)), to_client: None, currency: None }
+                                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 5, amount: Some(dec!(3.0)), to_client: None, currency: None }
                                                 ]));
         assert_eq!(txns_map.get(&3), None);
-        assert_eq!(txns_map.get(&4), Some(&vec![ Transaction{ kind: Dispute, client_id: 4, tx_id: 4, amount: None }
-                                                , Transaction{ kind: Resolve, client_id: 4, tx_id: 4, amount: None }
+        assert_eq!(opened, vec![1, 2, 4, 5]);
+        assert_eq!(txns_map.get(&4), Some(&vec![ Transaction{ kind: Dispute, client_id: 4, tx_id: 4, amount: None, to_client: None, currency: None }
+                                                , Transaction{ kind: Resolve, client_id: 4, tx_id: 4, amount: None, to_client: None, currency: None }
                                                 ]));
-        assert_eq!(txns_map.get(&5), Some(&vec![ Transaction{ kind: Chargeback, client_id: 5, tx_id: 5, amount: None }
+        assert_eq!(txns_map.get(&5), Some(&vec![ Transaction{ kind: Chargeback, client_id: 5, tx_id: 5, amount: None, to_client: None, currency: None }
                                                 ]));
         Ok(())
     }
 
     #[test]
-    fn test_txns_map_to_accounts() {
+    fn test_to_shard() {
         /*
          * Given
          */
         let txns =
-            hash_map!( 1 => vec![ Transaction{ kind: Deposit,    client_id: 1, tx_id: 1,   amount: Some(dec!(1.0001)) } // +1.0001
-                                , Transaction{ kind: Deposit,    client_id: 1, tx_id: 3,   amount: Some(dec!(2.00002)) } // +2.0
-                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4,   amount: Some(dec!(1.5001)) } // -1.5001
-                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4,   amount: Some(dec!(10.0)) } // ignore
-                                , Transaction{ kind: Resolve,    client_id: 1, tx_id: 3,   amount: None } // ignore
-                                , Transaction{ kind: Chargeback, client_id: 1, tx_id: 3,   amount: None } // ignore
-                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 3,   amount: None } // hold 2.0
-                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 3,   amount: None } // ignore
-                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 100, amount: None } // ignore
-                                , Transaction{ kind: Resolve,    client_id: 1, tx_id: 3,   amount: None } // release 2.0
-                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 4,   amount: None } // hold 1.5001
-                                , Transaction{ kind: Chargeback, client_id: 1, tx_id: 4,   amount: None } // revert 1.5001, freeze
-                                , Transaction{ kind: Deposit,    client_id: 1, tx_id: 5,   amount: Some(dec!(2.0)) } // ignore
+            hash_map!( 1 => vec![ Transaction{ kind: Deposit,    client_id: 1, tx_id: 1,   amount: Some(dec!(1.0001)), to_client: None, currency: None } // +1.0001
+                                , Transaction{ kind: Deposit,    client_id: 1, tx_id: 3,   amount: Some(dec!(2.00002)), to_client: None, currency: None } // +2.0
+                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4,   amount: Some(dec!(1.5001)), to_client: None, currency: None } // -1.5001
+                                , Transaction{ kind: Withdrawal, client_id: 1, tx_id: 4,   amount: Some(dec!(10.0)), to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Resolve,    client_id: 1, tx_id: 3,   amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Chargeback, client_id: 1, tx_id: 3,   amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 3,   amount: None, to_client: None, currency: None } // hold 2.0
+                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 3,   amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 100, amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Resolve,    client_id: 1, tx_id: 3,   amount: None, to_client: None, currency: None } // release 2.0
+                                , Transaction{ kind: Dispute,    client_id: 1, tx_id: 4,   amount: None, to_client: None, currency: None } // hold 1.5001
+                                , Transaction{ kind: Chargeback, client_id: 1, tx_id: 4,   amount: None, to_client: None, currency: None } // revert 1.5001, freeze
+                                , Transaction{ kind: Deposit,    client_id: 1, tx_id: 5,   amount: Some(dec!(2.0)), to_client: None, currency: None } // ignore
                                 ]
-                     , 2 => vec![ Transaction{ kind: Deposit,    client_id: 2, tx_id: 101, amount: Some(dec!(5.0)) } // +5.0
-                                , Transaction{ kind: Deposit,    client_id: 2, tx_id: 102, amount: Some(dec!(10.0)) } // +10.0
-                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 103, amount: Some(dec!(1.5)) } // -1.5
-                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 104, amount: Some(dec!(10.0)) } // -10.0
-                                , Transaction{ kind: Resolve,    client_id: 2, tx_id: 103, amount: None } // ignore
-                                , Transaction{ kind: Chargeback, client_id: 2, tx_id: 103, amount: None } // ignore
-                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 102, amount: None } // hold 10.0
-                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 101, amount: None } // hold 5.0
-                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 102, amount: None } // ignore
-                                , Transaction{ kind: Resolve,    client_id: 2, tx_id: 101, amount: None } // release 5.0
-                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 101, amount: None } // hold 5.0
-                                , Transaction{ kind: Chargeback, client_id: 2, tx_id: 102, amount: None } // revert 10.0, freeze
-                                , Transaction{ kind: Deposit,    client_id: 2, tx_id: 105, amount: Some(dec!(20.0)) } // ignore
+                     , 2 => vec![ Transaction{ kind: Deposit,    client_id: 2, tx_id: 101, amount: Some(dec!(5.0)), to_client: None, currency: None } // +5.0
+                                , Transaction{ kind: Deposit,    client_id: 2, tx_id: 102, amount: Some(dec!(10.0)), to_client: None, currency: None } // +10.0
+                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 103, amount: Some(dec!(1.5)), to_client: None, currency: None } // -1.5
+                                , Transaction{ kind: Withdrawal, client_id: 2, tx_id: 104, amount: Some(dec!(10.0)), to_client: None, currency: None } // -10.0
+                                , Transaction{ kind: Resolve,    client_id: 2, tx_id: 103, amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Chargeback, client_id: 2, tx_id: 103, amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 102, amount: None, to_client: None, currency: None } // hold 10.0
+                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 101, amount: None, to_client: None, currency: None } // hold 5.0
+                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 102, amount: None, to_client: None, currency: None } // ignore
+                                , Transaction{ kind: Resolve,    client_id: 2, tx_id: 101, amount: None, to_client: None, currency: None } // release 5.0
+                                , Transaction{ kind: Dispute,    client_id: 2, tx_id: 101, amount: None, to_client: None, currency: None } // hold 5.0
+                                , Transaction{ kind: Chargeback, client_id: 2, tx_id: 102, amount: None, to_client: None, currency: None } // revert 10.0, freeze
+                                , Transaction{ kind: Deposit,    client_id: 2, tx_id: 105, amount: Some(dec!(20.0)), to_client: None, currency: None } // ignore
                                 ]);
         /*
          * When
          */
-        let mut accounts = block_on(txns_map_to_accounts(txns));
+        let mut accounts: Vec<Account> =
+            txns.into_iter()
+                .map(|(client_id, client_txns)| {
+                    let jobs = (1..).map(Line::from).zip(client_txns).map(|(line, txn)| Job::Apply(line, txn, None));
+                    let (mut ledgers, _) = block_on(to_shard(HashMap::new(), futures::stream::iter(jobs), &Options::default()));
+                    ledgers.remove(&client_id).unwrap().account
+                })
+                .collect();
 
         /*
          * Then
          */
         accounts.sort_by_key(|a| a.client_id);
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(3.0001)
                                           , held:      dec!(0.0)
                                           , total:     dec!(3.0001)
                                           , locked:    true
                                           }
                                  , Account{ client_id: 2
+                                          , currency:  None
                                           , available: dec!(-11.5)
                                           , held:      dec!(5.0)
                                           , total:     dec!(-6.5)
@@ -563,6 +4666,97 @@ mod test {
                                  ]);
     }
 
+    #[test]
+    fn test_transaction_constructors() {
+        assert_eq!(Transaction::deposit(1, 1, dec!(1.0001)).unwrap(), Transaction{ kind: Deposit, client_id: 1, tx_id: 1, amount: Some(dec!(1.0001)), to_client: None, currency: None });
+        assert_eq!(Transaction::withdrawal(1, 2, dec!(0)).unwrap(),   Transaction{ kind: Withdrawal, client_id: 1, tx_id: 2, amount: Some(dec!(0)), to_client: None, currency: None });
+        assert_eq!(Transaction::dispute(1, 1),    Transaction{ kind: Dispute,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None });
+        assert_eq!(Transaction::resolve(1, 1),    Transaction{ kind: Resolve,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None });
+        assert_eq!(Transaction::chargeback(1, 1), Transaction{ kind: Chargeback, client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None });
+        assert_eq!(Transaction::unlock(1, 5),     Transaction{ kind: Unlock,     client_id: 1, tx_id: 5, amount: None, to_client: None, currency: None });
+        assert_eq!(Transaction::adjustment(1, 6, dec!(-2.5)).unwrap(), Transaction{ kind: Adjustment, client_id: 1, tx_id: 6, amount: Some(dec!(-2.5)), to_client: None, currency: None });
+        assert!(matches!(Transaction::deposit(1, 3, dec!(-1.0)),       Err(TxError::NegativeAmount(3))));
+        assert!(matches!(Transaction::withdrawal(1, 4, dec!(1.00001)), Err(TxError::InvalidScale(4))));
+        assert!(matches!(Transaction::adjustment(1, 7, dec!(-1.00001)), Err(TxError::InvalidScale(7))));
+        assert_eq!(Transaction::transfer(1, 2, 8, dec!(3)).unwrap().to_client(), Some(2));
+        assert!(matches!(Transaction::transfer(1, 1, 9, dec!(3)), Err(TxError::InvalidTransfer(9))));
+    }
+
+    #[test]
+    fn test_account_accessors() {
+        let account = Account{ client_id: 1
+                             , currency:  None
+                             , available: dec!(1.5)
+                             , held:      dec!(2.0)
+                             , total:     dec!(3.5)
+                             , locked:    true
+                             };
+        assert_eq!(account.client_id(), 1);
+        assert_eq!(account.available(), dec!(1.5));
+        assert_eq!(account.held(),      dec!(2.0));
+        assert_eq!(account.total(),     dec!(3.5));
+        assert!(account.is_locked());
+        assert!(account.balance_check());
+        assert!(!Account{ total: dec!(3.4), ..account }.balance_check());
+    }
+
+    #[test]
+    fn test_engine_apply() {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+
+        /*
+         * When
+         */
+        let results = [ engine.apply(Transaction{ kind: Deposit,    client_id: 1, tx_id: 1, amount: Some(dec!(100)), to_client: None, currency: None })
+                      , engine.apply(Transaction{ kind: Withdrawal, client_id: 1, tx_id: 2, amount: Some(dec!(500)), to_client: None, currency: None })
+                      , engine.apply(Transaction{ kind: Dispute,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None })
+                      , engine.apply(Transaction{ kind: Resolve,    client_id: 1, tx_id: 3, amount: None, to_client: None, currency: None })
+                      , engine.apply(Transaction{ kind: Deposit,    client_id: 2, tx_id: 4, amount: Some(dec!(-1)), to_client: None, currency: None })
+                      ];
+
+        /*
+         * Then
+         */
+        assert_eq!(results.iter().map(|r| r.is_ok()).collect::<Vec<bool>>(), vec![true, false, true, false, false]);
+        assert_eq!(engine.account(1), Some(&Account{ client_id: 1
+                                                   , currency:  None
+                                                   , available: dec!(0)
+                                                   , held:      dec!(100)
+                                                   , total:     dec!(100)
+                                                   , locked:    false
+                                                   }));
+        assert_eq!(engine.account(3), None);
+        let accounts = engine.into_accounts();
+        assert_eq!(accounts.iter().map(|a| a.client_id).collect::<Vec<u16>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_engine_apply_errors() {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        engine.apply(Transaction{ kind: Deposit, client_id: 1, tx_id: 1, amount: Some(dec!(100)), to_client: None, currency: None }).unwrap();
+        engine.apply(Transaction{ kind: Deposit, client_id: 2, tx_id: 2, amount: Some(dec!(100)), to_client: None, currency: None }).unwrap();
+        engine.apply(Transaction{ kind: Dispute, client_id: 2, tx_id: 2, amount: None, to_client: None, currency: None }).unwrap();
+        engine.apply(Transaction{ kind: Chargeback, client_id: 2, tx_id: 2, amount: None, to_client: None, currency: None }).unwrap();
+
+        /*
+         * When / Then
+         */
+        assert!(matches!(engine.apply(Transaction{ kind: Withdrawal, client_id: 1, tx_id: 3, amount: Some(dec!(500)), to_client: None, currency: None }), Err(TxError::InsufficientFunds(3))));
+        assert!(matches!(engine.apply(Transaction{ kind: Deposit,    client_id: 1, tx_id: 4, amount: Some(dec!(-1)), to_client: None, currency: None }),  Err(TxError::NegativeAmount(4))));
+        assert!(matches!(engine.apply(Transaction{ kind: Deposit,    client_id: 1, tx_id: 5, amount: None, to_client: None, currency: None }),            Err(TxError::MissingAmount(5))));
+        assert!(matches!(engine.apply(Transaction{ kind: Dispute,    client_id: 1, tx_id: 6, amount: None, to_client: None, currency: None }),            Err(TxError::UnknownTx(6))));
+        assert!(matches!(engine.apply(Transaction{ kind: Resolve,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None }),            Err(TxError::NotDisputed(1))));
+        engine.apply(Transaction{ kind: Dispute, client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None }).unwrap();
+        assert!(matches!(engine.apply(Transaction{ kind: Dispute,    client_id: 1, tx_id: 1, amount: None, to_client: None, currency: None }),            Err(TxError::AlreadyDisputed(1))));
+        assert!(matches!(engine.apply(Transaction{ kind: Deposit,    client_id: 2, tx_id: 7, amount: Some(dec!(1)), to_client: None, currency: None }),   Err(TxError::AccountLocked(2))));
+    }
+
     #[test]
     fn test_accounts_from_path_deposit() -> Result<(), Box<dyn std::error::Error>> {
         /*
@@ -591,6 +4785,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(10001.0001)
                                           , held:      dec!(0.0)
                                           , total:     dec!(10001.0001)
@@ -632,6 +4827,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(9993.9997)
                                           , held:      dec!(0.0)
                                           , total:     dec!(9993.9997)
@@ -680,19 +4876,20 @@ mod test {
         /*
          * When
          */
-        let mut accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
+        let accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
 
         /*
          * Then
          */
-        accounts.sort_by_key(|a| a.client_id);
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(-14.0012)
                                           , held:      dec!(12315.0013)
                                           , total:     dec!(12301.0001)
                                           , locked:    false
                                           }
                                  , Account{ client_id: 2
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -752,19 +4949,20 @@ mod test {
         /*
          * When
          */
-        let mut accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
+        let accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
 
         /*
          * Then
          */
-        accounts.sort_by_key(|a| a.client_id);
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(12285.9990)
                                           , held:      dec!(0)
                                           , total:     dec!(12285.999)
                                           , locked:    false
                                           }
                                  , Account{ client_id: 2
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -823,19 +5021,20 @@ mod test {
         /*
          * When
          */
-        let mut accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
+        let accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
 
         /*
          * Then
          */
-        accounts.sort_by_key(|a| a.client_id);
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(999.9979)
                                           , held:      dec!(0)
                                           , total:     dec!(999.9979)
                                           , locked:    true
                                           }
                                  , Account{ client_id: 2
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -868,6 +5067,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(100)
                                           , held:      dec!(300)
                                           , total:     dec!(400)
@@ -901,6 +5101,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(100)
                                           , total:     dec!(100)
@@ -935,6 +5136,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(50)
                                           , held:      dec!(50)
                                           , total:     dec!(100)
@@ -967,6 +5169,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(100)
                                           , held:      dec!(0)
                                           , total:     dec!(100)
@@ -1000,6 +5203,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -1034,6 +5238,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(100)
                                           , held:      dec!(0)
                                           , total:     dec!(100)
@@ -1065,6 +5270,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -1096,6 +5302,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -1127,6 +5334,7 @@ mod test {
          * Then
          */
         assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
                                           , available: dec!(0)
                                           , held:      dec!(0)
                                           , total:     dec!(0)
@@ -1135,4 +5343,40 @@ mod test {
                                  ]);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_accounts_from_path_unlock_and_adjustment() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,100
+                        dispute,1,1,
+                        chargeback,1,1,
+                        deposit,1,2,10
+                        adjustment,1,3,-20.5
+                        unlock,1,4,
+                        deposit,1,5,30
+                        adjustment,1,6,1.25")?;
+        let path = file.path().to_str().unwrap();
+
+        /*
+         * When
+         */
+        let accounts = block_on(accounts_from_path(&std::path::PathBuf::from(path)))?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts, vec![ Account{ client_id: 1
+                                          , currency:  None
+                                          , available: dec!(10.75)
+                                          , held:      dec!(0)
+                                          , total:     dec!(10.75)
+                                          , locked:    false
+                                          }
+                                 ]);
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::tx::{self, Engine, Options, Report, TxError};
+use csv::{Position, StringRecord};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Where and how often to checkpoint while reading a file.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    pub path:   PathBuf,
+    /// Number of records between two checkpoints, or `0` to only
+    /// checkpoint at the end.
+    pub every:  u64,
+    /// Continues from the checkpoint at `path`, if there is one,
+    /// instead of from the start of the file.
+    pub resume: bool,
+}
+
+/// The engine state after a prefix of the file, together with the
+/// position of the first record after it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    byte:   u64,
+    line:   u64,
+    engine: Engine,
+}
+
+/// Reads the transactions from a file in order and applies them to
+/// `engine`, saving the engine state and the position in the file to a
+/// checkpoint every `checkpoints.every` records and at the end. With
+/// `checkpoints.resume`, the engine and the position are restored from
+/// the last checkpoint instead, so a crashed run does not have to read
+/// the whole file again. The records before the checkpoint are not
+/// reported, or counted in the `Stats`, again.
+///
+/// The transactions are applied on the current thread, so that every
+/// checkpoint is a consistent cut of the file.
+pub fn process_path( path:        &PathBuf
+                   , options:     &Options
+                   , checkpoints: &Checkpoints
+                   , engine:      Engine
+                   ) -> Result<(Engine, Report), TxError> {
+    let mut checkpoint = if checkpoints.resume && checkpoints.path.exists() {
+        load(&checkpoints.path)?
+    } else {
+        Checkpoint { byte: 0, line: 0, engine }
+    };
+    checkpoint.engine.options = options.clone();
+    let mut rdr = tx::reader_from_path(path, &options.csv)
+        .map_err(|source| TxError::Read { path: path.clone(), source })?;
+    let headers = options.csv.headers(&mut rdr).map_err(|e| tx::parse_error(e, None))?;
+    if let Some(quarantine) = &options.quarantine {
+        quarantine.columns(rdr.has_headers().then(|| rdr.headers().ok()).flatten(), &headers);
+    }
+    if checkpoint.byte > 0 {
+        info!("Resuming from line {} of {:?}", checkpoint.line, path);
+        let mut pos = Position::new();
+        pos.set_byte(checkpoint.byte).set_line(checkpoint.line);
+        rdr.seek(pos).map_err(|e| tx::parse_error(e, None))?;
+    }
+
+    let mut report = Report::default();
+    let mut record = StringRecord::new();
+    let mut read = 0;
+    while let Some(parsed) = tx::read_parsed(&mut rdr, &mut record, Some(&headers), None, 0, options) {
+        if let Some(threshold) = &options.max_errors {
+            if parsed.is_ok() {
+                threshold.count_row();
+            }
+            threshold.check(false)?;
+        }
+        if tx::keep(&parsed, options.strict) && tx::selected(&parsed, options.clients.as_ref()) {
+            let (line, txn) = parsed?;
+            let txn = txn.in_unit(options.amounts);
+            report.stats.count_read(&txn);
+            let rejected = options.keeps_rejected().then(|| txn.clone());
+            if let Err(reason) = checkpoint.engine.apply(txn) {
+                report.reject(line, rejected, reason, options);
+            }
+        }
+        read += 1;
+        if checkpoints.every > 0 && read % checkpoints.every == 0 {
+            checkpoint.byte = rdr.position().byte();
+            checkpoint.line = rdr.position().line();
+            save(&checkpoints.path, &checkpoint)?;
+        }
+    }
+    if let Some(threshold) = &options.max_errors {
+        threshold.check(true)?;
+    }
+    checkpoint.byte = rdr.position().byte();
+    checkpoint.line = rdr.position().line();
+    save(&checkpoints.path, &checkpoint)?;
+    report.stats.count_ledgers(checkpoint.engine.ledgers.values());
+    Ok((checkpoint.engine, report.named(std::slice::from_ref(path))))
+}
+
+/// Writes the checkpoint to a temporary file first, and renames it,
+/// so a crash while saving leaves the previous checkpoint intact.
+fn save(path: &Path, checkpoint: &Checkpoint) -> Result<(), TxError> {
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut writer, checkpoint)?;
+    writer.flush()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Checkpoint, TxError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_process_path_resume() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,10
+                        deposit,2,2,5")?;
+        let dir = tempfile::tempdir()?;
+        let checkpoints = Checkpoints { path: dir.path().join("checkpoint.json"), every: 1, resume: true };
+        let path = file.path().to_path_buf();
+        let options = Options { report: true, ..Options::default() };
+
+        /*
+         * When
+         */
+        let (first, _) = process_path(&path, &options, &checkpoints, Engine::new())?;
+        writeln!(file, "withdrawal,2,3,20
+                        dispute,1,1,")?;
+        let (second, report) = process_path(&path, &options, &checkpoints, Engine::new())?;
+
+        /*
+         * Then
+         */
+        assert_eq!(first.account(1).map(|a| a.available()), Some(dec!(10)));
+        assert_eq!(second.account(1).map(|a| a.held()), Some(dec!(10)));
+        assert_eq!(second.account(2).map(|a| a.available()), Some(dec!(5)));
+        assert_eq!(report.rejections.iter().map(|r| r.line).collect::<Vec<u64>>(), vec![4]);
+        assert_eq!(report.stats.read, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_path_malformed() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount
+                        deposit,1,1,1
+                        withdrawal
+                        deposit,1,2,2")?;
+        let dir = tempfile::tempdir()?;
+        let checkpoints = Checkpoints { path: dir.path().join("checkpoint.json"), every: 1, resume: false };
+        let path = file.path().to_path_buf();
+        let funds = |account: &tx::Account| (account.client_id(), account.available(), account.held(), account.is_locked());
+
+        /*
+         * When
+         */
+        let (engine, _) = process_path(&path, &Options::default(), &checkpoints, Engine::new())?;
+        let (accounts, _) = futures::executor::block_on(tx::process_paths(std::slice::from_ref(&path), &Options::default()))?;
+        let strict = process_path(&path, &Options { strict: true, ..Options::default() }, &checkpoints, Engine::new());
+
+        /*
+         * Then
+         */
+        assert_eq!(engine.accounts().map(funds).collect::<Vec<_>>(), accounts.iter().map(funds).collect::<Vec<_>>());
+        assert_eq!(engine.account(1).map(|a| a.available()), Some(dec!(3)));
+        assert!(matches!(strict, Err(TxError::Parse { .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use crate::tx::Account;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The clients whose transactions are applied, as a list of ids and of
+/// ranges of ids, e.g. `1,5,100-200`. It is set in `Options::clients`,
+/// and the transactions of the other clients are skipped as they are
+/// read, before any worker is set up for them.
+///
+/// The accounts of the selected clients are as if all transactions were
+/// applied, unless they receive transfers: a transfer is only applied
+/// if its sender is selected as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientFilter {
+    ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl ClientFilter {
+    /// Returns `true` if the client is selected.
+    pub fn contains(&self, client: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&client))
+    }
+
+    /// Keeps only the accounts of the selected clients, e.g. those that
+    /// were credited by a transfer from a selected client.
+    pub fn accounts(&self, accounts: Vec<Account>) -> Vec<Account> {
+        accounts.into_iter().filter(|account| self.contains(account.client_id)).collect()
+    }
+}
+
+impl std::str::FromStr for ClientFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ClientFilter, String> {
+        let invalid = |part: &str| format!("invalid client `{}`, expected a client id like `5` or a range like `100-200`", part);
+        let ranges = s.split(',').map(str::trim).map(|part| {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            match (first.trim().parse::<u16>(), last.trim().parse::<u16>()) {
+                (Ok(first), Ok(last)) if first <= last => Ok(first..=last),
+                _ => Err(invalid(part)),
+            }
+        }).collect::<Result<Vec<_>, String>>()?;
+        Ok(ClientFilter { ranges })
+    }
+}
+
+impl fmt::Display for ClientFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, range) in self.ranges.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            match range.start() == range.end() {
+                true => write!(f, "{}{}", separator, range.start())?,
+                false => write!(f, "{}{}-{}", separator, range.start(), range.end())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::Options;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_client_filter() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount,to
+                     deposit,1,1,10,
+                     deposit,2,2,10,
+                     deposit,150,3,10,
+                     transfer,1,4,4,2
+                     transfer,2,5,3,5
+                     withdrawal,2,6,x,
+                     ";
+        let clients: ClientFilter = "1, 5,100-200".parse()?;
+        let options = Options { clients: Some(clients.clone()), ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, report) = futures::executor::block_on(crate::tx::process_reader(std::io::Cursor::new(input), &options))?;
+        let accounts = clients.accounts(accounts);
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.iter().map(|account| (account.client_id, account.total)).collect::<Vec<_>>(),
+                   vec![(1, dec!(6)), (150, dec!(10))]);
+        assert_eq!(report.stats.read, 3);
+        assert_eq!(clients.to_string(), "1,5,100-200");
+        assert!("200-100".parse::<ClientFilter>().is_err() && "1,".parse::<ClientFilter>().is_err() && "x".parse::<ClientFilter>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,906 @@
+use crate::tx::{shard_paths, CsvFormat, Transaction, TransactionKind, TxError};
+use rust_decimal::Decimal;
+use csv::WriterBuilder;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+use rayon::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The number of the last generated transactions that a transaction can
+/// refer to, and of the deposits and disputes a lifecycle can continue.
+const HISTORY: usize = 100_000;
+
+/// The time of the first generated row with `timestamps`, 2024-01-01
+/// 00:00:00 UTC in milliseconds since the epoch, so that the same seed
+/// generates the same times.
+const START: u64 = 1_704_067_200_000;
+
+/// How the amounts of the generated transactions are distributed between
+/// their smallest and largest amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Distribution {
+    /// Every amount is as likely.
+    #[default]
+    Uniform,
+    /// Most amounts are small and a few are large, with the median at
+    /// the geometric mean of the smallest and largest amount.
+    LogNormal,
+}
+
+impl Distribution {
+    pub const NAMES: &'static [&'static str] = &["uniform", "lognormal"];
+}
+
+impl std::str::FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Distribution, String> {
+        match s {
+            "uniform"   => Ok(Distribution::Uniform),
+            "lognormal" => Ok(Distribution::LogNormal),
+            _           => Err(format!("unknown distribution `{}`", s)),
+        }
+    }
+}
+
+/// How the generated transactions are distributed over the clients.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClientDistribution {
+    /// Every client is as likely.
+    #[default]
+    Uniform,
+    /// Client `k` is drawn with a weight of `1 / k^s`, so that a few
+    /// clients have most of the transactions, as in real workloads.
+    Zipf(f64),
+}
+
+impl std::str::FromStr for ClientDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ClientDistribution, String> {
+        match s.split_once(':') {
+            None if s == "uniform" => Ok(ClientDistribution::Uniform),
+            Some(("zipf", exponent)) => match exponent.parse::<f64>() {
+                Ok(exponent) if exponent > 0.0 && exponent.is_finite() => Ok(ClientDistribution::Zipf(exponent)),
+                _ => Err(format!("invalid exponent `{}`, expected a positive number like `zipf:1.1`", exponent)),
+            },
+            _ => Err(format!("unknown client distribution `{}`, expected `uniform` or `zipf:<s>`", s)),
+        }
+    }
+}
+
+/// The weights of the kinds of the generated transactions, relative to
+/// each other, e.g. a dispute for every two deposits with a `dispute` of
+/// 1 and a `deposit` of 2.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ratios {
+    pub deposit:    f64,
+    pub withdrawal: f64,
+    pub dispute:    f64,
+    pub resolve:    f64,
+    pub chargeback: f64,
+    pub unlock:     f64,
+    pub adjustment: f64,
+}
+
+impl Ratios {
+    /// Every kind is as likely.
+    pub const RANDOM: Ratios = Ratios { deposit: 1.0, withdrawal: 1.0, dispute: 1.0, resolve: 1.0, chargeback: 1.0, unlock: 1.0, adjustment: 1.0 };
+    /// The mix of `Generator::lifecycles`, in which an account is only
+    /// unlocked before its next deposit or withdrawal otherwise.
+    pub const LIFECYCLES: Ratios = Ratios { deposit: 40.0, withdrawal: 36.0, dispute: 12.0, resolve: 9.0, chargeback: 3.0, unlock: 0.0, adjustment: 0.0 };
+
+    /// Draws a kind by its weight, among those that `can` be generated
+    /// next, or a deposit if none of them has a weight.
+    fn draw(&self, rng: &mut impl Rng, can: impl Fn(TransactionKind) -> bool) -> TransactionKind {
+        let kinds = [ (TransactionKind::Deposit,    self.deposit)
+                    , (TransactionKind::Withdrawal, self.withdrawal)
+                    , (TransactionKind::Dispute,    self.dispute)
+                    , (TransactionKind::Resolve,    self.resolve)
+                    , (TransactionKind::Chargeback, self.chargeback)
+                    , (TransactionKind::Unlock,     self.unlock)
+                    , (TransactionKind::Adjustment, self.adjustment)
+                    ];
+        let kinds = kinds.iter().copied().filter(|&(kind, weight)| weight > 0.0 && can(kind)).collect::<Vec<_>>();
+        let mut weight = rng.gen::<f64>() * kinds.iter().map(|(_, weight)| weight).sum::<f64>();
+        for (kind, w) in kinds {
+            if weight < w {
+                return kind;
+            }
+            weight -= w;
+        }
+        TransactionKind::Deposit
+    }
+}
+
+/// Which random transactions to generate: how many, for how many clients,
+/// and the seed to draw them from, if they should be the same on every run.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub num_txns:       u32,
+    pub num_clients:    u16,
+    pub seed:           Option<u64>,
+    /// Generates valid lifecycles of disputes instead of transactions that
+    /// refer to any earlier one: a dispute only of a deposit that is not
+    /// disputed, a resolve or chargeback only of an open dispute, and a
+    /// withdrawal only of available funds.
+    pub lifecycles:     bool,
+    /// The share of the transactions, from 0 to 1, that are made invalid
+    /// on purpose with `lifecycles`, e.g. a resolve of a deposit that is
+    /// not disputed. They are rejected, and change no account.
+    pub invalid_rate:   f64,
+    /// The smallest and largest amount, in ten-thousandths, e.g. `10000`
+    /// for `1.0`.
+    pub min_amount:     i64,
+    pub max_amount:     i64,
+    pub amounts:        Distribution,
+    pub clients:        ClientDistribution,
+    /// The share of the rows, from 0 to 1, that are malformed on purpose,
+    /// e.g. with an unknown type or an amount that is not a number. They
+    /// are skipped when read, unless `Options::strict` is set.
+    pub malformed_rate: f64,
+    /// The mix of the kinds, or `Ratios::RANDOM`, or `Ratios::LIFECYCLES`
+    /// with `lifecycles`, if none is set.
+    pub ratios:         Option<Ratios>,
+    /// The currencies the transactions are drawn in, e.g. `EUR` and `USD`,
+    /// or none to leave the `currency` column empty. A dispute, resolve
+    /// or chargeback is in the currency of the transaction it refers to.
+    pub currencies:     Vec<String>,
+    /// Adds a `timestamp` column, with a time that is up to a second
+    /// later from one transaction to the next, from `START`.
+    pub timestamps:     bool,
+}
+
+impl Default for Generator {
+    fn default() -> Generator {
+        Generator { num_txns:       10000
+                  , num_clients:    100
+                  , seed:           None
+                  , lifecycles:     false
+                  , invalid_rate:   0.0
+                  , min_amount:     1
+                  , max_amount:     10_000_000
+                  , amounts:        Distribution::Uniform
+                  , clients:        ClientDistribution::Uniform
+                  , malformed_rate: 0.0
+                  , ratios:         None
+                  , currencies:     vec![]
+                  , timestamps:     false
+                  }
+    }
+}
+
+/// A generated row: a transaction, with its time in milliseconds since
+/// the epoch with `timestamps`, or the fields of a malformed row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Txn(Transaction, Option<u64>),
+    Malformed(Vec<String>),
+}
+
+impl Generator {
+    /// Returns the random rows as they are drawn, from a `StdRng` seeded
+    /// with the seed if there is one. Only the last `HISTORY` transactions
+    /// are kept to refer to, so any number of rows takes bounded memory.
+    pub fn rows(&self) -> Rows<'_> {
+        self.parts(1).remove(0)
+    }
+
+    /// Returns the random rows split into `parts` by client, with client
+    /// `k` in part `(k - 1) % parts`, so that they can be drawn at once.
+    /// Each part has the share of the transactions of its clients, a seed
+    /// of its own, and with `lifecycles` a range of ids of its own. A
+    /// transaction only refers to one of its part, so the transactions of
+    /// a client are in order within the part they are all in.
+    pub fn parts(&self, parts: usize) -> Vec<Rows<'_>> {
+        let parts = parts.max(1);
+        let clients = (0..parts).map(|part| Clients::new(self, part, parts)).collect::<Vec<_>>();
+        let total = clients.iter().map(Clients::weight).sum::<f64>();
+        let (mut weight, mut first) = (0.0, 0);
+        clients.into_iter()
+            .enumerate()
+            .map(|(part, clients)| {
+                weight += clients.weight();
+                let last = match total > 0.0 {
+                    true => ((self.num_txns as f64 * weight / total).round() as u32).min(self.num_txns),
+                    false => 0,
+                };
+                let rng = match self.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed ^ (part as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+                    None => StdRng::from_rng(thread_rng()).expect("thread_rng does not fail"),
+                };
+                let rows = Rows { generator:  self
+                                , rng
+                                , clients
+                                , lifecycles: Lifecycles { last_tx: first, ..Lifecycles::default() }
+                                , earlier:    VecDeque::new()
+                                , time:       START
+                                , left:       last - first
+                                };
+                first = last;
+                rows
+            })
+            .collect()
+    }
+
+    /// Returns the transactions of the random rows, without the malformed
+    /// ones.
+    pub fn txns(&self) -> Vec<Transaction> {
+        self.rows()
+            .filter_map(|row| match row {
+                Row::Txn(txn, _) => Some(txn),
+                Row::Malformed(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the columns of the rows: those of `CsvFormat::COLUMNS`, and
+    /// a `timestamp` column with `timestamps`.
+    pub fn columns(&self) -> Vec<&'static str> {
+        let timestamp = self.timestamps.then_some("timestamp");
+        CsvFormat::COLUMNS.iter().copied().chain(timestamp).collect()
+    }
+
+    /// Returns the mix of the kinds.
+    fn ratios(&self) -> Ratios {
+        match (self.ratios, self.lifecycles) {
+            (Some(ratios), _) => ratios,
+            (None, true) => Ratios::LIFECYCLES,
+            (None, false) => Ratios::RANDOM,
+        }
+    }
+
+    /// Returns the index of a random currency, or `0` if there are none.
+    fn currency(&self, rng: &mut impl Rng) -> usize {
+        match self.currencies.len() {
+            0 => 0,
+            n => rng.gen_range(0..n),
+        }
+    }
+
+    /// Returns a transaction in the currency at `index`, if there are any.
+    fn in_currency(&self, mut txn: Transaction, index: usize) -> Transaction {
+        txn.currency = self.currencies.get(index).cloned();
+        txn
+    }
+
+    /// Returns a random amount from `min_amount` to `max_amount`, in
+    /// ten-thousandths.
+    fn amount(&self, rng: &mut impl Rng) -> i64 {
+        let (min, max) = (self.min_amount.max(0), self.max_amount.max(self.min_amount).max(0));
+        match self.amounts {
+            Distribution::Uniform => rng.gen_range(min..=max),
+            Distribution::LogNormal => {
+                // A normal sample by the Box-Muller transform, scaled so
+                // that three standard deviations span the range.
+                let (low, high) = ((min.max(1) as f64).ln(), (max.max(1) as f64).ln());
+                let normal = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (std::f64::consts::TAU * rng.gen::<f64>()).cos();
+                let amount = ((low + high) / 2.0 + normal * (high - low) / 6.0).exp().round() as i64;
+                amount.clamp(min, max)
+            },
+        }
+    }
+}
+
+/// The random rows of a `Generator`, drawn one at a time.
+pub struct Rows<'a> {
+    generator:  &'a Generator,
+    rng:        StdRng,
+    clients:    Clients,
+    lifecycles: Lifecycles,
+    /// The last transactions, which the next one can refer to.
+    earlier:    VecDeque<Transaction>,
+    /// The time of the last transaction, with `timestamps`.
+    time:       u64,
+    left:       u32,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        self.left = self.left.checked_sub(1)?;
+        let (generator, rng) = (self.generator, &mut self.rng);
+        if generator.malformed_rate > 0.0 && rng.gen_bool(generator.malformed_rate.clamp(0.0, 1.0)) {
+            return Some(Row::Malformed(malformed(generator, &self.clients, rng)));
+        }
+        let txn = match generator.lifecycles {
+            true => self.lifecycles.txn(generator, &self.clients, rng),
+            false => {
+                let txn = random_txn(&self.earlier, generator, &self.clients, rng);
+                if self.earlier.len() == HISTORY {
+                    self.earlier.pop_front();
+                }
+                self.earlier.push_back(txn.clone());
+                txn
+            },
+        };
+        let time = generator.timestamps.then(|| {
+            self.time += self.rng.gen_range(1..=1000);
+            self.time
+        });
+        Some(Row::Txn(txn, time))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left as usize, Some(self.left as usize))
+    }
+}
+
+/// Writes rows as csv, with a header row of `columns`, e.g. those of
+/// `Generator::columns`. The malformed rows are written as they are, and
+/// may have fewer columns than the header.
+pub fn write_rows(writer: impl io::Write, columns: &[&str], rows: impl IntoIterator<Item = Row>) -> Result<(), TxError> {
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(writer);
+    wtr.write_record(columns).map_err(io::Error::from)?;
+    for row in rows {
+        match row {
+            Row::Txn(txn, None) => wtr.serialize(txn),
+            Row::Txn(txn, Some(time)) => wtr.serialize((txn, timestamp(time))),
+            Row::Malformed(fields) => wtr.write_record(&fields),
+        }.map_err(io::Error::from)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the rows of a generator to `shards` csv files, each with the
+/// next part of them, so that reading the files in order reads the rows
+/// in order. One shard is written to `out`, and more to `out` with the
+/// number of the shard appended to its stem, padded to sort in order,
+/// e.g. `txns-01.csv` to `txns-12.csv`. Returns the paths of the files.
+pub fn write_shards(out: &Path, shards: usize, generator: &Generator) -> Result<Vec<PathBuf>, TxError> {
+    let paths = shard_paths(out, shards);
+    let size = (generator.num_txns as usize).div_ceil(paths.len()).max(1);
+    let mut rows = generator.rows();
+    for path in &paths {
+        let file = io::BufWriter::new(std::fs::File::create(path)?);
+        write_rows(file, &generator.columns(), rows.by_ref().take(size))?;
+    }
+    Ok(paths)
+}
+
+/// Writes the rows of a generator to `shards` csv files like
+/// `write_shards`, but split by client with `Generator::parts` instead,
+/// each drawn and written on a thread of its own. Every client is in one
+/// of the files, with its transactions in order, so that reading them in
+/// any order leads to the same accounts. Returns the paths of the files.
+pub fn write_parts(out: &Path, shards: usize, generator: &Generator) -> Result<Vec<PathBuf>, TxError> {
+    let paths = shard_paths(out, shards);
+    paths.par_iter()
+        .zip(generator.parts(paths.len()))
+        .try_for_each(|(path, rows)| {
+            let file = io::BufWriter::new(std::fs::File::create(path)?);
+            write_rows(file, &generator.columns(), rows)
+        })?;
+    Ok(paths)
+}
+
+/// Draws the clients of the transactions of a part of the rows, those
+/// from 1 to `num_clients` in the part.
+struct Clients {
+    /// The first client, and the step to the next one.
+    first:   u16,
+    step:    u16,
+    count:   u16,
+    /// The cumulative weights of the clients in order, unless every one
+    /// is as likely.
+    weights: Option<Vec<f64>>,
+}
+
+impl Clients {
+    fn new(generator: &Generator, part: usize, parts: usize) -> Clients {
+        let (first, step) = (part as u16 + 1, parts.min(u16::MAX as usize) as u16);
+        let count = match generator.num_clients >= first {
+            true => (generator.num_clients - first) / step + 1,
+            false => 0,
+        };
+        let weights = match generator.clients {
+            ClientDistribution::Uniform => None,
+            ClientDistribution::Zipf(exponent) => {
+                let weights = (0..count).scan(0.0, |sum, i| {
+                    *sum += 1.0 / ((first + i * step) as f64).powf(exponent);
+                    Some(*sum)
+                });
+                Some(weights.collect())
+            },
+        };
+        Clients { first, step, count, weights }
+    }
+
+    /// Returns the sum of the weights of the clients.
+    fn weight(&self) -> f64 {
+        match &self.weights {
+            None => self.count as f64,
+            Some(weights) => weights.last().copied().unwrap_or_default(),
+        }
+    }
+
+    fn draw(&self, rng: &mut impl Rng) -> u16 {
+        let i = match &self.weights {
+            None => rng.gen_range(0..self.count),
+            Some(weights) => {
+                let weight = rng.gen::<f64>() * self.weight();
+                weights.partition_point(|&sum| sum <= weight).min(weights.len().saturating_sub(1)) as u16
+            },
+        };
+        self.first + i * self.step
+    }
+}
+
+fn random_txn(acc: &VecDeque<Transaction>, generator: &Generator, clients: &Clients, rng: &mut impl Rng) -> Transaction {
+    use TransactionKind::*;
+    let kind = generator.ratios().draw(rng, |kind| !acc.is_empty() || matches!(kind, Deposit | Withdrawal | Adjustment));
+    let earlier = (!acc.is_empty()).then(|| &acc[rng.gen_range(0..acc.len())]);
+    let (client_id, tx_id, amount) =
+        match (kind, earlier) {
+            (Dispute | Resolve | Chargeback, Some(txn)) => (txn.client_id, txn.tx_id, None),
+            (Unlock, Some(txn)) => (txn.client_id, rng.gen::<u32>(), None),
+            (Adjustment, _) => (clients.draw(rng), rng.gen::<u32>(), Some(generator.amount(rng) * if rng.gen() { 1 } else { -1 })),
+            _ => (clients.draw(rng), rng.gen::<u32>(), Some(generator.amount(rng))),
+        };
+    let txn = Transaction::new(kind, client_id, tx_id, amount);
+    match (amount, earlier) {
+        (None, Some(earlier)) => Transaction { currency: earlier.currency.clone(), ..txn },
+        _ => generator.in_currency(txn, generator.currency(rng)),
+    }
+}
+
+/// Returns a time in milliseconds since the epoch in RFC 3339, e.g.
+/// `2024-01-01T00:00:00.000Z`.
+fn timestamp(ms: u64) -> String {
+    let (days, ms) = (ms / 86_400_000, ms % 86_400_000);
+    // the civil date of a number of days since the epoch, by the
+    // algorithm of Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!( "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z"
+           , year, month, day, ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000
+           )
+}
+
+/// Returns the fields of a row that can not be parsed, like the malformed
+/// rows of the tests: an unknown type, missing fields, or a client, id or
+/// amount that is not a number.
+fn malformed(generator: &Generator, clients: &Clients, rng: &mut impl Rng) -> Vec<String> {
+    let client = clients.draw(rng).to_string();
+    let tx = rng.gen::<u32>().to_string();
+    let amount = Decimal::new(generator.amount(rng), 4).to_string();
+    let garbage = ["abc", "1.2.3", "1,5", "--5", "0x10"].choose(rng).copied().unwrap_or_default();
+    let fields = match rng.gen_range(0..5) {
+        0 => vec![["deposti", "withdraw", "DEPOSIT!", "refund"].choose(rng).copied().unwrap_or_default(), &client, &tx, &amount],
+        1 => vec!["deposit", &client],
+        2 => vec!["withdrawal", &client, &tx, garbage],
+        3 => vec!["deposit", garbage, &tx, &amount],
+        _ => vec!["dispute", &client, garbage, ""],
+    };
+    fields.into_iter().map(String::from).collect()
+}
+
+/// The accounts that the transactions generated so far lead to, as far
+/// as they are needed to generate the next transaction of a lifecycle.
+#[derive(Debug, Default)]
+struct Lifecycles {
+    /// The id of the last transaction.
+    last_tx:  u32,
+    clients:  HashMap<Key, Client>,
+    /// The deposits that can be disputed, by account, id and amount.
+    deposits: Vec<(Key, u32, i64)>,
+    /// The deposits under dispute.
+    disputes: Vec<(Key, u32, i64)>,
+}
+
+/// An account of a lifecycle, by client and the index of its currency
+/// among the currencies of the generator.
+type Key = (u16, usize);
+
+#[derive(Debug, Default)]
+struct Client {
+    available: i64,
+    locked:    bool,
+}
+
+impl Lifecycles {
+    /// Returns the next transaction, which is valid unless it is drawn
+    /// to be invalid at the `invalid_rate` of the generator.
+    fn txn(&mut self, generator: &Generator, clients: &Clients, rng: &mut impl Rng) -> Transaction {
+        if rng.gen_bool(generator.invalid_rate.clamp(0.0, 1.0)) {
+            return self.invalid(generator, clients, rng);
+        }
+        let kind = generator.ratios().draw(rng, |kind| match kind {
+            TransactionKind::Dispute => !self.deposits.is_empty(),
+            TransactionKind::Resolve | TransactionKind::Chargeback => !self.disputes.is_empty(),
+            TransactionKind::Unlock => self.clients.values().any(|client| client.locked),
+            _ => true,
+        });
+        let (key, txn) = match kind {
+            TransactionKind::Dispute => {
+                let (key, tx_id, amount) = self.deposits.swap_remove(rng.gen_range(0..self.deposits.len()));
+                self.client(key).available -= amount;
+                keep(&mut self.disputes, (key, tx_id, amount), rng);
+                (key, Transaction::dispute(key.0, tx_id))
+            },
+            TransactionKind::Resolve => {
+                let (key, tx_id, amount) = self.disputes.swap_remove(rng.gen_range(0..self.disputes.len()));
+                self.client(key).available += amount;
+                keep(&mut self.deposits, (key, tx_id, amount), rng);
+                (key, Transaction::resolve(key.0, tx_id))
+            },
+            TransactionKind::Chargeback => {
+                let (key, tx_id, _) = self.disputes.swap_remove(rng.gen_range(0..self.disputes.len()));
+                self.client(key).locked = true;
+                (key, Transaction::chargeback(key.0, tx_id))
+            },
+            TransactionKind::Unlock => {
+                let locked = self.clients.iter().filter(|(_, client)| client.locked).map(|(&key, _)| key).collect::<Vec<_>>();
+                let key = locked[rng.gen_range(0..locked.len())];
+                self.client(key).locked = false;
+                (key, Transaction::unlock(key.0, self.tx()))
+            },
+            TransactionKind::Adjustment => {
+                let key = (clients.draw(rng), generator.currency(rng));
+                let tx_id = self.tx();
+                let client = self.client(key);
+                // a debit only of available funds, so that a withdrawal
+                // or dispute later on still finds them
+                let amount = match rng.gen() && client.available > 0 {
+                    true => -generator.amount(rng).clamp(1, client.available),
+                    false => generator.amount(rng),
+                };
+                client.available += amount;
+                (key, Transaction::new(TransactionKind::Adjustment, key.0, tx_id, Some(amount)))
+            },
+            kind => {
+                let key = (clients.draw(rng), generator.currency(rng));
+                let tx_id = self.tx();
+                let client = self.client(key);
+                if client.locked {
+                    client.locked = false;
+                    (key, Transaction::unlock(key.0, tx_id))
+                } else if kind == TransactionKind::Withdrawal && client.available > 0 {
+                    let amount = generator.amount(rng).clamp(1, client.available);
+                    client.available -= amount;
+                    (key, Transaction::new(TransactionKind::Withdrawal, key.0, tx_id, Some(amount)))
+                } else {
+                    let amount = generator.amount(rng);
+                    client.available += amount;
+                    keep(&mut self.deposits, (key, tx_id, amount), rng);
+                    (key, Transaction::new(TransactionKind::Deposit, key.0, tx_id, Some(amount)))
+                }
+            },
+        };
+        generator.in_currency(txn, key.1)
+    }
+
+    /// Returns a transaction that is rejected, and so changes no account:
+    /// a resolve or chargeback of a deposit that is not disputed, a
+    /// dispute of a deposit that is, or a withdrawal of more than the
+    /// available funds.
+    fn invalid(&mut self, generator: &Generator, clients: &Clients, rng: &mut impl Rng) -> Transaction {
+        let key = (clients.draw(rng), generator.currency(rng));
+        let (key, txn) = match rng.gen_range(0..4) {
+            0 => match self.deposits.choose(rng) {
+                Some(&(key, tx_id, _)) => (key, Transaction::resolve(key.0, tx_id)),
+                None => (key, Transaction::resolve(key.0, self.tx())),
+            },
+            1 => match self.deposits.choose(rng) {
+                Some(&(key, tx_id, _)) => (key, Transaction::chargeback(key.0, tx_id)),
+                None => (key, Transaction::chargeback(key.0, self.tx())),
+            },
+            2 => match self.disputes.choose(rng) {
+                Some(&(key, tx_id, _)) => (key, Transaction::dispute(key.0, tx_id)),
+                None => (key, Transaction::dispute(key.0, self.tx())),
+            },
+            _ => {
+                let available = self.clients.get(&key).map_or(0, |client| client.available.max(0));
+                let amount = available + generator.amount(rng).max(1);
+                (key, Transaction::new(TransactionKind::Withdrawal, key.0, self.tx(), Some(amount)))
+            },
+        };
+        generator.in_currency(txn, key.1)
+    }
+
+    fn client(&mut self, key: Key) -> &mut Client {
+        self.clients.entry(key).or_default()
+    }
+
+    /// Returns the id of a new transaction.
+    fn tx(&mut self) -> u32 {
+        self.last_tx += 1;
+        self.last_tx
+    }
+}
+
+/// Adds a deposit or dispute to those a lifecycle can continue, in place
+/// of a random one once there are `HISTORY` of them, which then stays as
+/// it is.
+fn keep(kept: &mut Vec<(Key, u32, i64)>, txn: (Key, u32, i64), rng: &mut impl Rng) {
+    match kept.len() < HISTORY {
+        true => kept.push(txn),
+        false => kept[rng.gen_range(0..HISTORY)] = txn,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_random_txns_seeded() {
+        /*
+         * Given
+         */
+        let seeded = |seed| Generator { num_txns: 100, num_clients: 5, seed: Some(seed), ..Generator::default() }.txns();
+
+        /*
+         * When
+         */
+        let (first, again, other) = (seeded(42), seeded(42), seeded(43));
+
+        /*
+         * Then
+         */
+        assert_eq!(first.len(), 100);
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert!(first.iter().all(|txn| (1..=5).contains(&txn.client_id)));
+    }
+
+    #[test]
+    fn test_write_shards() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("txns.csv");
+        let generator = Generator { num_txns: 25, num_clients: 5, seed: Some(1), ..Generator::default() };
+
+        /*
+         * When
+         */
+        let paths = write_shards(&out, 10, &generator)?;
+        let one = write_shards(&dir.path().join("one.csv"), 1, &generator)?;
+
+        /*
+         * Then
+         */
+        assert_eq!(paths.first(), Some(&dir.path().join("txns-01.csv")));
+        assert_eq!(paths.last(), Some(&dir.path().join("txns-10.csv")));
+        assert_eq!(one, vec![dir.path().join("one.csv")]);
+        let pattern = dir.path().join("txns-*.csv");
+        let (accounts, _) = futures::executor::block_on(crate::tx::process_paths(&crate::tx::expand_paths(&[pattern])?, &Default::default()))?;
+        let (expected, _) = futures::executor::block_on(crate::tx::process_paths(&one, &Default::default()))?;
+        assert_eq!(accounts, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_parts() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        let generator = Generator { num_txns: 3000, num_clients: 10, seed: Some(6), lifecycles: true, clients: ClientDistribution::Zipf(1.1), ..Generator::default() };
+
+        /*
+         * When
+         */
+        let parts = generator.parts(4).into_iter().map(Iterator::collect::<Vec<_>>).collect::<Vec<_>>();
+        let paths = write_parts(&dir.path().join("txns.csv"), 4, &generator)?;
+        let (accounts, report) = futures::executor::block_on(crate::tx::process_paths(&paths, &Default::default()))?;
+
+        /*
+         * Then
+         */
+        let txns = parts.iter().flatten().filter_map(|row| match row { Row::Txn(txn, _) => Some(txn), Row::Malformed(_) => None }).collect::<Vec<_>>();
+        let ids = txns.iter().filter(|txn| txn.amount.is_some()).map(|txn| txn.tx_id).collect::<std::collections::HashSet<_>>();
+        assert_eq!(txns.len(), 3000);
+        assert_eq!(ids.len(), txns.iter().filter(|txn| txn.amount.is_some()).count());
+        assert!(parts[0].len() > parts[3].len());
+        assert!(parts.iter().enumerate().all(|(part, rows)| rows.iter().all(|row| matches!(row, Row::Txn(txn, _) if (txn.client_id as usize - 1) % 4 == part))));
+        assert_eq!(report.stats.read, 3000);
+        assert_eq!(report.stats.rejected.values().sum::<u64>(), 0);
+        assert_eq!(accounts.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lifecycles() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let lifecycles = |invalid_rate| Generator { num_txns: 2000, num_clients: 20, seed: Some(7), lifecycles: true, invalid_rate, ..Generator::default() };
+        let rejected = |generator: Generator| -> Result<u64, crate::tx::TxError> {
+            let txns = crate::tx::numbered(generator.txns().into_iter());
+            let (_, report) = futures::executor::block_on(crate::tx::process(txns, &Default::default()))?;
+            Ok(report.stats.rejected.values().sum())
+        };
+
+        /*
+         * When
+         */
+        let txns = lifecycles(0.0).txns();
+        let valid = rejected(lifecycles(0.0))?;
+        let some = rejected(lifecycles(0.1))?;
+        let invalid = rejected(lifecycles(1.0))?;
+
+        /*
+         * Then
+         */
+        let count = |kind| txns.iter().filter(|txn| txn.kind == kind).count();
+        assert!(count(TransactionKind::Dispute) > 0 && count(TransactionKind::Resolve) > 0 && count(TransactionKind::Chargeback) > 0);
+        assert_eq!(valid, 0);
+        assert!((100..300).contains(&some), "{}", some);
+        assert_eq!(invalid, 2000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_amounts() {
+        /*
+         * Given
+         */
+        let amounts = |amounts| Generator { num_txns: 2000, seed: Some(3), min_amount: 10000, max_amount: 1_000_000, amounts, ..Generator::default() };
+
+        /*
+         * When
+         */
+        let uniform = amounts(Distribution::Uniform).txns();
+        let lognormal = amounts(Distribution::LogNormal).txns();
+
+        /*
+         * Then
+         */
+        let within = |txns: &[Transaction]| txns.iter().filter_map(|txn| txn.amount).all(|amount| (dec!(1)..=dec!(100)).contains(&amount.abs()));
+        let below = |txns: &[Transaction]| txns.iter().filter_map(|txn| txn.amount).filter(|amount| amount.abs() < dec!(10)).count();
+        assert!(within(&uniform) && within(&lognormal));
+        assert!(below(&lognormal) > below(&uniform) * 3 / 2, "{} {}", below(&lognormal), below(&uniform));
+        assert!("zipf".parse::<Distribution>().is_err());
+    }
+
+    #[test]
+    fn test_zipf_clients() {
+        /*
+         * Given
+         */
+        let clients = |clients| Generator { num_txns: 5000, num_clients: 50, seed: Some(5), clients, ..Generator::default() };
+        let zipf = "zipf:1.2".parse::<ClientDistribution>();
+
+        /*
+         * When
+         */
+        let uniform = clients(ClientDistribution::Uniform).txns();
+        let skewed = clients(ClientDistribution::Zipf(1.2)).txns();
+
+        /*
+         * Then
+         */
+        let share = |txns: &[Transaction]| txns.iter().filter(|txn| txn.client_id <= 5).count() * 100 / txns.len();
+        assert!(share(&uniform) < 20, "{}", share(&uniform));
+        assert!(share(&skewed) > 50, "{}", share(&skewed));
+        assert!(skewed.iter().all(|txn| (1..=50).contains(&txn.client_id)));
+        assert_eq!(zipf, Ok(ClientDistribution::Zipf(1.2)));
+        assert!("zipf:0".parse::<ClientDistribution>().is_err() && "normal".parse::<ClientDistribution>().is_err());
+    }
+
+    #[test]
+    fn test_malformed_rows() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let rows = Generator { num_txns: 1000, seed: Some(9), malformed_rate: 0.2, ..Generator::default() }.rows().collect::<Vec<_>>();
+        let mut csv = vec![];
+        write_rows(&mut csv, CsvFormat::COLUMNS, rows.clone())?;
+        let lines = csv.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count();
+
+        /*
+         * When
+         */
+        let (_, report) = futures::executor::block_on(crate::tx::process_reader(io::Cursor::new(csv.clone()), &Default::default()))?;
+        let strict = crate::tx::Options { strict: true, ..Default::default() };
+        let failed = futures::executor::block_on(crate::tx::process_reader(io::Cursor::new(csv), &strict));
+
+        /*
+         * Then
+         */
+        let malformed = rows.iter().filter(|row| matches!(row, Row::Malformed(_))).count() as u64;
+        assert!((150..250).contains(&malformed), "{}", malformed);
+        assert_eq!(lines, 1001);
+        assert_eq!(report.stats.read, 1000 - malformed);
+        assert!(matches!(failed, Err(TxError::Parse { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows_history() {
+        /*
+         * Given
+         */
+        let generator = Generator { num_txns: HISTORY as u32 + 100, seed: Some(2), ..Generator::default() };
+        let lifecycles = Generator { lifecycles: true, ..generator.clone() };
+
+        /*
+         * When
+         */
+        let mut rows = generator.rows();
+        let mut flows = lifecycles.rows();
+        let count = rows.by_ref().count();
+        flows.by_ref().for_each(drop);
+
+        /*
+         * Then
+         */
+        assert_eq!(count, HISTORY + 100);
+        assert_eq!(rows.earlier.len(), HISTORY);
+        assert!(flows.lifecycles.deposits.len() <= HISTORY && flows.lifecycles.disputes.len() <= HISTORY);
+        assert_eq!(generator.rows().size_hint(), (HISTORY + 100, Some(HISTORY + 100)));
+    }
+
+    #[test]
+    fn test_ratios() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let disputes = Ratios { deposit: 2.0, withdrawal: 0.0, dispute: 4.0, resolve: 1.0, chargeback: 1.0, unlock: 0.0, adjustment: 0.0 };
+        let ratios = |lifecycles| Generator { num_txns: 4000, seed: Some(8), lifecycles, ratios: Some(disputes), ..Generator::default() };
+
+        /*
+         * When
+         */
+        let random = ratios(false).txns();
+        let flows = ratios(true).txns();
+        let (_, report) = futures::executor::block_on(crate::tx::process(crate::tx::numbered(flows.clone().into_iter()), &Default::default()))?;
+
+        /*
+         * Then
+         */
+        let share = |txns: &[Transaction], kind| txns.iter().filter(|txn| txn.kind == kind).count() * 100 / txns.len();
+        assert!((45..55).contains(&share(&random, TransactionKind::Dispute)), "{}", share(&random, TransactionKind::Dispute));
+        assert_eq!(share(&random, TransactionKind::Withdrawal), 0);
+        assert!(share(&flows, TransactionKind::Dispute) > share(&flows, TransactionKind::Deposit) / 2);
+        assert_eq!(report.stats.rejected.values().sum::<u64>(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_currencies_and_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let currencies = vec![String::from("EUR"), String::from("USD")];
+        let generator = Generator { num_txns: 2000, num_clients: 20, seed: Some(4), lifecycles: true, currencies, timestamps: true, ..Generator::default() };
+
+        /*
+         * When
+         */
+        let rows = generator.rows().collect::<Vec<_>>();
+        let mut csv = vec![];
+        write_rows(&mut csv, &generator.columns(), rows.clone())?;
+        let (accounts, report) = futures::executor::block_on(crate::tx::process_reader(io::Cursor::new(csv.clone()), &Default::default()))?;
+
+        /*
+         * Then
+         */
+        let times = rows.iter().filter_map(|row| match row { Row::Txn(_, time) => *time, Row::Malformed(_) => None }).collect::<Vec<_>>();
+        assert_eq!(times.len(), 2000);
+        assert!(times[0] > START && times.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(report.stats.rejected.values().sum::<u64>(), 0);
+        assert!(accounts.iter().any(|account| account.currency.as_deref() == Some("USD")));
+        assert!(String::from_utf8(csv)?.starts_with("type,client,tx,amount,to,currency,timestamp\n"));
+        assert_eq!([timestamp(START), timestamp(951_827_696_789)], ["2024-01-01T00:00:00.000Z", "2000-02-29T12:34:56.789Z"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::tx::{Account, Options, Transaction, TransactionKind, TxError};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+/// An invariant of the accounts that a transaction broke.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Violation {
+    #[error("the total funds of client {0} are not its available plus its held funds")]
+    Balance(u16),
+    #[error("transaction {0} was rejected, but changed the account of client {1}")]
+    Rejected(u32, u16),
+    #[error("transaction {0} changed the total funds of client {1} by other than its amount")]
+    Total(u32, u16),
+    #[error("transaction {0} was applied to the locked account of client {1}")]
+    Locked(u32, u16),
+}
+
+/// Checks that the total funds of every account are its available plus
+/// its held funds.
+pub fn balances<'a>(accounts: impl IntoIterator<Item = &'a Account>) -> Result<(), Violation> {
+    match accounts.into_iter().find(|account| !account.balance_check()) {
+        Some(account) => Err(Violation::Balance(account.client_id)),
+        None => Ok(()),
+    }
+}
+
+/// Checks the account of the client of a transaction, `before` and
+/// `after` it was applied with `result`, e.g. by `Engine::apply`, as
+/// returned by `Engine::account_in` for the currency of the transaction,
+/// where `original` is the deposit or withdrawal handled before with its
+/// id, if any:
+///
+/// * a rejected transaction leaves the account as it was,
+/// * an applied transaction changes the total funds by its amount, e.g.
+///   a dispute or resolve of a deposit leaves them as they were, and a
+///   chargeback of a deposit takes the deposit back,
+/// * a locked account rejects deposits and withdrawals,
+///
+/// and that the total funds are its available plus its held funds.
+pub fn applied( txn: &Transaction
+              , result: &Result<(), TxError>
+              , original: Option<&Transaction>
+              , before: Option<&Account>
+              , after: Option<&Account>
+              , options: &Options
+              ) -> Result<(), Violation> {
+    let (tx, client) = (txn.tx_id, txn.client_id);
+    balances(after)?;
+    if result.is_err() && before.is_some() && before != after {
+        return Err(Violation::Rejected(tx, client));
+    }
+    if result.is_err() {
+        return Ok(());
+    }
+    if before.is_some_and(|before| before.locked) && matches!(txn.kind, TransactionKind::Deposit | TransactionKind::Withdrawal) {
+        return Err(Violation::Locked(tx, client));
+    }
+    let total = |account: Option<&Account>| account.map_or(Decimal::ZERO, |account| account.total);
+    if total(before) + total_change(txn, original, options) != total(after) {
+        return Err(Violation::Total(tx, client));
+    }
+    Ok(())
+}
+
+/// Returns how much an applied transaction changes the total funds of
+/// its client, where `original` is the transaction it refers to.
+fn total_change(txn: &Transaction, original: Option<&Transaction>, options: &Options) -> Decimal {
+    let amount = |txn: &Transaction| txn.amount.map_or(Decimal::ZERO, |amount| options.rounding.round(amount));
+    let original = original.map(|original| (original.kind, amount(original)));
+    match (txn.kind, original) {
+        (TransactionKind::Deposit | TransactionKind::Adjustment, _) => amount(txn),
+        (TransactionKind::Withdrawal | TransactionKind::Transfer, _) => -amount(txn),
+        (TransactionKind::Dispute, Some((TransactionKind::Withdrawal, amount))) => amount,
+        (TransactionKind::Resolve, Some((TransactionKind::Withdrawal, amount))) => -amount,
+        (TransactionKind::Chargeback, Some((TransactionKind::Deposit, amount))) => -amount,
+        _ => Decimal::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::storage::Storage;
+    use crate::tx::{DisputePolicy, Engine, LockedPolicy, TxState};
+    use proptest::prelude::*;
+
+    /// Returns a strategy of transactions over a few clients and ids, so
+    /// that they refer to each other, or none if the amount is invalid.
+    fn txn() -> impl Strategy<Value = Option<Transaction>> {
+        let amount = (-100..1_000_000i64, 0..6u32).prop_map(|(num, scale)| Decimal::new(num, scale));
+        (1..=4u16, 1..=16u32, 1..=4u16, amount).prop_flat_map(|(client, tx, to, amount)| prop_oneof![
+            2 => Just(Transaction::deposit(client, tx, amount).ok()),
+            1 => Just(Transaction::withdrawal(client, tx, amount).ok()),
+            1 => Just(Some(Transaction::dispute(client, tx))),
+            1 => Just(Some(Transaction::resolve(client, tx))),
+            1 => Just(Some(Transaction::chargeback(client, tx))),
+            1 => prop_oneof![ Just(Some(Transaction::unlock(client, tx)))
+                            , Just(Transaction::adjustment(client, tx, amount).ok())
+                            ],
+            1 => Just(Transaction::transfer(client, to, tx, amount).ok()),
+        ])
+    }
+
+    /// Returns a strategy of sequences of transactions.
+    fn txns() -> impl Strategy<Value = Vec<Transaction>> {
+        proptest::collection::vec(txn(), 0..200).prop_map(|txns| txns.into_iter().flatten().collect())
+    }
+
+    /// Returns a strategy of the options that change how disputes and
+    /// locked accounts are handled.
+    fn options() -> impl Strategy<Value = Options> {
+        let dispute_policy = prop_oneof![Just(DisputePolicy::DepositsOnly), Just(DisputePolicy::All)];
+        let locked_policy = prop_oneof![Just(LockedPolicy::Frozen), Just(LockedPolicy::AllowDisputes)];
+        (dispute_policy, locked_policy).prop_map(|(dispute_policy, locked_policy)| Options { dispute_policy
+                                                                                         , locked_policy
+                                                                                         , threads: Some(3)
+                                                                                         , ..Options::default()
+                                                                                         })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn test_invariants(txns in txns(), options in options()) {
+            /*
+             * Given
+             */
+            let mut engine = Engine::new().with_options(options.clone());
+
+            /*
+             * When
+             */
+            for txn in &txns {
+                let history = Storage::history(&engine, txn.client_id, txn.tx_id).unwrap();
+                let before = engine.account_in(txn.client_id, txn.currency()).cloned();
+                let result = engine.apply(txn.clone());
+                let after = engine.account_in(txn.client_id, txn.currency());
+
+                /*
+                 * Then
+                 */
+                prop_assert_eq!(applied(txn, &result, history.as_ref().and_then(TxState::original), before.as_ref(), after, &options), Ok(()));
+                prop_assert_eq!(balances(engine.accounts()), Ok(()));
+            }
+            let (sharded, _) = futures::executor::block_on(crate::tx::process(crate::tx::numbered(txns.into_iter()), &options)).unwrap();
+            prop_assert_eq!(balances(&sharded), Ok(()));
+            prop_assert_eq!(sharded, engine.into_accounts());
+        }
+    }
+
+    #[test]
+    fn test_violations() {
+        /*
+         * Given
+         */
+        let mut locked = Account::new(1);
+        locked.locked = true;
+        let mut unbalanced = Account::new(2);
+        unbalanced.total = Decimal::ONE;
+        let deposit = Transaction::new(TransactionKind::Deposit, 1, 7, Some(10000));
+        let options = Options { dispute_policy: DisputePolicy::DepositsOnly, ..Options::default() };
+        let mut held = Account::new(1);
+        (held.held, held.total) = (Decimal::ONE, Decimal::ONE);
+        let one = |kind| Transaction::new(kind, 1, 7, Some(10000));
+
+        /*
+         * When
+         */
+        let applied_to_locked = applied(&deposit, &Ok(()), None, Some(&locked), Some(&locked), &options);
+        let rejected = applied(&deposit, &Err(TxError::AccountLocked(1)), None, Some(&locked), Some(&Account::new(1)), &options);
+        let dispute = applied(&Transaction::dispute(1, 7), &Ok(()), Some(&one(TransactionKind::Deposit)), Some(&Account::new(1)), Some(&held), &options);
+        let withdrawal_dispute = applied(&Transaction::dispute(1, 7), &Ok(()), Some(&one(TransactionKind::Withdrawal)), Some(&Account::new(1)), Some(&held), &Options::default());
+        let chargeback = applied(&Transaction::chargeback(1, 7), &Ok(()), Some(&one(TransactionKind::Deposit)), Some(&held), Some(&Account::new(1)), &options);
+        let withdrawal_chargeback = applied(&Transaction::chargeback(1, 7), &Ok(()), Some(&one(TransactionKind::Withdrawal)), Some(&held), Some(&Account::new(1)), &options);
+
+        /*
+         * Then
+         */
+        assert_eq!(balances([&Account::new(1), &unbalanced]), Err(Violation::Balance(2)));
+        assert_eq!(applied_to_locked, Err(Violation::Locked(7, 1)));
+        assert_eq!(rejected, Err(Violation::Rejected(7, 1)));
+        assert_eq!(dispute, Err(Violation::Total(7, 1)));
+        assert_eq!(withdrawal_dispute, Ok(()));
+        assert_eq!(chargeback, Ok(()));
+        assert_eq!(withdrawal_chargeback, Err(Violation::Total(7, 1)));
+    }
+}
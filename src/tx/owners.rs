@@ -0,0 +1,239 @@
+use crate::tx::{self, Ledger, Options, Transaction, TxError};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Approximate number of bytes a transaction id with its clients takes
+/// in memory, with its slot in the map, not counting the clients.
+const ENTRY_BYTES: usize = std::mem::size_of::<(u32, Vec<u16>)>() + 16;
+
+/// Number of bytes of an id and a client in a `Run`.
+const RECORD_BYTES: u64 = 6;
+
+/// Number of runs spilled before they are merged into one.
+const MAX_RUNS: usize = 8;
+
+/// The clients of the deposits and withdrawals read so far, keyed by
+/// transaction id, to tell a dispute, resolve or chargeback of another
+/// client's transaction with `TxError::ClientMismatch`, and to reject a
+/// deposit or withdrawal that reuses the id of another client with
+/// `Options::global_unique_tx`.
+///
+/// They are kept in memory until they take more than the budget, and
+/// then written to a `Run` sorted by id, which is searched on disk. The
+/// runs are merged into one once there are `MAX_RUNS` of them.
+#[derive(Default)]
+pub(crate) struct Owners {
+    memory: HashMap<u32, Vec<u16>>,
+    bytes:  usize,
+    runs:   Vec<Run>,
+    budget: Option<usize>,
+}
+
+impl Owners {
+    /// Returns the owners of the transactions handled in the ledgers,
+    /// which may take `budget` bytes in memory.
+    pub(crate) fn of<'a>(ledgers: impl IntoIterator<Item = &'a Ledger>, budget: Option<usize>) -> io::Result<Owners> {
+        let mut owners = Owners { budget, ..Owners::default() };
+        for ledger in ledgers {
+            for &tx_id in ledger.handled.keys() {
+                owners.insert(tx_id, ledger.account.client_id)?;
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Checks the transaction against the clients of the transactions
+    /// read before with its id, and records its client if it is a
+    /// deposit or withdrawal that was not rejected. The client is
+    /// recorded whether or not the transaction is then applied, so that
+    /// the check does not depend on the order the shards apply them in.
+    pub(crate) fn check(&mut self, txn: &Transaction, options: &Options) -> io::Result<Result<(), TxError>> {
+        if !txn.disputable() && (!txn.refers() || options.allow_client_mismatch) {
+            return Ok(Ok(()));
+        }
+        let client_id = txn.client_id;
+        let clients = self.clients(txn.tx_id)?;
+        if txn.disputable() {
+            if options.global_unique_tx && clients.iter().any(|&c| c != client_id) {
+                if let Err(reason) = tx::check_duplicate(txn, options) {
+                    return Ok(Err(reason));
+                }
+            }
+            if !clients.contains(&client_id) {
+                self.insert(txn.tx_id, client_id)?;
+            }
+        } else if !clients.is_empty() && !clients.contains(&client_id) {
+            return Ok(Err(TxError::ClientMismatch { tx: txn.tx_id, client: client_id, owner: clients[0] }));
+        }
+        Ok(Ok(()))
+    }
+
+    /// Returns the clients of the id, those spilled first.
+    fn clients(&mut self, tx_id: u32) -> io::Result<Vec<u16>> {
+        let mut clients = Vec::new();
+        for run in &mut self.runs {
+            run.clients(tx_id, &mut clients)?;
+        }
+        clients.extend(self.memory.get(&tx_id).into_iter().flatten());
+        Ok(clients)
+    }
+
+    fn insert(&mut self, tx_id: u32, client_id: u16) -> io::Result<()> {
+        let clients = self.memory.entry(tx_id).or_default();
+        self.bytes += if clients.is_empty() { ENTRY_BYTES } else { 0 } + std::mem::size_of::<u16>();
+        clients.push(client_id);
+        match self.budget {
+            Some(budget) if self.bytes > budget => self.spill(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the owners kept in memory to a new run, and merges the
+    /// runs once there are `MAX_RUNS` of them.
+    fn spill(&mut self) -> io::Result<()> {
+        let mut owners: Vec<(u32, u16)> = self.memory.drain()
+            .flat_map(|(tx_id, clients)| clients.into_iter().map(move |client_id| (tx_id, client_id)))
+            .collect();
+        owners.sort_unstable();
+        self.bytes = 0;
+        self.runs.push(Run::write(owners.into_iter().map(Ok))?);
+        if self.runs.len() >= MAX_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            self.runs.push(Run::merge(runs)?);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Owners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Owners")
+            .field("memory", &self.memory.len())
+            .field("runs", &self.runs.len())
+            .finish()
+    }
+}
+
+/// A temporary file of transaction ids and their clients, sorted by id,
+/// as fixed size records of a little-endian `u32` and `u16`.
+struct Run {
+    file: File,
+    len:  u64,
+}
+
+impl Run {
+    fn write(owners: impl Iterator<Item = io::Result<(u32, u16)>>) -> io::Result<Run> {
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        let mut len = 0;
+        for owner in owners {
+            let (tx_id, client_id) = owner?;
+            writer.write_all(&tx_id.to_le_bytes())?;
+            writer.write_all(&client_id.to_le_bytes())?;
+            len += 1;
+        }
+        Ok(Run { file: writer.into_inner().map_err(io::IntoInnerError::into_error)?, len })
+    }
+
+    /// Merges the runs into one, without the owners that are in more
+    /// than one of them.
+    fn merge(mut runs: Vec<Run>) -> io::Result<Run> {
+        let mut readers = runs.iter_mut().map(Run::records).collect::<io::Result<Vec<_>>>()?;
+        let mut heads = BinaryHeap::new();
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(owner) = reader.next().transpose()? {
+                heads.push(Reverse((owner, i)));
+            }
+        }
+        let mut last = None;
+        let merged = std::iter::from_fn(|| loop {
+            let Reverse((owner, i)) = heads.pop()?;
+            match readers[i].next().transpose() {
+                Ok(Some(next)) => heads.push(Reverse((next, i))),
+                Ok(None) => {},
+                Err(e) => return Some(Err(e)),
+            }
+            if last.replace(owner) != Some(owner) {
+                return Some(Ok(owner));
+            }
+        });
+        Run::write(merged)
+    }
+
+    /// Returns an iterator over the records of the run in order.
+    fn records(&mut self) -> io::Result<impl Iterator<Item = io::Result<(u32, u16)>> + '_> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        Ok((0..self.len).map(move |_| read_record(&mut reader)))
+    }
+
+    /// Appends the clients of the id to `clients`, after finding the
+    /// first record of the id with a binary search.
+    fn clients(&mut self, tx_id: u32, clients: &mut Vec<u16>) -> io::Result<()> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.record(mid)? {
+                (id, _) if id < tx_id => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        for i in low..self.len {
+            match self.record(i)? {
+                (id, client_id) if id == tx_id => clients.push(client_id),
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, i: u64) -> io::Result<(u32, u16)> {
+        self.file.seek(SeekFrom::Start(i * RECORD_BYTES))?;
+        read_record(&mut self.file)
+    }
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<(u32, u16)> {
+    let mut bytes = [0; RECORD_BYTES as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok((u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]), u16::from_le_bytes([bytes[4], bytes[5]])))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_owners_spill() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let options = Options { global_unique_tx: true, ..Options::default() };
+        let mut owners = Owners { budget: Some(4 * ENTRY_BYTES), ..Owners::default() };
+
+        /*
+         * When
+         */
+        for tx_id in 0..100 {
+            owners.check(&Transaction::deposit(1 + (tx_id % 3) as u16, tx_id, dec!(1))?, &options)??;
+        }
+        let duplicate = owners.check(&Transaction::deposit(9, 42, dec!(1))?, &options)?;
+        let mismatch = owners.check(&Transaction::dispute(9, 43), &options)?;
+        let own = owners.check(&Transaction::dispute(2, 43), &options)?;
+
+        /*
+         * Then
+         */
+        assert!(owners.runs.len() < MAX_RUNS && owners.memory.len() <= 4);
+        assert_eq!(owners.runs.iter().map(|run| run.len).sum::<u64>() + owners.memory.len() as u64, 100);
+        assert!(matches!(duplicate, Err(TxError::DuplicateTx(42))));
+        assert!(matches!(mismatch, Err(TxError::ClientMismatch { tx: 43, client: 9, owner: 2 })));
+        assert!(own.is_ok());
+        assert_eq!((0..100).map(|tx_id| owners.clients(tx_id)).collect::<io::Result<Vec<_>>>()?,
+                   (0..100).map(|tx_id| vec![1 + (tx_id % 3) as u16]).collect::<Vec<_>>());
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Returns `true` if the path is a named pipe, e.g. made by `mkfifo`,
+/// whose records are read as they arrive.
+#[cfg(unix)]
+pub fn is_fifo(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+/// Returns `true` if the path is a named pipe, which are only read as
+/// such on unix.
+#[cfg(not(unix))]
+pub fn is_fifo(_path: &std::path::Path) -> bool {
+    false
+}
+
+/// Reads a named pipe, and retries a read that was interrupted by a
+/// signal instead of failing, as a writer may take long to write the
+/// next records. A read returns the bytes that were written so far, so
+/// a partial line is completed by the next reads.
+pub struct Pipe {
+    file: File,
+}
+
+impl Pipe {
+    /// Opens the pipe, which waits until a writer opened it too.
+    pub fn open(path: &std::path::Path) -> io::Result<Pipe> {
+        Ok(Pipe { file: File::open(path)? })
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.file.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::tx::Options;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pipes() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        let paths = [dir.path().join("jan.pipe"), dir.path().join("feb.pipe")];
+        for path in &paths {
+            assert!(std::process::Command::new("mkfifo").arg(path).status()?.success());
+        }
+        let writer = {
+            let paths = paths.clone();
+            std::thread::spawn(move || -> io::Result<()> {
+                // Writes the pipes one after another, with records cut across writes
+                let mut jan = std::fs::OpenOptions::new().write(true).open(&paths[0])?;
+                for part in ["type,client,tx,amount\ndeposit,1,1,1", "0\ndeposit,2,2,5\n", "withdrawal,1,3,4\n"] {
+                    jan.write_all(part.as_bytes())?;
+                    jan.flush()?;
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                drop(jan);
+                let mut feb = std::fs::OpenOptions::new().write(true).open(&paths[1])?;
+                feb.write_all(b"type,client,tx,amount\ndispute,2,2,\n")
+            })
+        };
+
+        /*
+         * When
+         */
+        let options = Options { parallel_parse: true, mmap: true, ..Options::default() };
+        let (accounts, _) = futures::executor::block_on(crate::tx::process_paths(&paths, &options))?;
+
+        /*
+         * Then
+         */
+        writer.join().unwrap()?;
+        assert_eq!(accounts.iter().map(|account| (account.client_id(), account.available(), account.held())).collect::<Vec<_>>(),
+                   vec![(1, dec!(6), dec!(0)), (2, dec!(0), dec!(5))]);
+        assert!(is_fifo(&paths[0]) && !is_fifo(dir.path()));
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How far the reading of a run got: the bytes read so far out of the
+/// size of the input, if it is known, and the rows they contain. It is
+/// set in `Options::progress`, and drawn to stderr by an
+/// `indicatif::ProgressBar` if it is a terminal.
+pub struct Progress {
+    bar:  ProgressBar,
+    rows: Arc<AtomicU64>,
+}
+
+impl Progress {
+    /// Returns the progress of reading an input of `total` bytes, or of
+    /// unknown size like stdin.
+    pub fn new(total: Option<u64>) -> Progress {
+        let rows = Arc::new(AtomicU64::new(0));
+        let template = match total {
+            Some(_) => "{bytes} / {total_bytes} ({percent}%), {rows} rows, {bytes_per_sec}, ETA {eta}",
+            None => "{bytes}, {rows} rows, {bytes_per_sec}",
+        };
+        let counted = Arc::clone(&rows);
+        let style = ProgressStyle::with_template(template)
+            .expect("invalid progress template")
+            .with_key("rows", move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                let _ = write!(w, "{}", rows_line(counted.load(Ordering::Relaxed), state.pos(), state.len()));
+            });
+        let bar = ProgressBar::with_draw_target(total, ProgressDrawTarget::stderr()).with_style(style);
+        Progress { bar, rows }
+    }
+
+    /// Counts bytes that were read, and the rows that end within them.
+    pub fn add(&self, bytes: &[u8]) {
+        self.rows.fetch_add(bytes.iter().filter(|&&b| b == b'\n').count() as u64, Ordering::Relaxed);
+        self.bar.inc(bytes.len() as u64);
+    }
+
+    /// Returns the progress as one line, e.g. `12.0 MiB / 48.0 MiB (25%),
+    /// 250000 / ~1000000 rows, 6.0 MiB/s, ETA 6s`. The number of rows of
+    /// the whole input is estimated from the length of the rows so far.
+    pub fn line(&self) -> String {
+        let bytes = self.bar.position();
+        let rows = rows_line(self.rows.load(Ordering::Relaxed), bytes, self.bar.length());
+        let elapsed = self.bar.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { bytes as f64 / elapsed } else { 0.0 };
+        match self.bar.length() {
+            Some(total) => {
+                let percent = (bytes.min(total) * 100).checked_div(total).unwrap_or(100);
+                let eta = if rate > 0.0 { total.saturating_sub(bytes) as f64 / rate } else { 0.0 };
+                format!( "{} / {} ({}%), {} rows, {}/s, ETA {}"
+                       , mib(bytes), mib(total), percent, rows, mib(rate as u64), duration(eta.ceil() as u64)
+                       )
+            },
+            None => format!("{}, {} rows, {}/s", mib(bytes), rows, mib(rate as u64)),
+        }
+    }
+
+    /// Draws the progress a last time and leaves it on stderr. If stderr
+    /// is not a terminal, where the bar is hidden, the progress is
+    /// written as one line instead.
+    pub fn finish(&self) {
+        if self.bar.is_hidden() {
+            eprintln!("{}", self.line());
+        }
+        self.bar.finish();
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("bytes", &self.bar.position())
+            .field("total", &self.bar.length())
+            .field("rows", &self.rows.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Returns the rows read so far, and the rows of the whole input as
+/// estimated from them if its size is known, e.g. `250000 / ~1000000`.
+fn rows_line(rows: u64, bytes: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) => {
+            let estimated = if bytes > 0 { (rows as f64 * total as f64 / bytes as f64) as u64 } else { 0 };
+            format!("{} / ~{}", rows, estimated)
+        },
+        None => rows.to_string(),
+    }
+}
+
+/// Returns a number of bytes in MiB, e.g. `1.5 MiB`.
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+}
+
+/// Returns a number of seconds like `1h02m05s`, `2m05s` or `5s`.
+fn duration(secs: u64) -> String {
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+/// A reader that counts the bytes read through it in a `Progress`.
+pub(crate) struct Counted<R> {
+    inner:    R,
+    progress: Option<Arc<Progress>>,
+}
+
+impl<R> Counted<R> {
+    pub(crate) fn new(inner: R, progress: Option<&Arc<Progress>>) -> Counted<R> {
+        Counted { inner, progress: progress.cloned() }
+    }
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(progress) = &self.progress {
+            progress.add(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\ndeposit,1,3,1.0\n";
+        let progress = Arc::new(Progress::new(Some(input.len() as u64 * 2)));
+        let unknown = Progress::new(None);
+
+        /*
+         * When
+         */
+        let mut read = vec![];
+        Counted::new(&input[..], Some(&progress)).read_to_end(&mut read)?;
+        unknown.add(&input[..22]);
+
+        /*
+         * Then
+         */
+        assert_eq!(read, input);
+        assert!(progress.line().starts_with("0.0 MiB / 0.0 MiB (50%), 4 / ~8 rows, "), "{}", progress.line());
+        assert_eq!([duration(5), duration(125), duration(3725)], ["5s", "2m05s", "1h02m05s"]);
+        assert!(unknown.line().starts_with("0.0 MiB, 1 rows, "), "{}", unknown.line());
+        Ok(())
+    }
+}
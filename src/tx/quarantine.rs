@@ -0,0 +1,155 @@
+use crate::tx::{AmountUnit, CsvFormat, Options, Transaction, TxError};
+use csv::{StringRecord, WriterBuilder};
+use log::warn;
+use rust_decimal_macros::dec;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// A csv file that the rows which were skipped as malformed or rejected
+/// are written to, each with a `reason` column appended, so that they
+/// can be fixed and submitted again. It is set in `Options::quarantine`.
+///
+/// The rows are written in the columns of the first input that is read:
+/// a malformed row as it was read, and a rejected transaction with its
+/// fields in the columns they were read from.
+#[derive(Debug)]
+pub struct Quarantine {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    writer:  csv::Writer<File>,
+    /// The field of every column of the input, once it is known.
+    columns: Option<Vec<String>>,
+}
+
+impl Quarantine {
+    /// Creates the file at `path`, with the delimiter of `format`.
+    pub fn create(path: &Path, format: &CsvFormat) -> io::Result<Quarantine> {
+        let writer = WriterBuilder::new()
+            .delimiter(format.delimiter(Some(path)))
+            .flexible(true)
+            .from_path(path)?;
+        Ok(Quarantine { state: Mutex::new(State { writer, columns: None }) })
+    }
+
+    /// Takes the columns of an input, given the fields they were mapped
+    /// to, and writes its header row if it has one. Only the first input
+    /// is taken, and the columns of the others are ignored.
+    pub(crate) fn columns(&self, header: Option<&StringRecord>, fields: &StringRecord) {
+        let mut state = self.state();
+        if state.columns.is_some() {
+            return;
+        }
+        state.columns = Some(fields.iter().map(String::from).collect());
+        if let Some(header) = header {
+            state.write(header.iter().chain(["reason"]));
+        }
+    }
+
+    /// Writes a row that could not be parsed, as it was read.
+    pub(crate) fn malformed(&self, record: &StringRecord, reason: &TxError) {
+        let reason = reason.to_string();
+        self.state().write(record.iter().chain([reason.as_str()]));
+    }
+
+    /// Writes a rejected transaction, in the columns of the input and
+    /// with the amount in its unit.
+    pub(crate) fn rejected(&self, txn: &Transaction, reason: &TxError, options: &Options) {
+        let mut state = self.state();
+        let fields = match &state.columns {
+            Some(columns) => columns.iter().map(|column| field(txn, column, options)).collect::<Vec<_>>(),
+            None => CsvFormat::COLUMNS.iter().map(|column| field(txn, column, options)).collect(),
+        };
+        let reason = reason.to_string();
+        state.write(fields.iter().map(String::as_str).chain([reason.as_str()]));
+    }
+
+    /// Flushes the rows written so far to the file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.state().writer.flush()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl State {
+    fn write<'a>(&mut self, record: impl IntoIterator<Item = &'a str>) {
+        if let Err(e) = self.writer.write_record(record) {
+            warn!("Could not write to the quarantine file: {}", e);
+        }
+    }
+}
+
+/// Returns the field of a transaction that is read from `column`, as
+/// it would be read, or an empty one for a column that is not read.
+fn field(txn: &Transaction, column: &str, options: &Options) -> String {
+    match column {
+        "type" => format!("{:?}", txn.kind).to_lowercase(),
+        "client" => txn.client_id.to_string(),
+        "tx" => txn.tx_id.to_string(),
+        "amount" => txn.amount.map_or_else(String::new, |amount| {
+            let amount = match options.amounts {
+                AmountUnit::Decimal => amount.to_string(),
+                AmountUnit::Minor => (amount * dec!(10000)).normalize().to_string(),
+            };
+            match options.csv.decimal_comma {
+                true => amount.replace('.', ","),
+                false => amount,
+            }
+        }),
+        "to" => txn.to_client.map_or_else(String::new, |to| to.to_string()),
+        "currency" => txn.currency.clone().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_quarantine() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let input = "id;kind;client;amount\n\
+                     1;deposit;1;1,5\n\
+                     2;deposit;x;2\n\
+                     3;withdrawal;1;2,5\n\
+                     4;dispute;1;\n";
+        let file = NamedTempFile::new()?;
+        let csv = CsvFormat { map: vec![(String::from("type"), String::from("kind")), (String::from("tx"), String::from("id"))]
+                            , delimiter: Some(b';')
+                            , decimal_comma: true
+                            , ..CsvFormat::default()
+                            };
+        let quarantine = Arc::new(Quarantine::create(file.path(), &csv)?);
+        let options = Options { csv, quarantine: Some(Arc::clone(&quarantine)), ..Options::default() };
+
+        /*
+         * When
+         */
+        let (accounts, report) = futures::executor::block_on(crate::tx::process_reader(io::Cursor::new(input), &options))?;
+        quarantine.flush()?;
+
+        /*
+         * Then
+         */
+        assert_eq!(accounts.len(), 1);
+        assert!(report.rejections.is_empty());
+        assert_eq!(std::fs::read_to_string(file.path())?,
+                   "id;kind;client;amount;reason\n\
+                    2;deposit;x;2;could not parse line 3, field `client`: CSV deserialize error: record 2 (line: 3, byte: 38): field 2: invalid digit found in string\n\
+                    3;withdrawal;1;2,5;insufficient available funds for transaction 3\n\
+                    4;dispute;1;;transaction 4 not found\n");
+        Ok(())
+    }
+}
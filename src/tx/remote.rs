@@ -0,0 +1,131 @@
+use log::warn;
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// Returns `true` if the path is an `http://` or `https://` URL, which is
+/// read with `UrlReader` instead of opened as a file.
+pub fn is_url(path: &std::path::Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with("https://") || path.starts_with("http://"))
+}
+
+/// Reads the body of an `http://` or `https://` URL as it is
+/// downloaded, so that a file hosted on e.g. a signed URL is parsed
+/// without being downloaded first. A download that fails part way is
+/// resumed from the last byte read with a `Range` request up to
+/// `retries` times, which needs the server to support ranges.
+pub struct UrlReader {
+    url:     String,
+    retries: u32,
+    tries:   u32,
+    /// Number of bytes of the body read so far, where a retry resumes.
+    read:    u64,
+    body:    Box<dyn Read + Send + Sync>,
+}
+
+/// The delay before the first retry, doubled before every next one.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+impl UrlReader {
+    /// Starts the download of the URL.
+    pub fn open(url: &str, retries: u32) -> io::Result<UrlReader> {
+        let body = get(url, 0)?;
+        Ok(UrlReader { url: url.to_string(), retries, tries: 0, read: 0, body })
+    }
+}
+
+impl Read for UrlReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.body.read(buf) {
+                Ok(n) => {
+                    self.read += n as u64;
+                    return Ok(n);
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if self.tries < self.retries => {
+                    std::thread::sleep(RETRY_DELAY * 2u32.pow(self.tries));
+                    self.tries += 1;
+                    warn!("Could not download `{}`: {}, resuming at byte {} (retry {} of {})", self.url, e, self.read, self.tries, self.retries);
+                    self.body = get(&self.url, self.read)?;
+                },
+                Err(e) => return Err(io::Error::new(e.kind(), format!("could not download `{}`: {}", self.url, e))),
+            }
+        }
+    }
+}
+
+/// Requests the body of the URL from byte `from` on. It fails on an
+/// HTTP error status, and on a server that sends the whole body
+/// instead of the range.
+fn get(url: &str, from: u64) -> io::Result<Box<dyn Read + Send + Sync>> {
+    let mut request = ureq::get(url);
+    if from > 0 {
+        request = request.set("Range", &format!("bytes={}-", from));
+    }
+    let response = request.call().map_err(|e| match e {
+        ureq::Error::Status(status, _) => io::Error::other(format!("could not download `{}`: HTTP status {}", url, status)),
+        ureq::Error::Transport(e) => io::Error::other(format!("could not download `{}`: {}", url, e)),
+    })?;
+    if from > 0 && response.status() != 206 {
+        return Err(io::Error::other(format!("could not resume the download of `{}`, the server does not support ranges", url)));
+    }
+    Ok(response.into_reader())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::Options;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+
+    #[test]
+    fn test_url_reader() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let body = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,5\nwithdrawal,1,3,4\n";
+        let server = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/txns.csv?signature=a%2Fb", server.local_addr()?);
+        std::thread::spawn(move || -> io::Result<()> {
+            // A download from the start is cut off half way, and a resumed one ends
+            for stream in server.incoming() {
+                let mut stream = stream?;
+                let mut request = String::new();
+                while !request.ends_with("\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte)?;
+                    request.push(byte[0] as char);
+                }
+                let from = request.lines()
+                    .find_map(|line| line.strip_prefix("Range: bytes=")?.strip_suffix('-')?.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let head = match from {
+                    0 => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()),
+                    _ => format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n", body.len() - from, from, body.len() - 1, body.len()),
+                };
+                let sent = if from == 0 { body.len() / 2 } else { body.len() };
+                stream.write_all(head.as_bytes())?;
+                stream.write_all(&body.as_bytes()[from..sent])?;
+            }
+            Ok(())
+        });
+
+        /*
+         * When
+         */
+        let mut without_retries = String::new();
+        let failed = UrlReader::open(&url, 0)?.read_to_string(&mut without_retries);
+        let options = Options { retries: 1, ..Options::default() };
+        let (accounts, _) = futures::executor::block_on(crate::tx::process_paths(&[url.into()], &options))?;
+
+        /*
+         * Then
+         */
+        assert!(failed.is_err());
+        assert_eq!(accounts.iter().map(|account| (account.client_id, account.total)).collect::<Vec<_>>(),
+                   vec![(1, dec!(6)), (2, dec!(5))]);
+        assert!(is_url("https://example.com/a.csv".as_ref()) && !is_url("example.com/a.csv".as_ref()));
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::tx::{Engine, TxError};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Saves the state of the engine to a JSON file, that is the balances
+/// of all clients together with the transactions that can still be
+/// disputed.
+pub fn save(path: &Path, engine: &Engine) -> Result<(), TxError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, engine)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Loads the state of an engine saved by `save`.
+pub fn load(path: &Path) -> Result<Engine, TxError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::Transaction;
+    use rust_decimal_macros::dec;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_save_and_load() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let file = NamedTempFile::new()?;
+        let mut engine = Engine::new();
+        engine.apply(Transaction::deposit(1, 1, dec!(2.5))?)?;
+        engine.apply(Transaction::deposit(2, 2, dec!(1))?)?;
+
+        /*
+         * When
+         */
+        save(file.path(), &engine)?;
+        let mut loaded = load(file.path())?;
+
+        /*
+         * Then
+         */
+        assert_eq!(loaded.account(1), engine.account(1));
+        assert_eq!(loaded.account(2), engine.account(2));
+        // the deposit of the earlier run can still be disputed
+        loaded.apply(Transaction::dispute(1, 1))?;
+        assert_eq!(loaded.account(1).unwrap().held(), dec!(2.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_history() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut file = NamedTempFile::new()?;
+        // saved before the handled transactions were kept as a `TxState`
+        write!(file, r#"{{"ledgers":{{"1":{{"account":{{"client":1,"available":"0.0","held":"2.5","total":"2.5","locked":false}},
+                                           "handled":{{"1":[{{"type":"deposit","client":1,"tx":1,"amount":"2.5","to":null,"currency":null}},
+                                                           {{"type":"dispute","client":1,"tx":1,"amount":null,"to":null,"currency":null}}]}},
+                                           "saturated":false,"currencies":{{}}}}}}}}"#)?;
+
+        /*
+         * When
+         */
+        let mut loaded = load(file.path())?;
+        let resolve = loaded.apply(Transaction::resolve(1, 1));
+        let again = loaded.apply(Transaction::resolve(1, 1));
+
+        /*
+         * Then
+         */
+        assert!(resolve.is_ok());
+        assert!(matches!(again, Err(TxError::NotDisputed(1))));
+        assert_eq!(loaded.account(1).unwrap().available(), dec!(2.5));
+        Ok(())
+    }
+}
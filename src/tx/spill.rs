@@ -0,0 +1,325 @@
+use crate::tx::{Transaction, TxState};
+use serde::de::Deserializer;
+use serde::ser::{Error, SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Approximate number of bytes a `TxState` takes in memory, together
+/// with its id and its slot in the map, besides its currency.
+const STATE_BYTES: usize = std::mem::size_of::<(u32, TxState)>() + 16;
+
+/// Returns the approximate number of bytes a `TxState` takes in memory,
+/// with the currency of its transaction on the heap.
+fn state_bytes(state: &TxState) -> usize {
+    STATE_BYTES + state.original().and_then(Transaction::currency).map_or(0, str::len)
+}
+
+/// A temporary file that the `TxState` of handled transactions are
+/// written to once they no longer fit in memory. It is shared by the
+/// ledgers of a shard, and removed once the last of them is dropped.
+/// The place of a state that was read back is reused by the states
+/// spilled later, so that the file does not grow with every spill.
+#[derive(Debug)]
+pub(crate) struct SpillFile {
+    file: File,
+    len:  u64,
+    /// The free ranges of the file, by offset, none of them adjacent.
+    free: BTreeMap<u64, u64>,
+    /// The same free ranges as `(len, offset)`, to find the smallest
+    /// one a state fits in without a scan.
+    fits: BTreeSet<(u64, u64)>,
+}
+
+impl SpillFile {
+    pub(crate) fn new() -> io::Result<Arc<Mutex<SpillFile>>> {
+        Ok(Arc::new(Mutex::new(SpillFile { file: tempfile::tempfile()?, len: 0, free: BTreeMap::new(), fits: BTreeSet::new() })))
+    }
+
+    /// Writes a state to the smallest free range it fits in, or appends
+    /// it to the file, and returns where it was written.
+    fn write(&mut self, state: &TxState) -> io::Result<(u64, u32)> {
+        let bytes = serde_json::to_vec(state)?;
+        let len = bytes.len() as u64;
+        let offset = match self.fits.range((len, 0)..).next().copied() {
+            Some((free, offset)) => {
+                self.take(offset);
+                if free > len {
+                    self.give(offset + len, free - len);
+                }
+                offset
+            },
+            None => self.len,
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&bytes)?;
+        self.len = self.len.max(offset + len);
+        Ok((offset, bytes.len() as u32))
+    }
+
+    /// Frees the range of a state that is no longer kept in the file,
+    /// joined with the free ranges next to it. The file is truncated
+    /// if the range ends it.
+    fn release(&mut self, (offset, len): (u64, u32)) -> io::Result<()> {
+        let (mut start, mut end) = (offset, offset + u64::from(len));
+        if let Some((&before, &free)) = self.free.range(..start).next_back() {
+            if before + free == start {
+                self.take(before);
+                start = before;
+            }
+        }
+        if let Some(free) = self.take(end) {
+            end += free;
+        }
+        if end < self.len {
+            self.give(start, end - start);
+            return Ok(());
+        }
+        self.len = start;
+        self.file.set_len(start)
+    }
+
+    /// Adds a free range of `len` bytes at `offset`.
+    fn give(&mut self, offset: u64, len: u64) {
+        self.free.insert(offset, len);
+        self.fits.insert((len, offset));
+    }
+
+    /// Removes the free range at `offset`, if any, and returns its length.
+    fn take(&mut self, offset: u64) -> Option<u64> {
+        let len = self.free.remove(&offset)?;
+        self.fits.remove(&(len, offset));
+        Some(len)
+    }
+
+    /// Reads back a state written at `offset`.
+    fn read(&mut self, (offset, len): (u64, u32)) -> io::Result<TxState> {
+        let mut bytes = vec![0; len as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// The `TxState` of the handled transactions of a client, keyed by
+/// transaction id. They are kept in memory until they are spilled to
+/// a `SpillFile`, after which only where they were written is kept.
+#[derive(Default)]
+pub(crate) struct Handled {
+    memory:  HashMap<u32, TxState>,
+    /// The approximate number of bytes of the states in memory.
+    bytes:   usize,
+    spilled: HashMap<u32, (u64, u32)>,
+    file:    Option<Arc<Mutex<SpillFile>>>,
+}
+
+impl Handled {
+    /// Returns `true` if a transaction with the id was handled.
+    pub(crate) fn contains_key(&self, tx_id: &u32) -> bool {
+        self.memory.contains_key(tx_id) || self.spilled.contains_key(tx_id)
+    }
+
+    /// Returns the ids of the handled transactions.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &u32> {
+        self.memory.keys().chain(self.spilled.keys())
+    }
+
+    /// Returns the approximate number of bytes the states kept in
+    /// memory take.
+    pub(crate) fn in_memory(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns the state of the handled transactions with the id, if
+    /// it is kept in memory.
+    pub(crate) fn get(&self, tx_id: &u32) -> Option<&TxState> {
+        self.memory.get(tx_id)
+    }
+
+    /// Returns the state of the handled transactions with the id, which
+    /// is read back from the file if it was spilled.
+    pub(crate) fn read(&self, tx_id: &u32) -> io::Result<Option<Cow<'_, TxState>>> {
+        if let Some(state) = self.memory.get(tx_id) {
+            return Ok(Some(Cow::Borrowed(state)));
+        }
+        match (self.spilled.get(tx_id), &self.file) {
+            (Some(&at), Some(file)) => Ok(Some(Cow::Owned(lock(file).read(at)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Moves the state of the transactions with the id back to memory
+    /// if it was spilled, so that it can be handled, and frees its
+    /// place in the file.
+    pub(crate) fn restore(&mut self, tx_id: u32) -> io::Result<()> {
+        let (at, file) = match (self.spilled.get(&tx_id), &self.file) {
+            (Some(&at), Some(file)) => (at, file),
+            _ => return Ok(()),
+        };
+        let mut file = lock(file);
+        let state = file.read(at)?;
+        file.release(at)?;
+        self.spilled.remove(&tx_id);
+        self.bytes += state_bytes(&state);
+        self.memory.insert(tx_id, state);
+        Ok(())
+    }
+
+    /// Records a handled transaction in the state of its id, which
+    /// must have been restored if it was spilled.
+    pub(crate) fn record(&mut self, txn: Transaction) {
+        debug_assert!(!self.spilled.contains_key(&txn.tx_id));
+        let counted = self.memory.get(&txn.tx_id).map_or(0, state_bytes);
+        let state = self.memory.entry(txn.tx_id).or_default();
+        state.record(txn);
+        self.bytes = self.bytes - counted + state_bytes(state);
+    }
+
+    /// Moves the states kept in memory to `file`. They are all kept
+    /// in memory if any of them could not be written.
+    pub(crate) fn spill(&mut self, file: &Arc<Mutex<SpillFile>>) -> io::Result<()> {
+        let file = self.file.get_or_insert_with(|| Arc::clone(file));
+        let mut spill = lock(file);
+        let written = self.memory.iter()
+            .map(|(&tx_id, state)| Ok((tx_id, spill.write(state)?)))
+            .collect::<io::Result<Vec<(u32, (u64, u32))>>>()?;
+        self.spilled.extend(written);
+        self.memory.clear();
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+/// Frees the places of the spilled states in the file, which other
+/// ledgers of the shard may still write to.
+impl Drop for Handled {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let mut file = lock(file);
+            for &at in self.spilled.values() {
+                if let Err(e) = file.release(at) {
+                    log::debug!("Could not free a spilled transaction: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn lock(file: &Mutex<SpillFile>) -> std::sync::MutexGuard<'_, SpillFile> {
+    file.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl fmt::Debug for Handled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handled")
+            .field("memory", &self.memory)
+            .field("spilled", &self.spilled.len())
+            .finish()
+    }
+}
+
+/// Saved as a map of all the states, the spilled ones included.
+impl Serialize for Handled {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.memory.len() + self.spilled.len()))?;
+        for tx_id in self.keys() {
+            let state = self.read(tx_id).map_err(S::Error::custom)?;
+            map.serialize_entry(tx_id, &state)?;
+        }
+        map.end()
+    }
+}
+
+/// Loaded in memory, as it will be spilled again if needed.
+impl<'de> Deserialize<'de> for Handled {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Handled, D::Error> {
+        let memory: HashMap<u32, TxState> = HashMap::deserialize(deserializer)?;
+        let bytes = memory.values().map(state_bytes).sum();
+        Ok(Handled { memory, bytes, spilled: HashMap::new(), file: None })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_spill_and_restore() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let file = SpillFile::new()?;
+        let mut handled = Handled::default();
+        handled.record(Transaction::deposit(1, 1, dec!(2.5))?);
+        handled.record(Transaction::deposit(1, 2, dec!(4))?);
+        handled.record(Transaction::dispute(1, 2));
+        let disputed = handled.get(&2).cloned();
+
+        /*
+         * When
+         */
+        handled.spill(&file)?;
+        let spilled = (handled.in_memory(), handled.get(&2).is_none(), handled.read(&2)?.map(Cow::into_owned));
+        let saved = serde_json::to_string(&handled)?;
+        handled.restore(2)?;
+        handled.record(Transaction::resolve(1, 2));
+
+        /*
+         * Then
+         */
+        assert_eq!(spilled, (0, true, disputed.clone()));
+        assert!(handled.contains_key(&1) && handled.contains_key(&2) && !handled.contains_key(&3));
+        assert_eq!(handled.in_memory(), STATE_BYTES);
+        assert_eq!(handled.read(&1)?.map(Cow::into_owned), Some(TxState::of(vec![Transaction::deposit(1, 1, dec!(2.5))?])));
+        let loaded: Handled = serde_json::from_str(&saved)?;
+        assert_eq!(loaded.in_memory(), 2 * STATE_BYTES);
+        assert_eq!(loaded.get(&2), disputed.as_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn test_respill() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let file = SpillFile::new()?;
+        let mut handled = Handled::default();
+        let mut other = Handled::default();
+        for tx in 1..=100 {
+            handled.record(Transaction::deposit(1, tx, dec!(1.5))?.with_currency("EUR"));
+        }
+        other.record(Transaction::deposit(2, 101, dec!(4))?);
+        let in_memory = handled.in_memory();
+        other.spill(&file)?;
+        handled.spill(&file)?;
+        let size = |file: &Arc<Mutex<SpillFile>>| lock(file).file.metadata().map(|metadata| metadata.len());
+        let spilled = size(&file)?;
+
+        /*
+         * When
+         */
+        let mut sizes = vec![];
+        for round in 0..10 {
+            (1..=100).filter(|tx| tx % 2 == round % 2).try_for_each(|tx| handled.restore(tx))?;
+            handled.record(Transaction::dispute(1, 2 - round % 2));
+            handled.spill(&file)?;
+            sizes.push(size(&file)?);
+        }
+        drop(handled);
+        let dropped = size(&file)?;
+
+        /*
+         * Then
+         */
+        assert_eq!(in_memory, 100 * (STATE_BYTES + 3));
+        assert!(sizes.iter().all(|&size| size <= spilled + spilled / 10), "{:?} past {}", sizes, spilled);
+        assert!(dropped > 0 && dropped < spilled / 10);
+        assert_eq!(other.read(&101)?.map(Cow::into_owned), Some(TxState::of(vec![Transaction::deposit(2, 101, dec!(4))?])));
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use crate::tx::{self, Account, Engine, Ledger, Options, Parsed, Report, Transaction, TransactionKind, TxError, TxState};
+use tracing::info_span;
+use std::borrow::Cow;
+use std::io;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Number of transactions between two calls to `Storage::flush`.
+const FLUSH_EVERY: u64 = 10_000;
+
+/// Keeps the accounts of the clients and the transactions that can still
+/// be disputed. `Engine` keeps them in memory, while e.g. `SqliteStorage`
+/// keeps them on disk, so they survive restarts and need not fit in memory.
+pub trait Storage {
+    /// Returns the `Account` of a client in a `currency`, if the client
+    /// has been seen in it.
+    fn account(&self, client_id: u16, currency: Option<&str>) -> Result<Option<Account>, TxError>;
+
+    /// Returns the `TxState` of the handled transactions of a client with
+    /// the id `tx_id`, if any was handled.
+    fn history(&self, client_id: u16, tx_id: u32) -> Result<Option<TxState>, TxError>;
+
+    /// Stores the updated `Account` of a client together with the
+    /// `Transaction` that updated it.
+    fn commit(&mut self, account: Account, txn: Transaction) -> Result<(), TxError>;
+
+    /// Returns the accounts of all clients, ordered by client id and
+    /// currency.
+    fn accounts(&self) -> Result<Vec<Account>, TxError>;
+
+    /// Returns the client of the handled transaction `tx_id`, if any.
+    fn owner(&self, tx_id: u32) -> Result<Option<u16>, TxError>;
+
+    /// Makes the committed changes durable.
+    fn flush(&mut self) -> Result<(), TxError> {
+        Ok(())
+    }
+}
+
+impl Storage for Engine {
+    fn account(&self, client_id: u16, currency: Option<&str>) -> Result<Option<Account>, TxError> {
+        Ok(Engine::account_in(self, client_id, currency).cloned())
+    }
+
+    fn history(&self, client_id: u16, tx_id: u32) -> Result<Option<TxState>, TxError> {
+        Ok(self.ledgers.get(&client_id)
+            .map(|ledger| ledger.handled.read(&tx_id))
+            .transpose()?
+            .flatten()
+            .map(Cow::into_owned))
+    }
+
+    fn commit(&mut self, account: Account, txn: Transaction) -> Result<(), TxError> {
+        let ledger = self.ledgers.entry(account.client_id)
+            .or_insert_with(|| Ledger::new(account.client_id));
+        ledger.handled.restore(txn.tx_id)?;
+        ledger.keep(account);
+        ledger.handled.record(txn);
+        Ok(())
+    }
+
+    fn accounts(&self) -> Result<Vec<Account>, TxError> {
+        let mut accounts: Vec<Account> = Engine::accounts(self).cloned().collect();
+        accounts.sort_by_key(Account::client_id);
+        Ok(accounts)
+    }
+
+    fn owner(&self, tx_id: u32) -> Result<Option<u16>, TxError> {
+        Ok(self.ledgers.values()
+            .find(|ledger| ledger.handled.contains_key(&tx_id))
+            .map(|ledger| ledger.account.client_id))
+    }
+}
+
+/// Applies a `Transaction` to the account of its client in the storage,
+/// with the rules of `options`. Returns the reason if the
+/// transaction was rejected, which leaves the storage unchanged, and an
+/// error if the storage failed.
+pub fn apply(storage: &mut impl Storage, txn: Transaction, options: &Options) -> Result<Option<TxError>, TxError> {
+    if txn.kind() == TransactionKind::Transfer {
+        return transfer(storage, txn, options);
+    }
+    let mut account = storage.account(txn.client_id, txn.currency())?
+        .unwrap_or_else(|| Account::new_in(txn.client_id, txn.currency()));
+    let history = storage.history(txn.client_id, txn.tx_id)?;
+    if history.is_none() && txn.refers() && !options.allow_client_mismatch {
+        if let Some(owner) = storage.owner(txn.tx_id)? {
+            return Ok(Some(TxError::ClientMismatch { tx: txn.tx_id, client: txn.client_id, owner }));
+        }
+    }
+    if history.is_none() && txn.disputable() && options.global_unique_tx && storage.owner(txn.tx_id)?.is_some() {
+        if let Err(reason) = tx::check_duplicate(&txn, options) {
+            return Ok(Some(reason));
+        }
+    }
+    match tx::handle_txn(&mut account, history.as_ref(), &txn, options) {
+        Ok(()) => storage.commit(account, txn).map(|_| None),
+        Err(reason) => Ok(Some(reason)),
+    }
+}
+
+/// Applies a transfer to the accounts of both clients in the storage,
+/// or to neither if either side can not be applied.
+fn transfer(storage: &mut impl Storage, txn: Transaction, options: &Options) -> Result<Option<TxError>, TxError> {
+    let to_client = match txn.credited() {
+        Ok(to_client) => to_client,
+        Err(reason) => return Ok(Some(reason)),
+    };
+    let mut from = storage.account(txn.client_id(), txn.currency())?
+        .unwrap_or_else(|| Account::new_in(txn.client_id(), txn.currency()));
+    let mut to = storage.account(to_client, txn.currency())?
+        .unwrap_or_else(|| Account::new_in(to_client, txn.currency()));
+    let history = storage.history(txn.client_id(), txn.tx_id())?;
+    let applied = tx::handle_txn(&mut from, history.as_ref(), &txn, options)
+        .and_then(|_| tx::handle_credit(&mut to, &txn, options));
+    match applied {
+        Ok(()) => {
+            storage.commit(to, txn.clone())?;
+            storage.commit(from, txn).map(|_| None)
+        },
+        Err(reason) => Ok(Some(reason)),
+    }
+}
+
+/// Reads the transactions from several files as one ordered log, like
+/// `tx::process_paths`, and applies them to the storage in order.
+pub fn process_paths(paths: &[std::path::PathBuf], options: &Options, storage: &mut impl Storage) -> Result<Report, TxError> {
+    let files = tx::expand_paths(paths)?;
+    Ok(process(tx::txns_from_files(&files, options)?, options, storage)?.named(&files))
+}
+
+/// Reads the transactions from a `std::io::Read` reader, and applies
+/// them to the storage in order.
+pub fn process_reader(reader: impl io::Read, options: &Options, storage: &mut impl Storage) -> Result<Report, TxError> {
+    process(tx::txns_from_reader(reader, options), options, storage)
+}
+
+/// Applies the transactions to the storage in order, and returns the
+/// `Report` of the run. Stops at the first error.
+pub(crate) fn process( txns:    impl Iterator<Item = Parsed>
+                     , options: &Options
+                     , storage: &mut impl Storage
+                     ) -> Result<Report, TxError> {
+    let _span = info_span!("storage_process").entered();
+    let mut rejected = Report::default();
+    for (count, parsed) in (1..).zip(txns) {
+        let (line, txn) = parsed?;
+        if let Some(threshold) = &options.max_errors {
+            threshold.count_row();
+            threshold.check(false)?;
+        }
+        let txn = txn.in_unit(options.amounts);
+        rejected.stats.count_read(&txn);
+        let kept = options.keeps_rejected().then(|| txn.clone());
+        if let Some(reason) = apply(storage, txn, options)? {
+            rejected.reject(line, kept, reason, options);
+        }
+        if count % FLUSH_EVERY == 0 {
+            storage.flush()?;
+        }
+    }
+    storage.flush()?;
+    if let Some(threshold) = &options.max_errors {
+        threshold.check(true)?;
+    }
+    rejected.stats.count_accounts(&storage.accounts()?);
+    Ok(rejected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_apply_engine_storage() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+
+        /*
+         * When
+         */
+        let deposit = apply(&mut engine, Transaction::deposit(1, 1, dec!(10))?, &Options::default())?;
+        let withdrawal = apply(&mut engine, Transaction::withdrawal(1, 2, dec!(20))?, &Options::default())?;
+        let dispute = apply(&mut engine, Transaction::dispute(1, 1), &Options::default())?;
+        let again = apply(&mut engine, Transaction::dispute(1, 1), &Options::default())?;
+
+        /*
+         * Then
+         */
+        assert!(deposit.is_none());
+        assert!(matches!(withdrawal, Some(TxError::InsufficientFunds(2))));
+        assert!(dispute.is_none());
+        assert!(matches!(again, Some(TxError::AlreadyDisputed(1))));
+        assert_eq!(Storage::accounts(&engine)?, vec![Account { client_id: 1
+                                                             , currency:  None
+                                                             , available: dec!(0)
+                                                             , held:      dec!(10)
+                                                             , total:     dec!(10)
+                                                             , locked:    false
+                                                             }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_transfer() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = Engine::new();
+        apply(&mut engine, Transaction::deposit(1, 1, dec!(10))?, &Options::default())?;
+
+        /*
+         * When
+         */
+        let transfer = apply(&mut engine, Transaction::transfer(1, 2, 2, dec!(4))?, &Options::default())?;
+        let too_much = apply(&mut engine, Transaction::transfer(2, 1, 3, dec!(5))?, &Options::default())?;
+
+        /*
+         * Then
+         */
+        assert!(transfer.is_none());
+        assert!(matches!(too_much, Some(TxError::InsufficientFunds(3))));
+        assert_eq!(Storage::accounts(&engine)?.iter().map(Account::total).collect::<Vec<_>>(), vec![dec!(6), dec!(4)]);
+        Ok(())
+    }
+}
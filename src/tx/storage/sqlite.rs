@@ -0,0 +1,278 @@
+use crate::tx::storage::Storage;
+use crate::tx::{Account, Transaction, TransactionKind, TxError, TxState};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use rust_decimal::Decimal;
+use std::path::Path;
+use std::str::FromStr;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS accounts (
+        client    INTEGER PRIMARY KEY,
+        available TEXT    NOT NULL,
+        held      TEXT    NOT NULL,
+        total     TEXT    NOT NULL,
+        locked    INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        seq    INTEGER PRIMARY KEY AUTOINCREMENT,
+        client INTEGER NOT NULL,
+        tx     INTEGER NOT NULL,
+        type   TEXT    NOT NULL,
+        amount   TEXT,
+        currency TEXT
+    );
+    CREATE TABLE IF NOT EXISTS currency_accounts (
+        client    INTEGER NOT NULL,
+        currency  TEXT    NOT NULL,
+        available TEXT    NOT NULL,
+        held      TEXT    NOT NULL,
+        total     TEXT    NOT NULL,
+        locked    INTEGER NOT NULL,
+        PRIMARY KEY (client, currency)
+    );
+    CREATE INDEX IF NOT EXISTS history_client_tx ON history (client, tx);
+    CREATE INDEX IF NOT EXISTS history_tx ON history (tx);
+";
+
+/// Keeps the accounts and the dispute history in a SQLite database.
+/// The changes are committed in batches, on every `flush`.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<SqliteStorage, TxError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        // databases created before currencies have no currency in the history
+        let migrated = conn.prepare("SELECT 1 FROM pragma_table_info('history') WHERE name = 'currency'")?.exists([])?;
+        if !migrated {
+            conn.execute_batch("ALTER TABLE history ADD COLUMN currency TEXT")?;
+        }
+        conn.execute_batch("BEGIN")?;
+        Ok(SqliteStorage { conn })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn account(&self, client_id: u16, currency: Option<&str>) -> Result<Option<Account>, TxError> {
+        let account = match currency {
+            None => self.conn
+                .prepare_cached("SELECT client, NULL, available, held, total, locked FROM accounts WHERE client = ?1")?
+                .query_row(params![client_id], to_account),
+            Some(currency) => self.conn
+                .prepare_cached("SELECT client, currency, available, held, total, locked FROM currency_accounts WHERE client = ?1 AND currency = ?2")?
+                .query_row(params![client_id, currency], to_account),
+        };
+        Ok(account.optional()?)
+    }
+
+    fn history(&self, client_id: u16, tx_id: u32) -> Result<Option<TxState>, TxError> {
+        let txns = self.conn
+            .prepare_cached("SELECT client, tx, type, amount, currency FROM history WHERE client = ?1 AND tx = ?2 ORDER BY seq")?
+            .query_map(params![client_id, tx_id], to_transaction)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((!txns.is_empty()).then(|| TxState::of(txns)))
+    }
+
+    fn commit(&mut self, account: Account, txn: Transaction) -> Result<(), TxError> {
+        match account.currency() {
+            None => self.conn
+                .prepare_cached("INSERT OR REPLACE INTO accounts (client, available, held, total, locked) VALUES (?1, ?2, ?3, ?4, ?5)")?
+                .execute(params![ account.client_id
+                                , account.available.to_string()
+                                , account.held.to_string()
+                                , account.total.to_string()
+                                , account.locked
+                                ])?,
+            Some(currency) => self.conn
+                .prepare_cached("INSERT OR REPLACE INTO currency_accounts (client, currency, available, held, total, locked) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?
+                .execute(params![ account.client_id
+                                , currency
+                                , account.available.to_string()
+                                , account.held.to_string()
+                                , account.total.to_string()
+                                , account.locked
+                                ])?,
+        };
+        self.conn
+            .prepare_cached("INSERT INTO history (client, tx, type, amount, currency) VALUES (?1, ?2, ?3, ?4, ?5)")?
+            .execute(params![ account.client_id
+                            , txn.tx_id
+                            , kind_name(txn.kind)
+                            , txn.amount.map(|a| a.to_string())
+                            , txn.currency
+                            ])?;
+        Ok(())
+    }
+
+    fn accounts(&self) -> Result<Vec<Account>, TxError> {
+        let accounts = self.conn
+            .prepare_cached("SELECT client, NULL AS currency, available, held, total, locked FROM accounts
+                             UNION ALL
+                             SELECT client, currency, available, held, total, locked FROM currency_accounts
+                             ORDER BY client, currency")?
+            .query_map([], to_account)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(accounts)
+    }
+
+    fn owner(&self, tx_id: u32) -> Result<Option<u16>, TxError> {
+        let owner = self.conn
+            .prepare_cached("SELECT client FROM history WHERE tx = ?1 LIMIT 1")?
+            .query_row(params![tx_id], |row| row.get(0))
+            .optional()?;
+        Ok(owner)
+    }
+
+    fn flush(&mut self) -> Result<(), TxError> {
+        self.conn.execute_batch("COMMIT; BEGIN")?;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        let _ = self.conn.execute_batch("COMMIT");
+    }
+}
+
+fn to_account(row: &Row) -> rusqlite::Result<Account> {
+    Ok(Account {
+        client_id: row.get(0)?,
+        currency:  row.get(1)?,
+        available: decimal(row, 2)?,
+        held:      decimal(row, 3)?,
+        total:     decimal(row, 4)?,
+        locked:    row.get(5)?,
+    })
+}
+
+fn to_transaction(row: &Row) -> rusqlite::Result<Transaction> {
+    let kind: String = row.get(2)?;
+    let kind = kind_from_name(&kind)
+        .ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, format!("unknown type `{}`", kind).into()))?;
+    let amount = match row.get::<_, Option<String>>(3)? {
+        Some(_) => Some(decimal(row, 3)?),
+        None => None,
+    };
+    Ok(Transaction { kind, client_id: row.get(0)?, tx_id: row.get(1)?, amount, to_client: None, currency: row.get(4)? })
+}
+
+fn decimal(row: &Row, idx: usize) -> rusqlite::Result<Decimal> {
+    let text: String = row.get(idx)?;
+    Decimal::from_str(&text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn kind_name(kind: TransactionKind) -> &'static str {
+    match kind {
+        TransactionKind::Deposit    => "deposit",
+        TransactionKind::Withdrawal => "withdrawal",
+        TransactionKind::Dispute    => "dispute",
+        TransactionKind::Resolve    => "resolve",
+        TransactionKind::Chargeback => "chargeback",
+        TransactionKind::Unlock     => "unlock",
+        TransactionKind::Adjustment => "adjustment",
+        TransactionKind::Transfer   => "transfer",
+    }
+}
+
+fn kind_from_name(name: &str) -> Option<TransactionKind> {
+    match name {
+        "deposit"    => Some(TransactionKind::Deposit),
+        "withdrawal" => Some(TransactionKind::Withdrawal),
+        "dispute"    => Some(TransactionKind::Dispute),
+        "resolve"    => Some(TransactionKind::Resolve),
+        "chargeback" => Some(TransactionKind::Chargeback),
+        "unlock"     => Some(TransactionKind::Unlock),
+        "adjustment" => Some(TransactionKind::Adjustment),
+        "transfer"   => Some(TransactionKind::Transfer),
+        _            => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::storage::apply;
+    use crate::tx::Options;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sqlite_storage_survives_reopen() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("txreader.db");
+        {
+            let mut storage = SqliteStorage::open(&path)?;
+            apply(&mut storage, Transaction::deposit(1, 1, dec!(1.5))?, &Options::default())?;
+            apply(&mut storage, Transaction::deposit(2, 2, dec!(3))?, &Options::default())?;
+        }
+
+        /*
+         * When
+         */
+        let mut storage = SqliteStorage::open(&path)?;
+        let dispute = apply(&mut storage, Transaction::dispute(1, 1), &Options::default())?;
+        let chargeback = apply(&mut storage, Transaction::chargeback(1, 1), &Options::default())?;
+        let mismatch = apply(&mut storage, Transaction::dispute(2, 1), &Options::default())?;
+
+        /*
+         * Then
+         */
+        assert!(dispute.is_none());
+        assert!(chargeback.is_none());
+        assert!(matches!(mismatch, Some(TxError::ClientMismatch { tx: 1, client: 2, owner: 1 })));
+        assert_eq!(storage.accounts()?, vec![ Account { client_id: 1
+                                                      , currency:  None
+                                                      , available: dec!(0)
+                                                      , held:      dec!(0)
+                                                      , total:     dec!(0)
+                                                      , locked:    true
+                                                      }
+                                            , Account { client_id: 2
+                                                      , currency:  None
+                                                      , available: dec!(3)
+                                                      , held:      dec!(0)
+                                                      , total:     dec!(3)
+                                                      , locked:    false
+                                                      }
+                                            ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_storage_keeps_currencies() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let dir = tempfile::tempdir()?;
+        let mut storage = SqliteStorage::open(&dir.path().join("txreader.db"))?;
+        apply(&mut storage, Transaction::deposit(1, 1, dec!(2))?.with_currency("EUR"), &Options::default())?;
+        apply(&mut storage, Transaction::deposit(1, 2, dec!(3))?, &Options::default())?;
+
+        /*
+         * When
+         */
+        let mismatch = apply(&mut storage, Transaction::dispute(1, 1), &Options::default())?;
+        let dispute = apply(&mut storage, Transaction::dispute(1, 1).with_currency("EUR"), &Options::default())?;
+
+        /*
+         * Then
+         */
+        assert!(matches!(mismatch, Some(TxError::CurrencyMismatch(1))));
+        assert!(dispute.is_none());
+        assert_eq!(storage.accounts()?.iter().map(|a| (a.currency(), a.available(), a.held())).collect::<Vec<_>>(),
+                   vec![ (None,        dec!(3), dec!(0))
+                       , (Some("EUR"), dec!(0), dec!(2))
+                       ]);
+        Ok(())
+    }
+}
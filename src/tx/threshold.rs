@@ -0,0 +1,123 @@
+use crate::tx::TxError;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of rows read before a `Limit::Percent` is checked while a run
+/// is still reading, so that a few errors among the first rows do not
+/// abort it.
+pub const MIN_ROWS: u64 = 1000;
+
+/// How many of the rows of a run may be malformed or rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// At most this number of rows, e.g. `10`.
+    Count(u64),
+    /// At most this percentage of the rows, e.g. `5%`.
+    Percent(f64),
+}
+
+impl std::str::FromStr for Limit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Limit, String> {
+        let invalid = || format!("invalid limit `{}`, expected a number of rows like `10` or a percentage like `5%`", s);
+        match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(Limit::Percent(percent)),
+                _ => Err(invalid()),
+            },
+            None => s.trim().parse().map(Limit::Count).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Count(n) => write!(f, "{}", n),
+            Limit::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Counts the rows of a run and those that were malformed or rejected,
+/// and aborts the run once they exceed a `Limit`. It is set in
+/// `Options::max_errors`, and counts every run of the options it is set in.
+#[derive(Debug)]
+pub struct Threshold {
+    limit:  Limit,
+    rows:   AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Threshold {
+    pub fn new(limit: Limit) -> Threshold {
+        Threshold { limit, rows: AtomicU64::new(0), errors: AtomicU64::new(0) }
+    }
+
+    /// Counts a row that was read.
+    pub(crate) fn count_row(&self) {
+        self.rows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a row that was read but could not be parsed.
+    pub(crate) fn count_malformed(&self) {
+        self.count_row();
+        self.count_error();
+    }
+
+    /// Counts a row that was read before, and rejected.
+    pub(crate) fn count_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns `TxError::TooManyErrors` if the errors exceed the limit.
+    /// A percentage is only checked once `MIN_ROWS` were read, unless
+    /// `done` is set at the end of the run.
+    pub(crate) fn check(&self, done: bool) -> Result<(), TxError> {
+        let rows = self.rows.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        let exceeded = match self.limit {
+            Limit::Count(n) => errors > n,
+            Limit::Percent(percent) => (done || rows >= MIN_ROWS) && errors as f64 > rows as f64 * percent / 100.0,
+        };
+        match exceeded {
+            true => Err(TxError::TooManyErrors { errors, rows, limit: self.limit }),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_threshold() {
+        /*
+         * Given
+         */
+        let count = Threshold::new("2".parse().unwrap());
+        let percent = Threshold::new("10%".parse().unwrap());
+
+        /*
+         * When
+         */
+        (0..3).for_each(|_| count.count_malformed());
+        (0..MIN_ROWS - 1).for_each(|_| percent.count_row());
+        (0..200).for_each(|_| percent.count_error());
+        let early = percent.check(false);
+        let done = percent.check(true);
+        percent.count_row();
+
+        /*
+         * Then
+         */
+        assert!(matches!(count.check(false), Err(TxError::TooManyErrors { errors: 3, rows: 3, limit: Limit::Count(2) })));
+        assert!(early.is_ok());
+        assert!(done.is_err());
+        assert_eq!(percent.check(false).unwrap_err().to_string(),
+                   "200 of 1000 rows were malformed or rejected, more than the limit of 10%");
+        assert!("110%".parse::<Limit>().is_err() && "x".parse::<Limit>().is_err());
+    }
+}
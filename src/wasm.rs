@@ -0,0 +1,48 @@
+use crate::tx::{self, storage, Engine, Options};
+use wasm_bindgen::prelude::*;
+
+/// Applies the transactions of a csv file, given as a string, and
+/// returns the accounts as csv, like the output of the command line.
+/// The transactions are applied one after another on the calling
+/// thread, without any file or thread, so it also runs in a browser,
+/// e.g. to check a partner file before it is uploaded. A record that
+/// can not be parsed is skipped.
+#[wasm_bindgen]
+pub fn process_csv(input: &str) -> Result<String, String> {
+    let options = Options::default();
+    let mut engine = Engine::new();
+    storage::process_reader(input.as_bytes(), &options, &mut engine).map_err(|e| e.to_string())?;
+    let mut output = vec![];
+    tx::write_csv(&mut output, &engine.into_accounts(), false);
+    String::from_utf8(output).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_process_csv() {
+        /*
+         * Given
+         */
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,2.5\n\
+                     deposit,2,2,4\n\
+                     withdrawal,2,3,1\n\
+                     deposit,3,4,bad\n\
+                     dispute,1,1,\n";
+
+        /*
+         * When
+         */
+        let output = process_csv(input);
+
+        /*
+         * Then
+         */
+        assert_eq!(output, Ok(String::from("client,available,held,total,locked\n\
+                                             1,0.0,2.5,2.5,false\n\
+                                             2,3,0.0,3,false\n")));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::tx::{self, Account, TxError};
+use rust_decimal::prelude::ToPrimitive;
+use rust_xlsxwriter::{Workbook, Worksheet, XlsxError};
+use std::io;
+
+/// A cell of a worksheet.
+enum Cell {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+/// Writes the accounts to the `writer` as an Excel workbook with two
+/// sheets: `Accounts`, with the same columns as the csv output, and
+/// `Summary`, with the number of accounts and of locked accounts and
+/// their total funds, for every currency if any account has one. The
+/// funds are stored as numbers, which Excel keeps to 15 significant
+/// digits.
+pub async fn print_accounts_xlsx(writer: &mut impl io::Write, accounts: &[Account]) -> Result<(), TxError> {
+    let currency = accounts.iter().any(|a| a.currency().is_some());
+    let header = |columns: &[&str]| columns.iter().map(|column| Cell::Text(column.to_string())).collect::<Vec<_>>();
+    let funds = |funds: rust_decimal::Decimal| Cell::Number(funds.to_f64().unwrap_or_default());
+
+    let mut rows = vec![header(&["client", "currency", "available", "held", "total", "locked"])];
+    rows.extend(accounts.iter().map(|a| vec![ Cell::Number(a.client_id().into())
+                                           , Cell::Text(a.currency().unwrap_or_default().to_string())
+                                           , funds(a.available())
+                                           , funds(a.held())
+                                           , funds(a.total())
+                                           , Cell::Bool(a.is_locked())
+                                           ]));
+    let mut summary = vec![header(&["currency", "accounts", "locked", "available", "held", "total"])];
+    summary.extend(tx::totals(accounts).into_iter().map(|(currency, sum)| vec![ Cell::Text(currency.unwrap_or_default())
+                                                                              , Cell::Number(sum.accounts as f64)
+                                                                              , Cell::Number(sum.locked as f64)
+                                                                              , funds(sum.available)
+                                                                              , funds(sum.held)
+                                                                              , funds(sum.total)
+                                                                              ]));
+    if !currency {
+        rows.iter_mut().for_each(|row| { row.remove(1); });
+        summary.iter_mut().for_each(|row| { row.remove(0); });
+    }
+
+    let mut workbook = Workbook::new();
+    sheet(workbook.add_worksheet().set_name("Accounts")?, &rows)?;
+    sheet(workbook.add_worksheet().set_name("Summary")?, &summary)?;
+    writer.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+/// Writes the rows to the worksheet, from its first cell.
+fn sheet(worksheet: &mut Worksheet, rows: &[Vec<Cell>]) -> Result<(), XlsxError> {
+    for (r, row) in (0..).zip(rows) {
+        for (column, cell) in (0..).zip(row) {
+            match cell {
+                Cell::Text(text) => worksheet.write_string(r, column, text)?,
+                Cell::Number(number) => worksheet.write_number(r, column, *number)?,
+                Cell::Bool(b) => worksheet.write_boolean(r, column, *b)?,
+            };
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use calamine::{Data, Reader, Xlsx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_print_accounts_xlsx() -> Result<(), Box<dyn std::error::Error>> {
+        /*
+         * Given
+         */
+        let mut engine = crate::Engine::new();
+        engine.apply(crate::Transaction::deposit(1, 1, dec!(1.5))?)?;
+        engine.apply(crate::Transaction::deposit(2, 2, dec!(2))?)?;
+        engine.apply(crate::Transaction::dispute(2, 2))?;
+        let accounts = engine.into_accounts();
+
+        /*
+         * When
+         */
+        let mut xlsx = vec![];
+        futures::executor::block_on(print_accounts_xlsx(&mut xlsx, &accounts))?;
+        let mut workbook = Xlsx::new(io::Cursor::new(xlsx))?;
+
+        /*
+         * Then
+         */
+        let text = |text: &str| Data::String(text.to_string());
+        assert_eq!(workbook.sheet_names(), vec!["Accounts", "Summary"]);
+        assert_eq!(workbook.worksheet_range("Accounts")?.rows().map(<[Data]>::to_vec).collect::<Vec<_>>(),
+                   vec![ vec![text("client"), text("available"), text("held"), text("total"), text("locked")]
+                       , vec![Data::Float(1.0), Data::Float(1.5), Data::Float(0.0), Data::Float(1.5), Data::Bool(false)]
+                       , vec![Data::Float(2.0), Data::Float(0.0), Data::Float(2.0), Data::Float(2.0), Data::Bool(false)]
+                       ]);
+        assert_eq!(workbook.worksheet_range("Summary")?.rows().map(<[Data]>::to_vec).collect::<Vec<_>>(),
+                   vec![ vec![text("accounts"), text("locked"), text("available"), text("held"), text("total")]
+                       , vec![Data::Float(2.0), Data::Float(0.0), Data::Float(1.5), Data::Float(2.0), Data::Float(3.5)]
+                       ]);
+        Ok(())
+    }
+}
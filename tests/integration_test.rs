@@ -32,6 +32,74 @@ fn test_read_multiple_files_non_blocking() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[test]
+fn test_exit_codes() -> Result<(), anyhow::Error> {
+    /*
+     * Given
+     */
+    let mut rejected = tempfile::NamedTempFile::new()?;
+    writeln!(rejected, "type,client,tx,amount\ndeposit,1,1,5\nwithdrawal,1,2,9")?;
+    let mut malformed = tempfile::NamedTempFile::new()?;
+    writeln!(malformed, "type,client,tx,amount\ndeposit,x,1,5")?;
+    let mut old = tempfile::NamedTempFile::new()?;
+    writeln!(old, "client,available,held,total,locked\n1,5,0,5,false")?;
+    let mut new = tempfile::NamedTempFile::new()?;
+    writeln!(new, "client,available,held,total,locked\n1,4,0,4,false")?;
+    let status = |args: &[&std::ffi::OsStr]| -> Result<Option<i32>, anyhow::Error> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_txreader")).arg("-q").args(args).output()?;
+        Ok(output.status.code())
+    };
+
+    /*
+     * When
+     */
+    let success = status(&[rejected.path().as_ref()])?;
+    let strict = status(&["--strict".as_ref(), rejected.path().as_ref()])?;
+    let parse = status(&["--strict".as_ref(), malformed.path().as_ref()])?;
+    let missing = status(&["missing.csv".as_ref()])?;
+    let same = status(&["diff".as_ref(), old.path().as_ref(), old.path().as_ref()])?;
+    let differ = status(&["diff".as_ref(), old.path().as_ref(), new.path().as_ref()])?;
+
+    /*
+     * Then
+     */
+    assert_eq!((success, strict, parse, missing), (Some(0), Some(2), Some(1), Some(1)));
+    assert_eq!((same, differ), (Some(0), Some(3)));
+    Ok(())
+}
+
+#[test]
+fn test_golden() -> Result<(), anyhow::Error> {
+    /*
+     * Given
+     */
+    let dir = tempfile::tempdir()?;
+    let changed = dir.path().join("changed.csv");
+    std::fs::write(&changed, "client,available,held,total,locked\n1,1.4996,0.0000,1.4996,false\n2,3.0000,0.0000,3.0000,false\n")?;
+    let golden = |args: &[&std::ffi::OsStr]| -> Result<(Option<i32>, String), anyhow::Error> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_txreader")).arg("-q").arg("golden").args(args).output()?;
+        Ok((output.status.code(), String::from_utf8(output.stdout)?))
+    };
+
+    /*
+     * When
+     */
+    let written = golden(&["transactions_simple.csv".as_ref(), dir.path().join("simple.csv").as_ref()])?;
+    let corpus = ["transactions_simple.csv", "transactions.csv"].iter()
+        .map(|input| golden(&["--check".as_ref(), input.as_ref(), format!("tests/golden/{}", input).as_ref()]))
+        .collect::<Result<Vec<_>, _>>()?;
+    let differ = golden(&["--check".as_ref(), "transactions_simple.csv".as_ref(), changed.as_ref()])?;
+
+    /*
+     * Then
+     */
+    assert_eq!(written.0, Some(0));
+    assert_eq!(std::fs::read_to_string(dir.path().join("simple.csv"))?, std::fs::read_to_string("tests/golden/transactions_simple.csv")?);
+    assert!(corpus.iter().all(|(status, _)| *status == Some(0)), "{:?}", corpus);
+    assert_eq!(differ, (Some(3), String::from("client,status,available,held,total,locked\n2,changed,-1,0,-1,\n")));
+    Ok(())
+}
+
 async fn read_multiple_files_sequentially_1() -> Result<(), anyhow::Error> {
     for _ in 0..50 {
         tx::read(&std::path::PathBuf::from("transactions.csv")).await?;